@@ -3,9 +3,31 @@
 //! Wrapper around the ``MongoDB`` database connection
 //! and collections.
 
-use mongodb::{bson::doc, options::IndexOptions, IndexModel};
+use mongodb::{
+    bson::doc,
+    error::{ErrorKind, WriteFailure},
+    options::{Collation, CollationStrength, IndexOptions},
+    IndexModel,
+};
 
-use crate::structs::entities::{clan::Clan, player::ExtendedJid};
+use crate::{
+    structs::entities::{
+        audit::AuditEntry, clan::Clan, failed_write::FailedWrite, player::ExtendedJid,
+    },
+    utils,
+};
+
+/// Name of the unique index on the clans' IDs, as ``MongoDB`` names it by default.
+pub const CLAN_ID_INDEX: &str = "id_1";
+
+/// Name of the unique index on the clans' names.
+pub const CLAN_NAME_INDEX: &str = "name_unique";
+
+/// Name of the unique index on the clans' tags.
+pub const CLAN_TAG_INDEX: &str = "tag_unique";
+
+/// ``MongoDB`` error code for a duplicate key.
+const DUPLICATE_KEY_ERROR: i32 = 11000;
 
 /// Database utility struct.
 #[derive(Debug, Clone)]
@@ -24,19 +46,33 @@ pub struct Database {
     /// when creating clans outside of the game. Else the game will
     /// ignore the clan we've made.
     pub players: mongodb::Collection<ExtendedJid>,
+
+    /// Collection of clans that failed to be saved.
+    ///
+    /// These are kept so that the changes aren't lost,
+    /// and can be replayed once the database recovers.
+    pub failed_writes: mongodb::Collection<FailedWrite>,
+
+    /// Collection of sensitive changes made to clans.
+    pub audit_log: mongodb::Collection<AuditEntry>,
 }
 
 impl Database {
+    /// URI of the ``MongoDB`` server, from the ``MONGO_URI`` environment variable.
+    ///
+    /// Defaults to a local server.
+    pub fn uri() -> String {
+        std::env::var("MONGO_URI")
+            .unwrap_or_else(|_| String::from("mongodb://localhost:27017"))
+    }
+
     /// Initialize the database connection.
     /// 
     /// ## Panic
     /// This function will panic if the ``MONGO_URI`` environment variable
     /// is not set, or if the connection to the database fails.
     pub async fn init() -> Self {
-        let mongo_uri = std::env::var("MONGO_URI")
-            .unwrap_or_else(|_| String::from("mongodb://localhost:27017"));
-
-        let client = mongodb::Client::with_uri_str(&mongo_uri).await.unwrap();
+        let client = mongodb::Client::with_uri_str(Self::uri()).await.unwrap();
         let database = client.default_database()
             .unwrap_or_else(|| client.database("clans"));
 
@@ -53,12 +89,103 @@ impl Database {
 
         clans.create_index(index).await.unwrap();
 
+        // Let clans be synced by the last time they changed
+        let index = IndexModel::builder()
+            .keys(doc! { "updated_at": 1 })
+            .build();
+
+        if let Err(e) = clans.create_index(index).await {
+            log::error!("Failed to create the index on clan update dates: {e}");
+        }
+
+        // Short tags may be exempt from uniqueness, which an index can't express,
+        // so the server is left to check the tags on its own
+        let short_tags_exempt = utils::validation::tag_unique_min_length().is_some();
+        if short_tags_exempt && clans.drop_index(CLAN_TAG_INDEX).await.is_ok() {
+            log::info!("Dropped the unique index on clan tags, as short tags are exempt");
+        }
+
+        // Make sure clan names and tags are unique, ignoring case.
+        // This is what keeps two concurrent creations from both succeeding.
+        for (field, name) in [("name", CLAN_NAME_INDEX), ("tag", CLAN_TAG_INDEX)] {
+            if field == "tag" && short_tags_exempt {
+                continue;
+            }
+
+            let collation = Collation::builder()
+                .locale("en")
+                .strength(CollationStrength::Secondary)
+                .build();
+
+            let index = IndexModel::builder()
+                .keys(doc! { field: 1 })
+                .options(IndexOptions::builder()
+                    .name(name.to_string())
+                    .unique(true)
+                    .collation(collation)
+                    // Clans without a value don't conflict with each other
+                    .partial_filter_expression(doc! { field: { "$gt": "" } })
+                    .build())
+                .build();
+
+            // Existing duplicates would make this fail, but they shouldn't stop the server
+            if let Err(e) = clans.create_index(index).await {
+                log::error!("Failed to create the unique index on clan {field}s: {e}");
+            }
+        }
+
         let players = database.collection("players");
+        let failed_writes = database.collection("failed_writes");
+        let audit_log = database.collection("audit_log");
 
         Self {
             database,
             clans,
             players,
+            failed_writes,
+            audit_log,
         }
     }
+}
+
+/// Returns the name of the unique index that an error violated, if any.
+pub fn duplicate_key_index(error: &mongodb::error::Error) -> Option<&str> {
+    let ErrorKind::Write(WriteFailure::WriteError(write_error)) = error.kind.as_ref() else {
+        return None;
+    };
+
+    if write_error.code != DUPLICATE_KEY_ERROR {
+        return None;
+    }
+
+    violated_index(&write_error.message)
+}
+
+/// Returns the name of the index named in a duplicate key error's message.
+///
+/// The message looks like: ``E11000 duplicate key error collection: clans.clans index: name_unique dup key: ...``
+fn violated_index(message: &str) -> Option<&str> {
+    message
+        .split_whitespace()
+        .skip_while(|word| *word != "index:")
+        .nth(1)
+}
+
+#[cfg(test)]
+mod tests {
+    //! Tests for reading the database's errors.
+
+    use super::{violated_index, CLAN_ID_INDEX, CLAN_NAME_INDEX, CLAN_TAG_INDEX};
+
+    /// The violated index is read from the error message, including the default one on IDs.
+    #[test]
+    fn violated_indexes() {
+        for index in [CLAN_ID_INDEX, CLAN_NAME_INDEX, CLAN_TAG_INDEX] {
+            let message = format!("E11000 duplicate key error collection: clans.clans index: {index} dup key: {{ x: 1 }}");
+            assert_eq!(violated_index(&message), Some(index));
+        }
+
+        assert_eq!(violated_index("E11000 duplicate key error"), None);
+        assert_eq!(violated_index("index:"), None);
+    }
 }
\ No newline at end of file
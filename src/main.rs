@@ -10,6 +10,7 @@
 mod database;
 mod routes;
 mod structs;
+mod tasks;
 mod utils;
 
 use actix_web::{middleware::Logger, web::Data, App, HttpServer};
@@ -36,8 +37,25 @@ async fn main() -> std::io::Result<()> {
         .parse::<u16>()
         .expect("PORT must be a number");
 
+    // Refuse to start with a broken configuration
+    if let Err(problems) = utils::startup::validate().await {
+        for problem in &problems {
+            log::error!("{problem}");
+        }
+
+        log::error!("The server is misconfigured, exiting");
+        std::process::exit(1);
+    }
+
     let database = Database::init().await;
 
+    tasks::spawn_announcement_sweeper(database.clone());
+    tasks::spawn_disband_sweeper(database.clone());
+
+    let stats_cache = Data::new(routes::stats::StatsCache::default());
+    let rate_limiter = Data::new(utils::rate_limit::RateLimiter::default());
+    let creation_limiter = Data::new(utils::rate_limit::CreationLimiter::from_env());
+
     log::info!("Starting server at {host}:{port}");
 
     HttpServer::new(move || {
@@ -45,18 +63,24 @@ async fn main() -> std::io::Result<()> {
             // Clans
             .service(routes::clans::get_clan_info)
             .service(routes::clans::get_clan_list)
+            .service(routes::clans::get_last_active_clan)
+            .service(routes::clans::leaderboard)
             .service(routes::clans::clan_search)
             .service(routes::clans::create_clan)
             .service(routes::clans::disband_clan)
+            .service(routes::clans::cancel_disband)
             .service(routes::clans::update_clan_info)
             // Blacklist
             .service(routes::blacklist::get_blacklist)
+            .service(routes::blacklist::is_blacklisted)
+            .service(routes::blacklist::get_blacklist_count)
             .service(routes::blacklist::record_blacklist_entry)
             .service(routes::blacklist::delete_blacklist_entry)
             // Members
             .service(routes::members::get_member_list)
             .service(routes::members::get_member_info)
             .service(routes::members::change_member_role)
+            .service(routes::members::transfer_leadership)
             .service(routes::members::update_member_info)
             .service(routes::members::kick_member)
             .service(routes::members::join_clan)
@@ -65,8 +89,13 @@ async fn main() -> std::io::Result<()> {
             .service(routes::announcements::retrieve_announcements)
             .service(routes::announcements::post_announcement)
             .service(routes::announcements::delete_announcement)
+            .service(routes::announcements::delete_announcements_before)
+            .service(routes::announcements::pin_announcement)
+            .service(routes::announcements::mark_announcement_read)
+            .service(routes::announcements::get_announcement_readers)
             // Invites
             .service(routes::invites::send_invitation)
+            .service(routes::invites::resend_invitation)
             .service(routes::invites::cancel_invitation)
             .service(routes::invites::accept_invitation)
             .service(routes::invites::decline_invitation)
@@ -75,6 +104,10 @@ async fn main() -> std::io::Result<()> {
             .service(routes::invites::cancel_request_membership)
             .service(routes::invites::accept_membership_request)
             .service(routes::invites::decline_membership_request)
+            // Server
+            .service(routes::capabilities::capabilities)
+            .service(routes::capabilities::roles)
+            .service(routes::stats::get_stats)
             // Fallback handler
             .default_service(actix_web::web::to(|| async {
                 Response::<()>::error(ErrorCode::NoSuchClanService)
@@ -90,13 +123,41 @@ async fn main() -> std::io::Result<()> {
                         cfg.service(
                             actix_web::web::scope("")
                                 .wrap(actix_web::middleware::from_fn(utils::auth::admin))
-                                .service(routes::admin::create_clan),
+                                .service(routes::admin::create_clan)
+                                .service(routes::admin::validate_name)
+                                .service(routes::admin::transfer_platform)
+                                .service(routes::admin::migrate_platform)
+                                .service(routes::admin::get_failed_writes)
+                                .service(routes::admin::replay_failed_write)
+                                .service(routes::admin::approve_clan)
+                                .service(routes::admin::reassign_id)
+                                .service(routes::admin::set_cross_platform)
+                                .service(routes::admin::set_role_names)
+                                .service(routes::admin::get_members)
+                                .service(routes::admin::add_member)
+                                .service(routes::admin::import_blacklist)
+                                .service(routes::admin::merge_clan)
+                                .service(routes::admin::diagnostics)
+                                .service(routes::admin::list_clans)
+                                .service(routes::admin::get_player_clans),
                         );
                     }
                 },
             )
-            .wrap(Logger::default())
+            .wrap(actix_web::middleware::from_fn(utils::flags::disabled_routes))
+            .wrap(actix_web::middleware::from_fn(utils::rate_limit::limit))
+            .wrap(
+                // Same as the default format, but using the client's real IP
+                Logger::new(r#"%{ip}xi "%r" %s %b "%{Referer}i" "%{User-Agent}i" %T"#)
+                    .custom_request_replace("ip", |req| {
+                        utils::ip::real_ip(req.peer_addr(), req.headers())
+                            .map_or_else(|| String::from("-"), |ip| ip.to_string())
+                    }),
+            )
             .app_data(Data::new(database.clone()))
+            .app_data(stats_cache.clone())
+            .app_data(rate_limiter.clone())
+            .app_data(creation_limiter.clone())
     })
     .bind((host, port))?
     .run()
@@ -6,71 +6,117 @@
 //! revival project's Discord bot.
 
 use actix_web::{
-    put,
-    web::{Data, Json},
+    get, post, put,
+    web::{Data, Json, Path, Query},
+    Either,
 };
-use mongodb::bson::doc;
+use chrono::Utc;
+use futures_util::StreamExt;
+use mongodb::bson::{doc, oid::ObjectId};
 
 use crate::{
     database::Database,
     structs::{
         entities::{
             clan::{
-                Clan, Platform, MAX_CLAN_MEMBERSHIP, MAX_CLAN_NAME_LENGTH, MAX_CLAN_OWNERSHIP,
+                Clan, Id, Platform, MAX_CLAN_MEMBERSHIP, MAX_CLAN_NAME_LENGTH, MAX_CLAN_OWNERSHIP,
                 MAX_CLAN_TAG_LENGTH,
             },
-            player::{Jid, Status},
+            audit::{Action, AuditEntry},
+            player::{Jid, Player, Role, Status},
+        },
+        requests::admin::{
+            AddMember, CreateClan, ImportBlacklist, ListClans, MergeClan, ReassignId, SetCrossPlatform, SetRoleNames,
+            TransferPlatform, ValidateName,
         },
-        requests::admin::CreateClan,
         responses::{
-            admin::Response,
+            admin::{
+                BlacklistImport, ClanMembers, ClanMerge, ClanSummary, Clans, DataResponse, Diagnostics, FailedWriteInfo, FailedWrites, NameValidation,
+                PlatformMigration, PlatformTransfer, PlayerClans, Response, Rule,
+            },
             error::{ErrorCode, SUCCESS},
         },
-        ticket::{DEFAULT_DOMAIN, DEFAULT_REGION},
     },
+    utils::{rate_limit::CreationLimiter, validation},
 };
 
+/// Check a clan's name and tag against every rule, without
+/// stopping at the first one that fails.
+async fn check_name(database: &Data<Database>, name: &str, tag: &str) -> NameValidation {
+    let mut validation = NameValidation::default();
+
+    for (field, value, max) in [
+        ("name", name, MAX_CLAN_NAME_LENGTH),
+        ("tag", tag, MAX_CLAN_TAG_LENGTH),
+    ] {
+        let value = match validation::length(value, max) {
+            Ok(value) => {
+                validation.push(field, Rule::Length, Ok(()));
+                value
+            }
+            Err(e) => {
+                validation.push(field, Rule::Length, Err(e));
+                value.to_string()
+            }
+        };
+
+        validation.push(field, Rule::Charset, validation::charset(&value));
+        validation.push(field, Rule::Profanity, validation::profanity(&value));
+
+        if field == "name" {
+            validation.push(field, Rule::Reserved, validation::reserved(&value));
+
+            let result = validation::duplicate_name(&value, database).await;
+            validation.push(field, Rule::Duplicate, result);
+        } else {
+            let result = validation::duplicate_tag(&value, database).await;
+            validation.push(field, Rule::Duplicate, result);
+        }
+    }
+
+    validation
+}
+
 /// Create a clan.
+///
+/// If the name or tag break any rule, every failure is reported
+/// at once, so that they can all be fixed before trying again.
+///
+/// The clan counts towards the user's clan creation limit,
+/// unless the request bypasses it. See: [`CreationLimiter`]
 #[put("/admin/clan/create")]
-pub async fn create_clan(database: Data<Database>, mut data: Json<CreateClan>) -> Response {
-    // Look-up the player in the database
-    let filter = match data.clan_platform {
-        Platform::Console => doc! {
-            "username": data.username.clone(),
-            "domain": {"$ne": DEFAULT_DOMAIN},
-            "region": {"$ne": DEFAULT_REGION},
-        },
-        Platform::Emulator => doc! {
-            "username": data.username.clone(),
-            "domain": DEFAULT_DOMAIN,
-            "region": DEFAULT_REGION,
-        },
-    };
+pub async fn create_clan(
+    database: Data<Database>,
+    limiter: Data<CreationLimiter>,
+    data: Json<CreateClan>,
+) -> Either<Response, NameValidation> {
+    let limited = !data.bypass_rate_limit;
 
-    // Limit the clan name and tag to their maximum lengths
-    data.clan_name = data.clan_name.chars().take(MAX_CLAN_NAME_LENGTH).collect();
-    data.clan_tag = data.clan_tag.chars().take(MAX_CLAN_TAG_LENGTH).collect();
+    // Look-up the player in the database
+    let filter = data.clan_platform.player_filter(&data.username);
 
-    // Make sure Unicode chars don't exceed the limits
-    if data.clan_name.len() > MAX_CLAN_NAME_LENGTH || data.clan_tag.len() > MAX_CLAN_TAG_LENGTH {
-        return Response::from(ErrorCode::PermissionDenied);
+    // Validate the clan name and tag, which are within their maximum lengths if they pass
+    let mut validation = check_name(&database, &data.clan_name, &data.clan_tag).await;
+    if let Some(code) = validation.first_error() {
+        validation.status_code = code;
+        return Either::Right(validation);
     }
 
     let Ok(author) = database.players.find_one(filter).await else {
-        return Response::from(ErrorCode::InternalServerError);
+        return Either::Left(Response::from(ErrorCode::InternalServerError));
     };
 
     // If the player was not found, return an error
-    if author.is_none() {
-        return Response::from(ErrorCode::InvalidNpId);
-    }
+    let Some(author) = author else {
+        return Either::Left(Response::from(ErrorCode::InvalidNpId));
+    };
 
-    let author: Jid = author.unwrap().into();
-    let clan = Clan::from((data.into_inner(), author.clone()));
+    let author: Jid = author.into();
+    let mut clan = Clan::from((data.into_inner(), author.clone()));
 
     // Check the clans the author is in
     let Ok(clans) = author.clans(database.clone()).await else {
-        return Response::from(ErrorCode::InternalServerError);
+        return Either::Left(Response::from(ErrorCode::InternalServerError));
     };
 
     let clans_owned_len = clans
@@ -84,18 +130,523 @@ pub async fn create_clan(database: Data<Database>, mut data: Json<CreateClan>) -
 
     // Check if the author is already in too many clans
     if clans_member_len >= MAX_CLAN_MEMBERSHIP {
-        return Response::from(ErrorCode::ClanJoinedLimitReached);
+        return Either::Left(Response::from(ErrorCode::ClanJoinedLimitReached));
     }
 
     // Check if the author already owns too many clans
     if clans_owned_len >= MAX_CLAN_OWNERSHIP {
-        return Response::from(ErrorCode::ClanLeaderLimitReached);
+        return Either::Left(Response::from(ErrorCode::ClanLeaderLimitReached));
+    }
+
+    // Check if the author has been creating clans too often
+    if limited && !limiter.allows(&author.to_string()) {
+        return Either::Left(Response::from(ErrorCode::ExceedsCreateClanFrequency));
+    }
+
+    // Save the clan to the database, without replacing another one
+    if let Err(e) = clan.create(&database).await {
+        return Either::Left(Response::from(e));
+    }
+
+    if limited {
+        limiter.record(&author.to_string());
+    }
+
+    Either::Left(Response::from(SUCCESS))
+}
+
+/// Check a clan's name and tag against every rule
+/// that would be enforced when creating the clan.
+#[post("/admin/clan/validate_name")]
+pub async fn validate_name(database: Data<Database>, data: Json<ValidateName>) -> NameValidation {
+    check_name(&database, &data.clan_name, &data.clan_tag).await
+}
+
+/// Move a clan to another platform.
+///
+/// Every member of the clan must already be on the target platform,
+/// according to the ``players`` collection, as the game crashes
+/// when fetching a leader from the other platform.
+#[put("/admin/clan/{id}/platform")]
+pub async fn transfer_platform(
+    database: Data<Database>,
+    id: Path<Id>,
+    data: Json<TransferPlatform>,
+) -> DataResponse<PlatformTransfer> {
+    let mut clan = match Clan::resolve(id.into_inner(), &database).await {
+        Ok(clan) => clan,
+        Err(e) => return DataResponse::from(e),
+    };
+
+    // Find the members that aren't known to be on the target platform
+    let mut offending = Vec::new();
+    for member in clan.members.iter().filter(|p| p.status == Status::Member) {
+        let filter = doc! {
+            "username": member.jid.username.clone(),
+            "domain": member.jid.domain.clone(),
+            "region": member.jid.region.clone(),
+        };
+
+        let known = match database.players.find_one(filter).await {
+            Ok(player) => player.is_some(),
+            Err(_) => return DataResponse::from(ErrorCode::InternalServerError),
+        };
+
+        if !known || Platform::from(&member.jid) != data.platform {
+            offending.push(member.jid.to_string());
+        }
+    }
+
+    if !offending.is_empty() {
+        return DataResponse {
+            status_code: ErrorCode::InvalidEnvironment as u8,
+            data: PlatformTransfer { offending },
+        };
+    }
+
+    clan.platform = data.into_inner().platform;
+
+    if let Err(e) = clan.save(&database).await {
+        return DataResponse::from(e);
+    }
+
+    DataResponse::from(SUCCESS)
+}
+
+/// Move a clan to another platform, along with its members.
+///
+/// Every member is matched with the account that has the same username
+/// on the target platform, according to the ``players`` collection,
+/// and their JID is updated to it. This lets communities move from
+/// real PS3s to the emulator without re-creating their clan.
+///
+/// If any member has no such account, nothing is changed and they're reported.
+/// Invitations and requests that can't be migrated are dropped instead,
+/// as they couldn't be accepted anyway.
+#[put("/admin/clan/{id}/migrate")]
+pub async fn migrate_platform(
+    database: Data<Database>,
+    id: Path<Id>,
+    data: Json<TransferPlatform>,
+) -> DataResponse<PlatformMigration> {
+    let mut clan = match Clan::resolve(id.into_inner(), &database).await {
+        Ok(clan) => clan,
+        Err(e) => return DataResponse::from(e),
+    };
+
+    let platform = data.into_inner().platform;
+
+    let mut accounts = Vec::with_capacity(clan.members.len());
+    for member in &clan.members {
+        let filter = platform.player_filter(&member.jid.username);
+
+        match database.players.find_one(filter).await {
+            Ok(account) => accounts.push(account.map(Jid::from)),
+            Err(_) => return DataResponse::from(ErrorCode::InternalServerError),
+        }
+    }
+
+    let mut migration = DataResponse::<PlatformMigration>::from(SUCCESS);
+    if let Err(offending) = clan.migrate(platform, accounts) {
+        migration.status_code = ErrorCode::InvalidEnvironment as u8;
+        migration.data.offending = offending.iter().map(Jid::to_string).collect();
+        return migration;
+    }
+
+    migration.data.migrated = clan
+        .members
+        .iter()
+        .filter(|p| p.status == Status::Member)
+        .map(|p| p.jid.to_string())
+        .collect();
+
+    if let Err(e) = clan.save(&database).await {
+        return DataResponse::from(e);
+    }
+
+    migration
+}
+
+/// List the clans that failed to be saved.
+#[get("/admin/failed_writes")]
+pub async fn get_failed_writes(database: Data<Database>) -> DataResponse<FailedWrites> {
+    let Ok(mut cursor) = database.failed_writes.find(doc! {}).await else {
+        return DataResponse::from(ErrorCode::InternalServerError);
+    };
+
+    let mut failed_writes = DataResponse::<FailedWrites>::from(SUCCESS);
+    while let Some(failed_write) = cursor.next().await {
+        match failed_write {
+            Ok(failed_write) => {
+                failed_writes.data.failed_writes.push(FailedWriteInfo::from(failed_write));
+            }
+            Err(e) => log::error!("Error while fetching failed write: {e}"),
+        }
+    }
+
+    failed_writes
+}
+
+/// Replay a clan that failed to be saved, removing
+/// the record once the clan has been saved.
+#[post("/admin/failed_writes/{id}/replay")]
+pub async fn replay_failed_write(database: Data<Database>, id: Path<String>) -> Response {
+    let Ok(id) = ObjectId::parse_str(id.as_str()) else {
+        return Response::from(ErrorCode::BadRequest);
+    };
+
+    let mut failed_write = match database.failed_writes.find_one(doc! { "_id": id }).await {
+        Ok(Some(failed_write)) => failed_write,
+        Ok(None) => return Response::from(ErrorCode::BadRequest),
+        Err(_) => return Response::from(ErrorCode::InternalServerError),
+    };
+
+    // Write the clan directly, so that another failure doesn't record it twice
+    if let Err(e) = failed_write.clan.write(&database).await {
+        log::error!("Failed to replay the write of clan `{}`: {e}", failed_write.clan.id());
+        return Response::from(ErrorCode::InternalServerError);
+    }
+
+    if database.failed_writes.delete_one(doc! { "_id": id }).await.is_err() {
+        return Response::from(ErrorCode::InternalServerError);
+    }
+
+    Response::from(SUCCESS)
+}
+
+/// Approve a clan that is pending approval, making it visible.
+#[put("/admin/clan/{id}/approve")]
+pub async fn approve_clan(database: Data<Database>, id: Path<Id>) -> Response {
+    let mut clan = match Clan::resolve(id.into_inner(), &database).await {
+        Ok(clan) => clan,
+        Err(e) => return Response::from(e),
+    };
+
+    clan.pending_approval = false;
+
+    if let Err(e) = clan.save(&database).await {
+        return Response::from(e);
+    }
+
+    Response::from(SUCCESS)
+}
+
+/// Move a clan to another ID, e.g. if it was created with a problematic one.
+///
+/// The new ID must be in the range the game can handle, and not be taken
+/// by another clan. Everything else about the clan is kept as it is.
+#[put("/admin/clan/{id}/id")]
+pub async fn reassign_id(
+    database: Data<Database>,
+    id: Path<Id>,
+    data: Json<ReassignId>,
+) -> Response {
+    let mut clan = match Clan::resolve(id.into_inner(), &database).await {
+        Ok(clan) => clan,
+        Err(e) => return Response::from(e),
+    };
+
+    let old_id = clan.id();
+    if let Err(e) = clan.reassign_id(data.id, &database).await {
+        return Response::from(e);
+    }
+
+    AuditEntry::new(clan.id(), None, Action::IdReassigned { old_id }).record(&database).await;
+
+    Response::from(SUCCESS)
+}
+
+/// Let players of every platform join a clan, or stop them from doing so.
+///
+/// This is meant for communities that want a single clan across platforms.
+/// Members that already joined from another platform are kept when disabling it.
+#[put("/admin/clan/{id}/cross_platform")]
+pub async fn set_cross_platform(
+    database: Data<Database>,
+    id: Path<Id>,
+    data: Json<SetCrossPlatform>,
+) -> Response {
+    let mut clan = match Clan::resolve(id.into_inner(), &database).await {
+        Ok(clan) => clan,
+        Err(e) => return Response::from(e),
+    };
+
+    clan.cross_platform = data.enabled;
+
+    if let Err(e) = clan.save(&database).await {
+        return Response::from(e);
     }
 
-    // Save the clan to the database.
+    Response::from(SUCCESS)
+}
+
+/// Set the custom names a clan displays its roles with.
+///
+/// The game still receives the numeric roles, so this only changes labels.
+#[put("/admin/clan/{id}/role_names")]
+pub async fn set_role_names(
+    database: Data<Database>,
+    id: Path<Id>,
+    data: Json<SetRoleNames>,
+) -> Response {
+    let mut clan = match Clan::resolve(id.into_inner(), &database).await {
+        Ok(clan) => clan,
+        Err(e) => return Response::from(e),
+    };
+
+    // Blank names would hide the role entirely, so they're left at the default
+    clan.role_names = data.into_inner().names
+        .into_iter()
+        .map(|(role, name)| (role, name.trim().to_string()))
+        .filter(|(_, name)| !name.is_empty())
+        .collect();
+
     if let Err(e) = clan.save(&database).await {
         return Response::from(e);
     }
 
     Response::from(SUCCESS)
 }
+
+/// List every member of a clan, as they're stored.
+///
+/// Unlike the game's member list, this includes invitations, requests and
+/// members with an unknown role or status, which the game hides.
+#[get("/admin/clan/{id}/members")]
+pub async fn get_members(database: Data<Database>, id: Path<Id>) -> DataResponse<ClanMembers> {
+    let clan = match Clan::resolve(id.into_inner(), &database).await {
+        Ok(clan) => clan,
+        Err(e) => return DataResponse::from(e),
+    };
+
+    let mut members = DataResponse::<ClanMembers>::from(SUCCESS);
+    members.data.members = clan.members.into_iter().map(Into::into).collect();
+
+    members
+}
+
+/// Add a player to a clan, bypassing invitations.
+///
+/// The player is looked up in the ``players`` collection,
+/// on the platform the clan was created for.
+#[put("/admin/clan/{id}/members")]
+pub async fn add_member(
+    database: Data<Database>,
+    id: Path<Id>,
+    data: Json<AddMember>,
+) -> Response {
+    let mut clan = match Clan::resolve(id.into_inner(), &database).await {
+        Ok(clan) => clan,
+        Err(e) => return Response::from(e),
+    };
+
+    // Look-up the player in the database
+    let filter = clan.platform.player_filter(&data.username);
+    let player: Jid = match database.players.find_one(filter).await {
+        Ok(Some(player)) => player.into(),
+        Ok(None) => return Response::from(ErrorCode::InvalidNpId),
+        Err(_) => return Response::from(ErrorCode::InternalServerError),
+    };
+
+    // Check if the player is already in the clan
+    if clan.members.iter().any(|p| p.jid == player) {
+        return Response::from(ErrorCode::MemberStatusInvalid);
+    }
+
+    // Check if the player has been blacklisted
+    if clan.is_blacklisted(&player) {
+        return Response::from(ErrorCode::Blacklisted);
+    }
+
+    // Check if the player can be given the role
+    let role = Role::from(data.role);
+    if let Err(e) = clan.validate_role(&player, role) {
+        return Response::from(e);
+    }
+
+    // Check if the player is in too many clans
+    let Ok(clans) = player.clans(database.clone()).await else {
+        return Response::from(ErrorCode::InternalServerError);
+    };
+
+    let clans_member = clans.iter().filter(|c| c.status_of(&player) == Some(&Status::Member));
+    if clans_member.count() >= MAX_CLAN_MEMBERSHIP {
+        return Response::from(ErrorCode::ClanJoinedLimitReached);
+    }
+
+    // Stamp the clan if the player was made its leader
+    if role == Role::Leader {
+        clan.leadership_changed_at = Some(Utc::now());
+    }
+
+    clan.members.push(Player {
+        jid: player.clone(),
+        role,
+        status: Status::Member,
+        allow_msg: clan.default_allow_msg,
+        ..Default::default()
+    });
+
+    if let Err(e) = clan.save(&database).await {
+        return Response::from(e);
+    }
+
+    // Keep track of who took over the clan
+    if role == Role::Leader {
+        let action = Action::LeadershipChanged { old_leader: None, new_leader: player };
+        AuditEntry::new(clan.id(), None, action).record(&database).await;
+    }
+
+    Response::from(SUCCESS)
+}
+
+/// Add players to a clan's blacklist, in bulk.
+///
+/// Every entry is checked on its own, so that invalid or
+/// duplicate entries don't prevent the others from being imported.
+#[put("/admin/clan/{id}/blacklist")]
+pub async fn import_blacklist(
+    database: Data<Database>,
+    id: Path<Id>,
+    data: Json<ImportBlacklist>,
+) -> DataResponse<BlacklistImport> {
+    let mut clan = match Clan::resolve(id.into_inner(), &database).await {
+        Ok(clan) => clan,
+        Err(e) => return DataResponse::from(e),
+    };
+
+    let mut import = DataResponse::<BlacklistImport>::from(SUCCESS);
+    for value in data.into_inner().jids {
+        let Ok(jid) = Jid::try_from(value.clone()) else {
+            import.data.push(value, Err(ErrorCode::InvalidNpId));
+            continue;
+        };
+
+        let result = clan.import_blacklist_entry(jid);
+        import.data.push(value, result);
+    }
+
+    if let Err(e) = clan.save(&database).await {
+        return DataResponse::from(e);
+    }
+
+    import
+}
+
+/// Merge another clan into a clan, then delete the other clan.
+///
+/// The clan keeps its leader: the other clan's leader joins as a sub-leader,
+/// and everyone else keeps their role. Blacklists and announcements are combined.
+///
+/// Players that are already in the clan, blacklisted from it or on another
+/// platform are left out and reported. If the members wouldn't fit within the
+/// member limit, nothing is changed and the ones that didn't fit are reported.
+#[post("/admin/clan/{id}/merge")]
+pub async fn merge_clan(
+    database: Data<Database>,
+    id: Path<Id>,
+    data: Json<MergeClan>,
+) -> DataResponse<ClanMerge> {
+    let id = id.into_inner();
+    if id == data.from {
+        return DataResponse::from(ErrorCode::BadRequest);
+    }
+
+    let mut clan = match Clan::resolve(id, &database).await {
+        Ok(clan) => clan,
+        Err(e) => return DataResponse::from(e),
+    };
+
+    let mut other = match Clan::resolve(data.from, &database).await {
+        Ok(other) => other,
+        Err(e) => return DataResponse::from(e),
+    };
+
+    let mut merge = DataResponse::<ClanMerge>::from(SUCCESS);
+    if let Err(e) = merge.data.perform(&mut clan, &mut other) {
+        merge.status_code = e as u8;
+        return merge;
+    }
+
+    if let Err(e) = clan.save(&database).await {
+        return DataResponse::from(e);
+    }
+
+    // The members are already in the clan, so a failure here only leaves a duplicate
+    if let Err(e) = other.delete(&database).await {
+        return DataResponse::from(e);
+    }
+
+    AuditEntry::new(clan.id(), None, Action::ClanMerged { from: other.id() }).record(&database).await;
+
+    merge
+}
+
+/// Count the clans with broken invariants, without fixing them.
+#[get("/admin/maintenance/diagnostics")]
+pub async fn diagnostics(database: Data<Database>) -> DataResponse<Diagnostics> {
+    let Ok(mut cursor) = database.clans.find(doc! {}).await else {
+        return DataResponse::from(ErrorCode::InternalServerError);
+    };
+
+    let mut diagnostics = DataResponse::<Diagnostics>::from(SUCCESS);
+    while let Some(clan) = cursor.next().await {
+        let clan = match clan {
+            Ok(clan) => clan,
+            Err(e) => {
+                log::error!("Error while fetching clan: {e}");
+                continue;
+            }
+        };
+
+        diagnostics.data.record(&clan);
+    }
+
+    diagnostics
+}
+
+/// List the clans, optionally only the ones on a given platform.
+///
+/// Passing ``updatedSince`` only lists the clans that changed after it,
+/// so that other services can sync incrementally.
+#[get("/admin/clans")]
+pub async fn list_clans(database: Data<Database>, query: Query<ListClans>) -> DataResponse<Clans> {
+    let Ok(mut cursor) = database.clans.find(query.filter()).await else {
+        return DataResponse::from(ErrorCode::InternalServerError);
+    };
+
+    let mut clans = DataResponse::<Clans>::from(SUCCESS);
+    while let Some(clan) = cursor.next().await {
+        match clan {
+            Ok(clan) => clans.data.clans.push(ClanSummary::from(clan)),
+            Err(e) => log::error!("Error while fetching clan: {e}"),
+        }
+    }
+
+    clans
+}
+
+/// List the clans a player is in, on every platform.
+#[get("/admin/player/{username}/clans")]
+pub async fn get_player_clans(database: Data<Database>, username: Path<String>) -> DataResponse<PlayerClans> {
+    // Match the username of any JID, escaping it so it can't alter the pattern
+    let escaped: String = username
+        .chars()
+        .flat_map(|c| (!c.is_alphanumeric()).then_some('\\').into_iter().chain([c]))
+        .collect();
+    let filter = doc! { "members.jid": { "$regex": format!("^{escaped}@") } };
+
+    let Ok(mut cursor) = database.clans.find(filter).await else {
+        return DataResponse::from(ErrorCode::InternalServerError);
+    };
+
+    let mut clans = DataResponse::<PlayerClans>::from(SUCCESS);
+    while let Some(clan) = cursor.next().await {
+        match clan {
+            Ok(clan) => clans.data.push(&clan, &username),
+            Err(e) => log::error!("Error while fetching clan: {e}"),
+        }
+    }
+
+    clans
+}
\ No newline at end of file
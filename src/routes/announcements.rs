@@ -1,8 +1,9 @@
 //! TODO: document this
 
 use actix_web::{post, web::Data};
+use chrono::DateTime;
 
-use crate::{database::Database, structs::{entities::{announcement::Announcement, clan::Clan, player::{Jid, Role, Status}}, requests::{announcements::{DeleteAnnouncement, PostAnnouncement, RetrieveAnnouncements}, base::Request}, responses::{base::{Content, List, Response}, entities::{AnnouncementInfo, IdEntity}, error::ErrorCode}}};
+use crate::{database::Database, structs::{entities::{announcement::Announcement, clan::Clan, permission::Operation, player::{Jid, Status}}, requests::{announcements::{DeleteAnnouncement, DeleteAnnouncementsBefore, GetAnnouncementReaders, MarkAnnouncementRead, PinAnnouncement, PostAnnouncement, RetrieveAnnouncements}, base::Request}, responses::{base::{Content, List, Response}, entities::{AnnouncementInfo, BlacklistEntry, CountEntity, IdEntity}, error::ErrorCode}}, utils};
 
 /// Retrieve a clan's announcements.
 /// 
@@ -12,7 +13,7 @@ use crate::{database::Database, structs::{entities::{announcement::Announcement,
 pub async fn retrieve_announcements(database: Data<Database>, req: Request<RetrieveAnnouncements>) -> Response<AnnouncementInfo> {
     let jid = Jid::from(req.request.ticket.clone());
 
-    let clan = match Clan::resolve(req.request.id, &database).await {
+    let clan = match Clan::resolve_active(req.request.id, &req.request.ticket, &database).await {
         Ok(clan) => clan,
         Err(e) => return Response::error(e),
     };
@@ -22,20 +23,30 @@ pub async fn retrieve_announcements(database: Data<Database>, req: Request<Retri
         return Response::error(ErrorCode::PermissionDenied);
     }
 
+    // Only keep the requested category, if any
+    let mut announcements: Vec<_> = clan.announcements
+        .iter()
+        .filter(|m| m.is_in(req.request.category.as_deref()))
+        .collect();
+
+    // List pinned announcements first, keeping the rest in order
+    announcements.sort_by_key(|m| !m.pinned);
+
     // Collect all valid entries
-    let items = clan.announcements
+    let items = announcements
         .iter()
-        .skip((req.request.start - 1).max(0) as usize)
+        .skip(utils::pagination::skip(req.request.start) as usize)
         .take(req.request.max as usize)
         .filter(|m| !m.has_expired())
-        .map(|m| AnnouncementInfo::from(m.to_owned()))
+        .map(|m| AnnouncementInfo::from((*m).to_owned()))
         .collect::<Vec<AnnouncementInfo>>();
 
     let list = List {
         results: items.len() as u32,
-        total: clan.announcements.len() as u32,
+        total: announcements.len() as u32,
 
         items,
+        next: None,
     };
 
     Response::success(Content::List(list))
@@ -45,26 +56,59 @@ pub async fn retrieve_announcements(database: Data<Database>, req: Request<Retri
 /// 
 /// The author needs to:
 ///     - Be at least a ``SubLeader`` of the clan
+/// 
+/// The clan needs to:
+///     - Not have posted another announcement too recently
+///
+/// The announcement's ``bin-data`` can't be larger than ``MAX_ANNOUNCEMENT_DATA_SIZE``.
 #[post("/clan_manager_update/sec/post_announcement")]
 pub async fn post_announcement(database: Data<Database>, req: Request<PostAnnouncement>) -> Response<IdEntity> {
     let jid = Jid::from(req.request.ticket.clone());
 
-    let mut clan = match Clan::resolve(req.request.id, &database).await {
+    let mut clan = match Clan::resolve_active(req.request.id, &req.request.ticket, &database).await {
         Ok(clan) => clan,
         Err(e) => return Response::error(e),
     };
 
     // Check if the author has permissions to post an announcement
-    if clan.role_of(&jid).is_none_or(|role| role < &Role::SubLeader) {
+    if !Operation::PostAnnouncement.allows(clan.role_of(&jid)) {
         return Response::error(ErrorCode::PermissionDenied);
     }
 
+    // Check if the clan has posted another announcement too recently
+    if clan.posted_recently() {
+        return Response::error(ErrorCode::AnnouncementLimitReached);
+    }
+
     // Create the announcement
-    let announcement = Announcement::from(req.request);
+    let mut announcement = Announcement::from(req.request);
     let id = announcement.id();
 
+    // Check if the announcement's data fits
+    if announcement.has_too_much_data() {
+        return Response::error(ErrorCode::BadRequest);
+    }
+
+    // Check if the announcement's data matches its declared type
+    if let Err(e) = announcement.bin_data_type.validate(&announcement.bin_data) {
+        return Response::error(e);
+    }
+
+    // Only refer to announcements that exist, as a ``from-id`` of ``0`` breaks the game
+    announcement.refer_within(&clan.announcements);
+
+    // Remember the author's name, as they might leave the clan later
+    announcement.author_online_name = clan.members
+        .iter()
+        .find(|p| p.jid == jid)
+        .map(|p| p.online_name.clone())
+        .filter(|name| !name.is_empty());
+
     clan.announcements.push(announcement);
 
+    // Record the author's activity
+    clan.touch(&jid);
+
     // Update the clan
     if let Err(e) = clan.save(&database).await { return Response::error(e); }
 
@@ -79,13 +123,13 @@ pub async fn post_announcement(database: Data<Database>, req: Request<PostAnnoun
 pub async fn delete_announcement(database: Data<Database>, req: Request<DeleteAnnouncement>) -> Response<()> {
     let jid = Jid::from(req.request.ticket.clone());
 
-    let mut clan = match Clan::resolve(req.request.id, &database).await {
+    let mut clan = match Clan::resolve_active(req.request.id, &req.request.ticket, &database).await {
         Ok(clan) => clan,
         Err(e) => return Response::error(e),
     };
 
     // Check if the author has permissions to delete the announcement
-    if clan.role_of(&jid).is_none_or(|role| role < &Role::SubLeader) {
+    if !Operation::DeleteAnnouncement.allows(clan.role_of(&jid)) {
         return Response::error(ErrorCode::PermissionDenied);
     }
 
@@ -95,8 +139,154 @@ pub async fn delete_announcement(database: Data<Database>, req: Request<DeleteAn
 
     clan.announcements.remove(index);
 
+    // Record the author's activity
+    clan.touch(&jid);
+
     // Update the clan
     if let Err(e) = clan.save(&database).await { return Response::error(e); }
 
     Response::success(Content::Empty)
+}
+
+/// Delete every announcement of a clan posted before a date,
+/// returning how many were deleted.
+/// 
+/// The author needs to:
+///     - Be at least a ``SubLeader`` of the clan
+#[post("/clan_manager_update/sec/delete_announcements_before")]
+#[allow(clippy::cast_possible_truncation)]
+pub async fn delete_announcements_before(database: Data<Database>, req: Request<DeleteAnnouncementsBefore>) -> Response<CountEntity> {
+    let jid = Jid::from(req.request.ticket.clone());
+
+    let mut clan = match Clan::resolve_active(req.request.id, &req.request.ticket, &database).await {
+        Ok(clan) => clan,
+        Err(e) => return Response::error(e),
+    };
+
+    // Check if the author has permissions to delete announcements
+    if !Operation::DeleteAnnouncement.allows(clan.role_of(&jid)) {
+        return Response::error(ErrorCode::PermissionDenied);
+    }
+
+    let Some(cutoff) = DateTime::from_timestamp(req.request.before, 0)
+    else { return Response::error(ErrorCode::BadRequest) };
+
+    // Remove the older announcements
+    let deleted = clan.delete_announcements_before(cutoff);
+
+    // Record the author's activity
+    clan.touch(&jid);
+
+    // Update the clan
+    if let Err(e) = clan.save(&database).await { return Response::error(e); }
+
+    Response::success(Content::Item(CountEntity::from(deleted as u32)))
+}
+
+/// Pin or unpin an announcement, so that it's listed before the others.
+/// 
+/// The author needs to:
+///     - Be at least a ``SubLeader`` of the clan
+/// 
+/// The clan needs to:
+///     - Not have too many pinned announcements already
+#[post("/clan_manager_update/sec/pin_announcement")]
+pub async fn pin_announcement(database: Data<Database>, req: Request<PinAnnouncement>) -> Response<()> {
+    let jid = Jid::from(req.request.ticket.clone());
+
+    let mut clan = match Clan::resolve_active(req.request.id, &req.request.ticket, &database).await {
+        Ok(clan) => clan,
+        Err(e) => return Response::error(e),
+    };
+
+    // Check if the author has permissions to pin the announcement
+    if !Operation::PinAnnouncement.allows(clan.role_of(&jid)) {
+        return Response::error(ErrorCode::PermissionDenied);
+    }
+
+    // Pin the announcement, if the clan has room for another pinned one
+    if let Err(e) = clan.pin_announcement(req.request.msg_id, req.request.pinned) {
+        return Response::error(e);
+    }
+
+    // Record the author's activity
+    clan.touch(&jid);
+
+    // Update the clan
+    if let Err(e) = clan.save(&database).await { return Response::error(e); }
+
+    Response::success(Content::Empty)
+}
+
+/// Mark an announcement as read by the author.
+/// 
+/// The author needs to:
+///     - Be a member of the clan
+#[post("/clan_manager_update/sec/mark_announcement_read")]
+pub async fn mark_announcement_read(database: Data<Database>, req: Request<MarkAnnouncementRead>) -> Response<()> {
+    let jid = Jid::from(req.request.ticket.clone());
+
+    let mut clan = match Clan::resolve_active(req.request.id, &req.request.ticket, &database).await {
+        Ok(clan) => clan,
+        Err(e) => return Response::error(e),
+    };
+
+    // Check if the author has permissions to view the announcements
+    if !(clan.status_of(&jid) == Some(&Status::Member)) {
+        return Response::error(ErrorCode::PermissionDenied);
+    }
+
+    let Some(announcement) = clan.announcements.iter_mut().find(|m| m.id() == req.request.msg_id)
+    else { return Response::error(ErrorCode::NoSuchClanAnnouncement) };
+
+    // Only record each player once
+    if !announcement.mark_read(jid) {
+        return Response::success(Content::Empty);
+    }
+
+    // Update the clan
+    if let Err(e) = clan.save(&database).await { return Response::error(e); }
+
+    Response::success(Content::Empty)
+}
+
+/// Get the players who have read an announcement.
+/// 
+/// The author needs to:
+///     - Be at least a ``SubLeader`` of the clan
+#[post("/clan_manager_view/sec/get_announcement_readers")]
+#[allow(clippy::cast_possible_truncation)]
+pub async fn get_announcement_readers(database: Data<Database>, req: Request<GetAnnouncementReaders>) -> Response<BlacklistEntry> {
+    let jid = Jid::from(req.request.ticket.clone());
+
+    let clan = match Clan::resolve_active(req.request.id, &req.request.ticket, &database).await {
+        Ok(clan) => clan,
+        Err(e) => return Response::error(e),
+    };
+
+    // Check if the author has permissions to view the readers
+    if !Operation::ViewAnnouncementReaders.allows(clan.role_of(&jid)) {
+        return Response::error(ErrorCode::PermissionDenied);
+    }
+
+    let Some(announcement) = clan.announcements.iter().find(|m| m.id() == req.request.msg_id)
+    else { return Response::error(ErrorCode::NoSuchClanAnnouncement) };
+
+    // Collect all valid entries
+    let items = announcement.read_by
+        .iter()
+        .skip(utils::pagination::skip(req.request.start) as usize)
+        .take(req.request.max as usize)
+        .map(|m| BlacklistEntry::from(m.to_owned()))
+        .collect::<Vec<BlacklistEntry>>();
+
+    let list = List {
+        results: items.len() as u32,
+        total: announcement.read_by.len() as u32,
+
+        items,
+        next: None,
+    };
+
+    Response::success(Content::List(list))
 }
\ No newline at end of file
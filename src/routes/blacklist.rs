@@ -6,20 +6,19 @@
 //! - ...
 
 use actix_web::{post, web::Data};
-use mongodb::bson::doc;
 
 use crate::{database::Database, structs::{
-    entities::{clan::Clan, player::{Jid, Role, Status}}, requests::{base::Request, blacklist::{DeleteBlacklistEntry, GetBlacklist, RecordBlacklistEntry}}, responses::{
+    entities::{clan::{Clan, MAX_CLAN_BLACKLIST_SIZE}, permission::Operation, player::{Jid, Status}}, requests::{base::Request, blacklist::{DeleteBlacklistEntry, GetBlacklist, GetBlacklistCount, IsBlacklisted, RecordBlacklistEntry}}, responses::{
         base::{Content, List, Response},
-        entities::BlacklistEntry, error::ErrorCode,
+        entities::{BlacklistEntry, BlacklistStatus, CountEntity}, error::ErrorCode,
     }
-}};
+}, utils};
 
 /// Get a clan's blacklist.
 #[post("/clan_manager_view/sec/get_blacklist")]
 #[allow(clippy::cast_possible_truncation)]
 pub async fn get_blacklist(database: Data<Database>, req: Request<GetBlacklist>) -> Response<BlacklistEntry> {
-    let clan = match Clan::resolve(req.request.id, &database).await {
+    let clan = match Clan::resolve_active(req.request.id, &req.request.ticket, &database).await {
         Ok(clan) => clan,
         Err(e) => return Response::error(e),
     };
@@ -27,7 +26,7 @@ pub async fn get_blacklist(database: Data<Database>, req: Request<GetBlacklist>)
     // Collect all valid entries
     let items = clan.blacklist
         .iter()
-        .skip((req.request.start - 1).max(0) as usize)
+        .skip(utils::pagination::skip(req.request.start) as usize)
         .take(req.request.max as usize)
         .map(|m| BlacklistEntry::from(m.to_owned()))
         .collect::<Vec<BlacklistEntry>>();
@@ -37,45 +36,111 @@ pub async fn get_blacklist(database: Data<Database>, req: Request<GetBlacklist>)
         total: clan.blacklist.len() as u32,
 
         items,
+        next: None,
     };
 
     Response::success(Content::List(list))
 }
 
+/// Count the entries in a clan's blacklist, without listing them.
+/// 
+/// - The author needs to:
+///     - Be a ``SubLeader`` or higher
+#[post("/clan_manager_view/sec/get_blacklist_count")]
+#[allow(clippy::cast_possible_truncation)]
+pub async fn get_blacklist_count(database: Data<Database>, req: Request<GetBlacklistCount>) -> Response<CountEntity> {
+    let jid = Jid::from(req.request.ticket.clone());
+
+    let clan = match Clan::resolve_active(req.request.id, &req.request.ticket, &database).await {
+        Ok(clan) => clan,
+        Err(e) => return Response::error(e),
+    };
+
+    // Check if the user is allowed to view the blacklist
+    if !Operation::ViewBlacklist.allows(clan.role_of(&jid)) {
+        return Response::error(ErrorCode::PermissionDenied);
+    }
+
+    Response::success(Content::Item(CountEntity::from(clan.blacklist.len() as u32)))
+}
+
+/// Check whether a player is in a clan's blacklist.
+/// 
+/// - The author needs to:
+///     - Be a ``SubLeader`` or higher
+#[post("/clan_manager_view/sec/is_blacklisted")]
+pub async fn is_blacklisted(database: Data<Database>, req: Request<IsBlacklisted>) -> Response<BlacklistStatus> {
+    let jid = Jid::from(req.request.ticket.clone());
+    let Ok(target) = Jid::try_from(req.request.jid.clone())
+    else { return Response::error(ErrorCode::InvalidNpId) };
+
+    let clan = match Clan::resolve_active(req.request.id, &req.request.ticket, &database).await {
+        Ok(clan) => clan,
+        Err(e) => return Response::error(e),
+    };
+
+    // Check if the user is allowed to view the blacklist
+    if !Operation::ViewBlacklist.allows(clan.role_of(&jid)) {
+        return Response::error(ErrorCode::PermissionDenied);
+    }
+
+    Response::success(Content::Item(BlacklistStatus::from(clan.is_blacklisted(&target))))
+}
+
 /// Add a player to a clan's blacklist.
 /// 
 /// - The author needs to:
 ///     - Be a ``SubLeader`` or higher
 /// 
 /// - The player needs to:
+///     - Not be the author, or the clan's leader
+///     - Not outrank the author
+///     - Not lead a clan the author leads
 ///     - Not be a member of the clan
+/// 
+/// - The clan needs to:
+///     - Not have a full blacklist
+/// 
+/// Blacklisting a player twice does nothing.
 #[post("/clan_manager_update/sec/record_blacklist_entry")]
 pub async fn record_blacklist_entry(database: Data<Database>, req: Request<RecordBlacklistEntry>) -> Response<()> {
-    let jid = Jid::from(req.request.ticket);
+    let jid = Jid::from(req.request.ticket.clone());
     let Ok(target) = Jid::try_from(req.request.jid.clone())
     else { return Response::error(ErrorCode::InvalidNpId) };
 
-    let mut clan = match Clan::resolve(req.request.id, &database).await {
+    let clan = match Clan::resolve_active(req.request.id, &req.request.ticket, &database).await {
         Ok(clan) => clan,
         Err(e) => return Response::error(e),
     };
 
     // Check if the user is allowed to add to the blacklist
-    if clan.role_of(&jid).is_none_or(|role| role < &Role::SubLeader) {
+    if !Operation::RecordBlacklistEntry.allows(clan.role_of(&jid)) {
         return Response::error(ErrorCode::PermissionDenied);
     }
 
-    // Check if the player is a member of the clan
-    if clan.status_of(&target) == Some(&Status::Member) {
-        return Response::error(ErrorCode::MemberStatusInvalid);
+    // Check if the player can be blacklisted by the user,
+    // including whether they lead any clan the user leads
+    let Ok(clans) = jid.clans(database.clone()).await
+    else { return Response::error(ErrorCode::InternalServerError) };
+
+    if let Err(e) = clan.validate_blacklist_entry(&jid, &target, &clans) {
+        return Response::error(e);
+    }
+
+    // Check if the player is already blacklisted
+    if clan.is_blacklisted(&target) {
+        return Response::success(Content::Empty);
     }
 
-    // Add the player to the blacklist
-    clan.blacklist.push(target);
+    // Check if the blacklist is full
+    if clan.blacklist.len() >= MAX_CLAN_BLACKLIST_SIZE {
+        return Response::error(ErrorCode::BlacklistLimitReached);
+    }
 
-    // Update the clan
-    if database.clans.replace_one(doc! { "id": clan.id() }, clan).await.is_err() {
-        return Response::error(ErrorCode::InternalServerError);
+    // Add the player atomically, recording the author's activity,
+    // so that a concurrent request can't add them twice
+    if let Err(e) = clan.push_blacklist(&target, &jid, &database).await {
+        return Response::error(e);
     }
 
     Response::success(Content::Empty)
@@ -91,18 +156,18 @@ pub async fn record_blacklist_entry(database: Data<Database>, req: Request<Recor
 ///     - Be blacklisted
 #[post("/clan_manager_update/sec/delete_blacklist_entry")]
 pub async fn delete_blacklist_entry(database: Data<Database>, req: Request<DeleteBlacklistEntry>) -> Response<()> {
-    let jid = Jid::from(req.request.ticket);
+    let jid = Jid::from(req.request.ticket.clone());
     let Ok(target) = Jid::try_from(req.request.jid.clone())
     else { return Response::error(ErrorCode::InvalidNpId) };
 
     // Find the clan
-    let mut clan = match Clan::resolve(req.request.id, &database).await {
+    let mut clan = match Clan::resolve_active(req.request.id, &req.request.ticket, &database).await {
         Ok(clan) => clan,
         Err(e) => return Response::error(e),
     };
 
     // Check if the user is allowed to remove from the blacklist
-    if clan.role_of(&jid).is_none_or(|role| role < &Role::SubLeader) {
+    if !Operation::DeleteBlacklistEntry.allows(clan.role_of(&jid)) {
         return Response::error(ErrorCode::PermissionDenied);
     }
 
@@ -119,8 +184,11 @@ pub async fn delete_blacklist_entry(database: Data<Database>, req: Request<Delet
     // Remove the player from the blacklist
     clan.blacklist.retain(|j| j != &target);
 
+    // Record the author's activity
+    clan.touch(&jid);
+
     // Update the clan
-    if database.clans.replace_one(doc! { "id": clan.id() }, clan).await.is_err() {
+    if clan.write(&database).await.is_err() {
         return Response::error(ErrorCode::InternalServerError);
     }
 
@@ -0,0 +1,52 @@
+//! Routes describing the server itself, for companion clients
+//! that need to know what a given deployment supports.
+
+use actix_web::get;
+
+use crate::structs::responses::capabilities::{Capabilities, RolePermissions};
+
+/// Get the server's version, enabled features and limits.
+#[get("/capabilities")]
+pub async fn capabilities() -> Capabilities {
+    Capabilities::default()
+}
+
+/// Get the minimum role each operation requires, so that
+/// clients can tell which actions a player can take.
+#[get("/capabilities/roles")]
+pub async fn roles() -> RolePermissions {
+    RolePermissions::default()
+}
+
+#[cfg(test)]
+mod tests {
+    //! Tests for describing the server.
+
+    use actix_web::{test, App};
+
+    use super::capabilities;
+
+    /// Fetch the capabilities, as a companion client would.
+    async fn fetch() -> String {
+        let app = test::init_service(App::new().service(capabilities)).await;
+        let body = test::call_and_read_body(&app, test::TestRequest::get().uri("/capabilities").to_request()).await;
+
+        String::from_utf8_lossy(&body).into_owned()
+    }
+
+    /// Servers compiled with the admin routes report them.
+    #[cfg(feature = "admin")]
+    #[actix_web::test]
+    async fn reports_admin_feature() {
+        let body = fetch().await;
+        assert!(body.contains(r#""features":["admin"]"#), "{body}");
+    }
+
+    /// Servers compiled without the admin routes don't report them.
+    #[cfg(not(feature = "admin"))]
+    #[actix_web::test]
+    async fn omits_admin_feature() {
+        let body = fetch().await;
+        assert!(body.contains(r#""features":[]"#), "{body}");
+    }
+}
@@ -6,24 +6,32 @@
 //! - Editing a clan
 //! - ...
 
-use actix_web::{post, web::Data};
+use actix_web::{
+    http::header::{ETag, EntityTag, IfNoneMatch},
+    post,
+    web::{Data, Header},
+    CustomizeResponder, Either, HttpResponse, Responder,
+};
+use chrono::Utc;
 use futures_util::StreamExt;
-use mongodb::bson::doc;
+use mongodb::bson::{doc, Bson, Document};
 
 use crate::{
     database::Database,
     structs::{
         entities::{
             clan::{
-                Clan, Platform, MAX_CLAN_DESCRIPTION_LENGTH, MAX_CLAN_MEMBERSHIP,
-                MAX_CLAN_NAME_LENGTH, MAX_CLAN_OWNERSHIP, MAX_CLAN_TAG_LENGTH,
+                Clan, Platform, MAX_CLAN_MEMBERSHIP,
+                MAX_CLAN_OWNERSHIP, REQUIRE_CLAN_APPROVAL_ENV,
             },
-            player::{ExtendedJid, Jid, Role, Status},
+            permission::Operation,
+            player::{ExtendedJid, Jid, Status},
         },
         requests::{
             base::Request,
             clans::{
-                ClanSearch, CreateClan, DisbandClan, GetClanInfo, GetClanList, UpdateClanInfo,
+                CancelDisband, ClanSearch, ClanSearchFilter, ClanSort, CreateClan, DisbandClan, GetClanInfo, GetClanList, GetLastActiveClan,
+                GetLeaderboard, UpdateClanInfo,
             },
         },
         responses::{
@@ -32,24 +40,101 @@ use crate::{
             error::ErrorCode,
         },
     },
+    utils::{self, rate_limit::CreationLimiter, validation},
 };
 
 /// View basic information about a clan.
+///
+/// If a ticket is given, the viewer's own role and status are included,
+/// so that clients know whether to offer joining or leaving the clan.
+///
+/// The response carries an ``ETag`` header, so that clients polling
+/// the clan can send it back as ``If-None-Match`` and receive
+/// ``304 Not Modified`` if nothing changed. The tag depends on the viewer,
+/// as their membership is part of the response.
 #[post("/clan_manager_view/func/get_clan_info")]
 pub async fn get_clan_info(
     database: Data<Database>,
     req: Request<GetClanInfo>,
-) -> Response<ClanInfo> {
+    if_none_match: Option<Header<IfNoneMatch>>,
+) -> Either<CustomizeResponder<Response<ClanInfo>>, HttpResponse> {
     let Ok(clan) = database.clans.find_one(doc! { "id": req.request.id }).await else {
-        return Response::error(ErrorCode::InternalServerError);
+        return Either::Left(Response::error(ErrorCode::InternalServerError).customize());
     };
 
-    if clan.is_none() {
-        return Response::error(ErrorCode::NoSuchClan);
+    // Without a ticket, only clans of the default title can be told apart
+    let title = req.request.ticket.as_ref().and_then(Clan::title_of);
+    let Some(clan) = clan.filter(|clan| clan.is_shown_in(title.as_deref())) else {
+        return Either::Left(Response::error(ErrorCode::NoSuchClan).customize());
+    };
+
+    // Include the viewer's membership, from their platform's perspective
+    let viewer = req.request.ticket.map(|ticket| (Jid::from(ticket.clone()), Platform::from(ticket)));
+    let etag = EntityTag::new_strong(clan.etag(viewer.as_ref().map(|(jid, platform)| (jid, platform))));
+
+    // Check if the client already has the latest version of the clan
+    let not_modified = match if_none_match.map(Header::into_inner) {
+        Some(IfNoneMatch::Any) => true,
+        Some(IfNoneMatch::Items(tags)) => tags.iter().any(|tag| tag.weak_eq(&etag)),
+        None => false,
+    };
+
+    if not_modified {
+        return Either::Right(HttpResponse::NotModified().insert_header(ETag(etag)).finish());
     }
 
-    let info = ClanInfo::from(clan.unwrap());
-    Response::success(Content::Item(info))
+    let info = match viewer {
+        Some((jid, platform)) => ClanInfo::from(clan.clone()).with_viewer(&clan, &jid, &platform),
+        None => ClanInfo::from(clan),
+    };
+
+    Either::Left(
+        Response::success(Content::Item(info))
+            .customize()
+            .insert_header(ETag(etag)),
+    )
+}
+
+/// Find a page of the clans matching `filter`, in the given order.
+///
+/// Clans are given a ``member_count`` field to be sorted by,
+/// which only counts the players that are actually members.
+async fn find_page(
+    database: &Database,
+    filter: Document,
+    sort: Option<Document>,
+    skip: i64,
+    limit: i64,
+) -> Result<Vec<Clan>, ErrorCode> {
+    let mut pipeline = vec![
+        doc! { "$match": filter },
+        doc! { "$addFields": { "member_count": { "$size": { "$filter": {
+            "input": "$members",
+            "cond": { "$eq": ["$$this.status", "Member"] },
+        } } } } },
+    ];
+
+    if let Some(sort) = sort {
+        pipeline.push(doc! { "$sort": sort });
+    }
+
+    pipeline.push(doc! { "$skip": skip });
+    pipeline.push(doc! { "$limit": limit });
+
+    let Ok(mut cursor) = database.clans.aggregate(pipeline).await else {
+        return Err(ErrorCode::InternalServerError);
+    };
+
+    let mut clans = vec![];
+    while let Some(document) = cursor.next().await {
+        match document.map(mongodb::bson::from_document::<Clan>) {
+            Ok(Ok(clan)) => clans.push(clan),
+            Ok(Err(e)) => log::error!("Error while deserializing clan: {e}"),
+            Err(e) => log::error!("Error while fetching clan: {e}"),
+        }
+    }
+
+    Ok(clans)
 }
 
 /// Get a list of clans.
@@ -59,11 +144,12 @@ pub async fn get_clan_list(
     database: Data<Database>,
     req: Request<GetClanList>,
 ) -> Response<ClanPlayerInfo> {
-    let jid = Jid::from(req.request.ticket.clone());
-
     // EXTRA: log the player's Jid in the `Players` collection, for future lookups
-    let jid_ext = ExtendedJid::from(jid.clone());
-    let player = match database
+    //
+    // This is best-effort, as listing the clans doesn't depend on it
+    let jid_ext = ExtendedJid::resolve(&req.request.ticket, &database).await;
+    let jid = Jid::from(jid_ext.clone());
+    let missing = match database
         .players
         .find_one(doc! {
             "username": jid_ext.username.clone(),
@@ -72,15 +158,15 @@ pub async fn get_clan_list(
         })
         .await
     {
-        Ok(player) => player,
+        Ok(player) => player.is_none(),
         Err(e) => {
             log::error!("Failed to look-up player `{jid}` in the database: {e}");
-            return Response::error(ErrorCode::InternalServerError);
+            false
         }
     };
 
     // Store the player's Jid in the database, if it doesn't exist
-    if player.is_none() {
+    if missing {
         match database.players.insert_one(jid_ext).await {
             Ok(_) => log::info!("Inserted player `{jid}` into the database"),
             Err(e) => log::error!("Failed to log player `{jid}` into the database: {e}"),
@@ -88,54 +174,88 @@ pub async fn get_clan_list(
     }
 
     // Find all the clans where the user is relevant
-    let Ok(mut clans) = database
-        .clans
-        .find(doc! {
-            "members.jid": jid.to_string(),
-        })
-        .await
-    else {
+    let mut filter = req.request.filter(&jid);
+
+    let Ok(total) = database.clans.count_documents(filter.clone()).await else {
         return Response::error(ErrorCode::InternalServerError);
     };
 
-    // Collect all valid entries
-    let mut data: Vec<Clan> = vec![];
-    while let Some(clan) = clans.next().await {
-        match clan {
-            Ok(clan) => data.push(clan),
-            Err(e) => log::error!("Error while fetching clan: {e}"),
-        }
-    }
+    // Page with a cursor, if given, rather than skipping clans
+    let after = match req.request.after.as_deref().map(utils::cursor::decode).transpose() {
+        Ok(after) => after,
+        Err(e) => return Response::error(e),
+    };
 
-    let data_len = data.len();
+    let skip = i64::from(utils::pagination::skip(req.request.start));
+    let limit = req.request.page_size();
 
-    // Format them from the perspective of the player
-    let mut items: Vec<ClanPlayerInfo> = data
-        .into_iter()
-        .skip((req.request.start - 1).max(0) as usize)
-        .take(req.request.max as usize)
-        .map(|clan| ClanPlayerInfo::from((clan, jid.clone())))
-        .collect();
+    let (skip, sort) = if let Some(after) = after {
+        filter.insert("id", doc! { "$gt": after });
+        (0, Some(doc! { "id": 1 }))
+    } else {
+        (skip, req.request.sort.map(ClanSort::to_sort))
+    };
+
+    let data = match find_page(&database, filter, sort, skip, limit).await {
+        Ok(data) => data,
+        Err(e) => return Response::error(e),
+    };
+
+    // Point to the next page, if this one is full
+    let next = data
+        .last()
+        .filter(|_| after.is_some() && data.len() == limit as usize)
+        .map(|clan| utils::cursor::encode(clan.id()));
 
-    // Make sure the game doesn't know they're a member of another clan on a different platform
+    // Format them from the perspective of the player, on their platform
     let platform = Platform::from(req.request.ticket);
-    for c in &mut items {
-        if c.status == Status::Member as u32 && c.platform != platform {
-            c.role = Role::NonMember as u32;
-            c.status = Status::Unknown as u32;
-        }
-    }
+    let items: Vec<ClanPlayerInfo> = data
+        .iter()
+        .map(|clan| clan.view_for(&jid, &platform))
+        .collect();
 
     let list = List {
         results: items.len() as u32,
-        total: data_len as u32,
+        total: total as u32,
 
         items,
+        next,
     };
 
     Response::success(Content::List(list))
 }
 
+/// Get the clan the player most recently interacted with,
+/// among the ones they're a member of on their platform.
+///
+/// This is not part of the game's API: it lets companion
+/// clients pre-select the player's clan.
+#[post("/clan_manager_view/sec/get_last_active_clan")]
+pub async fn get_last_active_clan(
+    database: Data<Database>,
+    req: Request<GetLastActiveClan>,
+) -> Response<ClanPlayerInfo> {
+    let jid = Jid::from(req.request.ticket.clone());
+    let title = Clan::title_of(&req.request.ticket);
+    let platform = Platform::from(req.request.ticket);
+
+    let Ok(clans) = jid.clans(database.clone()).await else {
+        return Response::error(ErrorCode::InternalServerError);
+    };
+
+    // Clans the player never interacted with come last
+    let Some(clan) = clans
+        .into_iter()
+        .filter(|c| c.is_shown_in(title.as_deref()))
+        .filter(|c| c.status_of(&jid) == Some(&Status::Member) && c.accepts_platform(&platform))
+        .max_by_key(|c| c.last_seen_of(&jid))
+    else {
+        return Response::error(ErrorCode::NoSuchClan);
+    };
+
+    Response::success(Content::Item(clan.view_for(&jid, &platform)))
+}
+
 /// Search for a clan.
 #[post("/clan_manager_view/func/clan_search")]
 #[allow(clippy::cast_possible_truncation)]
@@ -143,56 +263,45 @@ pub async fn clan_search(
     database: Data<Database>,
     req: Request<ClanSearch>,
 ) -> Response<ClanSearchInfo> {
-    let mut filter_doc = doc! {};
-
-    if let Some(filter) = &req.request.filter {
-        let mut value = filter.name.value.trim().to_lowercase();
-        let mut platform = None;
-
-        // Clan names are actually returned from the API with a suffix
-        // indicating their platform of creation. This is so players
-        // in-game can know which platform a clan is from.
-        //
-        // We want clans of all platforms to be visible, but not cross-joinable,
-        // so we do support searching but will block joining in the other endpoints.
-        if value.ends_with("[ps3]") {
-            value = value.strip_suffix("[ps3]").unwrap().trim().to_string();
-            platform = Some("Console");
-        } else if value.ends_with("[pc]") {
-            value = value.strip_suffix("[pc]").unwrap().trim().to_string();
-            platform = Some("Emulator");
-        }
+    let mut filter_doc = req.request.filter.as_ref().map_or_else(|| doc! {}, ClanSearchFilter::to_filter);
 
-        filter_doc = filter.name.operator.to_filter(&value);
+    // Hide clans that haven't been approved yet, or have been disbanded
+    filter_doc.insert("pending_approval", doc! { "$ne": true });
+    filter_doc.insert("disband_at", Bson::Null);
 
-        if let Some(p) = platform {
-            filter_doc.insert("platform", p);
-        }
-    }
+    // Only search the clans of the player's title, or the default one without a ticket
+    filter_doc.insert("title", req.request.ticket.as_ref().and_then(Clan::title_of));
 
     let Ok(total) = database.clans.count_documents(filter_doc.clone()).await else {
         return Response::error(ErrorCode::InternalServerError);
     };
 
-    let skip = (req.request.start - 1).max(0) as u64;
+    // Page with a cursor, if given, rather than skipping clans
+    let after = match req.request.after.as_deref().map(utils::cursor::decode).transpose() {
+        Ok(after) => after,
+        Err(e) => return Response::error(e),
+    };
+
+    let skip = i64::from(utils::pagination::skip(req.request.start));
     let limit = i64::from(req.request.max.max(1));
 
-    let Ok(mut cursor) = database
-        .clans
-        .find(filter_doc)
-        .skip(skip)
-        .limit(limit)
-        .await
-    else {
-        return Response::error(ErrorCode::InternalServerError);
+    let (skip, sort) = if let Some(after) = after {
+        filter_doc.insert("id", doc! { "$gt": after });
+        (0, Some(doc! { "id": 1 }))
+    } else {
+        (skip, req.request.sort.map(ClanSort::to_sort))
     };
 
-    let mut data: Vec<Clan> = vec![];
-    while let Some(clan) = cursor.next().await {
-        if let Ok(clan) = clan {
-            data.push(clan);
-        }
-    }
+    let data = match find_page(&database, filter_doc, sort, skip, limit).await {
+        Ok(data) => data,
+        Err(e) => return Response::error(e),
+    };
+
+    // Point to the next page, if this one is full
+    let next = data
+        .last()
+        .filter(|_| after.is_some() && data.len() == req.request.max.max(1) as usize)
+        .map(|clan| utils::cursor::encode(clan.id()));
 
     // Format them from the perspective of the player (Clan -> ClanSearchInfo)
     let items: Vec<ClanSearchInfo> = data.into_iter().map(ClanSearchInfo::from).collect();
@@ -202,25 +311,72 @@ pub async fn clan_search(
         total: total as u32,
 
         items,
+        next,
+    };
+
+    Response::success(Content::List(list))
+}
+
+/// Get the clans with the most members, on the player's platform.
+#[post("/clan_manager_view/sec/get_leaderboard")]
+#[allow(clippy::cast_possible_truncation)]
+pub async fn leaderboard(
+    database: Data<Database>,
+    req: Request<GetLeaderboard>,
+) -> Response<ClanSearchInfo> {
+    let title = Clan::title_of(&req.request.ticket);
+    let platform = Platform::from(req.request.ticket);
+
+    // Only rank the clans the player could actually join
+    let filter = doc! {
+        "$or": [{ "platform": platform.name() }, { "cross_platform": true }],
+        "pending_approval": { "$ne": true },
+        "disband_at": null,
+        "title": title,
+    };
+
+    let Ok(total) = database.clans.count_documents(filter.clone()).await else {
+        return Response::error(ErrorCode::InternalServerError);
+    };
+
+    let skip = i64::from(utils::pagination::skip(req.request.start));
+    let limit = i64::from(req.request.max.max(1));
+
+    let sort = Some(ClanSort::MembersDesc.to_sort());
+    let items: Vec<ClanSearchInfo> = match find_page(&database, filter, sort, skip, limit).await {
+        Ok(data) => data.into_iter().map(ClanSearchInfo::from).collect(),
+        Err(e) => return Response::error(e),
+    };
+
+    let list = List {
+        results: items.len() as u32,
+        total: total as u32,
+
+        items,
+        next: None,
     };
 
     Response::success(Content::List(list))
 }
 
 /// Create a clan.
+///
+/// Players can only create so many clans within a window of time.
+/// See: [`CreationLimiter`]
 #[post("/clan_manager_update/sec/create_clan")]
-pub async fn create_clan(database: Data<Database>, req: Request<CreateClan>) -> Response<IdEntity> {
+pub async fn create_clan(
+    database: Data<Database>,
+    limiter: Data<CreationLimiter>,
+    req: Request<CreateClan>,
+) -> Response<IdEntity> {
     let author = Jid::from(req.request.ticket.clone());
     let mut clan = Clan::from(req.request);
 
-    // Limit the clan name and tag to their maximum lengths
-    clan.name = clan.name.chars().take(MAX_CLAN_NAME_LENGTH).collect();
-    clan.tag = clan.tag.chars().take(MAX_CLAN_TAG_LENGTH).collect();
-
-    // Make sure Unicode chars don't exceed the limits
-    if clan.name.len() > MAX_CLAN_NAME_LENGTH || clan.tag.len() > MAX_CLAN_TAG_LENGTH {
-        return Response::error(ErrorCode::PermissionDenied);
-    }
+    // Validate the clan name and tag, limiting them to their maximum lengths
+    (clan.name, clan.tag) = match validation::clan(&clan.name, &clan.tag, &database).await {
+        Ok(validated) => validated,
+        Err(e) => return Response::error(e),
+    };
 
     // Check the clans the author is in
     let Ok(clans) = author.clans(database.clone()).await else {
@@ -231,10 +387,8 @@ pub async fn create_clan(database: Data<Database>, req: Request<CreateClan>) ->
         .iter()
         .filter(|c| c.owner().is_some_and(|o| o.jid == author))
         .count();
-    let clans_member_len = clans
-        .iter()
-        .filter(|c| c.status_of(&author) == Some(&Status::Member))
-        .count();
+
+    let clans_member_len = Clan::memberships_on(&author, &clan.platform, &clans);
 
     // Check if the author is already in too many clans
     if clans_member_len >= MAX_CLAN_MEMBERSHIP {
@@ -246,36 +400,62 @@ pub async fn create_clan(database: Data<Database>, req: Request<CreateClan>) ->
         return Response::error(ErrorCode::ClanLeaderLimitReached);
     }
 
-    // Save the clan to the database.
-    if let Err(e) = clan.save(&database).await {
+    // Check if the author has been creating clans too often
+    if !limiter.allows(&author.to_string()) {
+        return Response::error(ErrorCode::ExceedsCreateClanFrequency);
+    }
+
+    // Hide the clan until a moderator approves it, if required
+    clan.pending_approval = utils::env::flag(REQUIRE_CLAN_APPROVAL_ENV, false);
+
+    // Record the author's activity
+    clan.touch(&author);
+
+    // Save the clan to the database, without replacing another one
+    if let Err(e) = clan.create(&database).await {
         return Response::error(e);
     }
 
+    limiter.record(&author.to_string());
+
     Response::success(Content::Item(clan.into()))
 }
 
 /// Disband a clan.
 ///
+/// If a grace period is configured, the clan is only marked for deletion,
+/// so that the leader can still recover it with ``cancel_disband``.
+///
 /// - The author needs to:
-///    - Be the owner of the clan
+///    - Be the owner of the clan, unless configured otherwise. See: [`Operation::Disband`]
 #[post("/clan_manager_update/sec/disband_clan")]
 pub async fn disband_clan(database: Data<Database>, req: Request<DisbandClan>) -> Response<()> {
-    let jid = Jid::from(req.request.ticket);
+    let jid = Jid::from(req.request.ticket.clone());
 
-    let clan = match Clan::resolve(req.request.id, &database).await {
+    let mut clan = match Clan::resolve_for(req.request.id, &req.request.ticket, &database).await {
         Ok(clan) => clan,
         Err(e) => return Response::error(e),
     };
 
     // Check if the user is allowed to disband the clan
-    let Some(owner) = clan.owner() else {
-        return Response::error(ErrorCode::InternalServerError);
-    };
-
-    if owner.jid != jid {
+    if !Operation::Disband.allows(clan.role_of(&jid)) {
         return Response::error(ErrorCode::PermissionDenied);
     }
 
+    // Give the leader a chance to change their mind, if configured
+    if let Some(grace_period) = Clan::disband_grace_period() {
+        // Disbanding again doesn't extend the grace period
+        if clan.disband_at.is_none() {
+            clan.disband_at = Some(Utc::now() + grace_period);
+
+            if let Err(e) = clan.save(&database).await {
+                return Response::error(e);
+            }
+        }
+
+        return Response::success(Content::Empty);
+    }
+
     // Disband the clan
     if let Err(e) = clan.delete(&database).await {
         return Response::error(e);
@@ -284,6 +464,46 @@ pub async fn disband_clan(database: Data<Database>, req: Request<DisbandClan>) -
     Response::success(Content::Empty)
 }
 
+/// Cancel the disband of a clan, while it can still be recovered.
+///
+/// This is not part of the game's API.
+///
+/// - The author needs to:
+///    - Be the owner of the clan, unless configured otherwise. See: [`Operation::Disband`]
+///
+/// - The clan needs to:
+///    - Have been disbanded, within the grace period
+#[post("/clan_manager_update/sec/cancel_disband")]
+pub async fn cancel_disband(database: Data<Database>, req: Request<CancelDisband>) -> Response<()> {
+    let jid = Jid::from(req.request.ticket.clone());
+
+    let mut clan = match Clan::resolve_for(req.request.id, &req.request.ticket, &database).await {
+        Ok(clan) => clan,
+        Err(e) => return Response::error(e),
+    };
+
+    // Check if the user is allowed to recover the clan
+    if !Operation::Disband.allows(clan.role_of(&jid)) {
+        return Response::error(ErrorCode::PermissionDenied);
+    }
+
+    // Check if the clan has been disbanded
+    if clan.disband_at.is_none() {
+        return Response::error(ErrorCode::BadRequest);
+    }
+
+    clan.disband_at = None;
+
+    // Record the author's activity
+    clan.touch(&jid);
+
+    if let Err(e) = clan.save(&database).await {
+        return Response::error(e);
+    }
+
+    Response::success(Content::Empty)
+}
+
 /// Update a member's information in a clan.
 ///
 /// - The author needs to:
@@ -293,23 +513,23 @@ pub async fn update_clan_info(
     database: Data<Database>,
     req: Request<UpdateClanInfo>,
 ) -> Response<()> {
-    let mut clan = match Clan::resolve(req.request.id, &database).await {
+    let mut clan = match Clan::resolve_active(req.request.id, &req.request.ticket, &database).await {
         Ok(clan) => clan,
         Err(e) => return Response::error(e),
     };
 
+    let author = Jid::from(req.request.ticket);
+
     // Check if the user is allowed to update the clan's info
-    if !(clan.status_of(&Jid::from(req.request.ticket)) == Some(&Status::Member)) {
+    if !(clan.status_of(&author) == Some(&Status::Member)) {
         return Response::error(ErrorCode::PermissionDenied);
     }
 
-    // Update the clan's info, making sure to limit the description's length
-    clan.description = req
-        .request
-        .description
-        .chars()
-        .take(MAX_CLAN_DESCRIPTION_LENGTH)
-        .collect();
+    // Update the clan's info
+    clan.set_description(&req.request.description);
+
+    // Record the author's activity
+    clan.touch(&author);
 
     // Save the updated clan to the database
     if let Err(e) = clan.save(&database).await {
@@ -3,7 +3,14 @@
 use actix_web::{post, web::Data};
 use mongodb::bson::doc;
 
-use crate::{database::Database, structs::{entities::{clan::{Clan, Platform, MAX_CLAN_MEMBERSHIP}, player::{Jid, Player, Role, Status}}, requests::{base::Request, invites::{AcceptInvitation, AcceptMembershipRequest, CancelInvitation, CancelRequestMembership, DeclineInvitation, DeclineMembershipRequest, RequestMembership, SendInvitation}}, responses::{base::{Content, Response}, error::ErrorCode}}};
+use crate::{database::Database, structs::{entities::{clan::{Clan, Platform, MAX_CLAN_MEMBERSHIP}, permission::Operation, player::{Jid, Player, Role, Status}}, requests::{base::Request, invites::{AcceptInvitation, AcceptMembershipRequest, CancelInvitation, CancelRequestMembership, DeclineInvitation, DeclineMembershipRequest, RequestMembership, ResendInvitation, SendInvitation}}, responses::{base::{Content, Response}, error::ErrorCode}}};
+
+/// Let a player know they've been invited to a clan.
+/// 
+/// There's no NP messaging yet, so this only logs the invitation.
+fn notify_invitation(clan: &Clan, jid: &Jid) {
+    log::info!("Player `{jid}` has been invited to clan `{}`", clan.id());
+}
 
 /// Invite a player to a clan.
 /// 
@@ -14,17 +21,18 @@ use crate::{database::Database, structs::{entities::{clan::{Clan, Platform, MAX_
 ///     - Not be a member of the clan
 ///     - Not have been invited to the clan
 ///     - Not be blacklisted
+///     - Not be known to be on another platform
 #[post("/clan_manager_update/sec/send_invitation")]
 pub async fn send_invitation(database: Data<Database>, req: Request<SendInvitation>) -> Response<()> {
-    let jid = Jid::from(req.request.ticket);
+    let jid = Jid::from(req.request.ticket.clone());
 
-    let mut clan = match Clan::resolve(req.request.id, &database).await {
+    let clan = match Clan::resolve_active(req.request.id, &req.request.ticket, &database).await {
         Ok(clan) => clan,
         Err(e) => return Response::error(e),
     };
 
     // Check if the author has permissions to invite the player
-    if clan.role_of(&jid).is_none_or(|role| role < &Role::Member) {
+    if !Operation::Invite.allows(clan.role_of(&jid)) {
         return Response::error(ErrorCode::PermissionDenied);
     }
 
@@ -38,18 +46,65 @@ pub async fn send_invitation(database: Data<Database>, req: Request<SendInvitati
         return Response::error(ErrorCode::Blacklisted);
     }
 
+    // Check if the player is known to be on another platform, as they couldn't accept
+    let filter = doc! {
+        "username": req.request.jid.username.clone(),
+        "domain": req.request.jid.domain.clone(),
+        "region": req.request.jid.region.clone(),
+    };
+
+    let Ok(known) = database.players.find_one(filter).await
+    else { return Response::error(ErrorCode::InternalServerError) };
+
+    if known.is_some() && !clan.accepts_platform(&Platform::from(&req.request.jid)) {
+        return Response::error(ErrorCode::InvalidEnvironment);
+    }
+
     // Invite the player
     let player = Player {
-        jid: req.request.jid,
+        jid: req.request.jid.clone(),
         role: Role::NonMember,
         status: Status::Invited,
         ..Default::default()
     };
 
-    clan.members.push(player);
+    // Add the player atomically, so that a concurrent invitation can't add them twice
+    match clan.push_member(&player, &jid, &database).await {
+        Ok(true) => notify_invitation(&clan, &req.request.jid),
+        Ok(false) => log::info!("Player `{}` was already invited to clan `{}`", req.request.jid, clan.id()),
+        Err(e) => return Response::error(e),
+    }
 
-    // Update the clan
-    if let Err(e) = clan.save(&database).await { return Response::error(e); }
+    Response::success(Content::Empty)
+}
+
+/// Remind a player of an invitation to a clan.
+/// 
+/// The author needs to:
+///     - Be a member of the clan
+/// 
+/// The player needs to:
+///     - Have been invited
+#[post("/clan_manager_update/sec/resend_invitation")]
+pub async fn resend_invitation(database: Data<Database>, req: Request<ResendInvitation>) -> Response<()> {
+    let jid = Jid::from(req.request.ticket.clone());
+
+    let clan = match Clan::resolve_active(req.request.id, &req.request.ticket, &database).await {
+        Ok(clan) => clan,
+        Err(e) => return Response::error(e),
+    };
+
+    // Check if the author has permissions to invite the player
+    if !Operation::Invite.allows(clan.role_of(&jid)) {
+        return Response::error(ErrorCode::PermissionDenied);
+    }
+
+    // Check if the user has been invited
+    if !(clan.status_of(&req.request.jid) == Some(&Status::Invited)) {
+        return Response::error(ErrorCode::MemberStatusInvalid);
+    }
+
+    notify_invitation(&clan, &req.request.jid);
 
     Response::success(Content::Empty)
 }
@@ -63,15 +118,15 @@ pub async fn send_invitation(database: Data<Database>, req: Request<SendInvitati
 ///     - Have been invited
 #[post("/clan_manager_update/sec/cancel_invitation")]
 pub async fn cancel_invitation(database: Data<Database>, req: Request<CancelInvitation>) -> Response<()> {
-    let jid = Jid::from(req.request.ticket);
+    let jid = Jid::from(req.request.ticket.clone());
 
-    let mut clan = match Clan::resolve(req.request.id, &database).await {
+    let mut clan = match Clan::resolve_active(req.request.id, &req.request.ticket, &database).await {
         Ok(clan) => clan,
         Err(e) => return Response::error(e),
     };
 
     // Check if the author has permissions to cancel the invitation
-    if clan.role_of(&jid).is_none_or(|role| role < &Role::Member) {
+    if !Operation::Invite.allows(clan.role_of(&jid)) {
         return Response::error(ErrorCode::PermissionDenied);
     }
 
@@ -83,6 +138,9 @@ pub async fn cancel_invitation(database: Data<Database>, req: Request<CancelInvi
     // Cancel the invitation
     clan.members.retain(|p| p.jid != req.request.jid);
 
+    // Record the author's activity
+    clan.touch(&jid);
+
     // Update the clan
     if let Err(e) = clan.save(&database).await { return Response::error(e); }
 
@@ -98,9 +156,9 @@ pub async fn cancel_invitation(database: Data<Database>, req: Request<CancelInvi
 #[post("/clan_manager_update/sec/accept_invitation")]
 pub async fn accept_invitation(database: Data<Database>, req: Request<AcceptInvitation>) -> Response<()> {
     let jid = Jid::from(req.request.ticket.clone());
-    let platform = Platform::from(req.request.ticket);
+    let platform = Platform::from(req.request.ticket.clone());
 
-    let mut clan = match Clan::resolve(req.request.id, &database).await {
+    let mut clan = match Clan::resolve_active(req.request.id, &req.request.ticket, &database).await {
         Ok(clan) => clan,
         Err(e) => return Response::error(e),
     };
@@ -115,11 +173,16 @@ pub async fn accept_invitation(database: Data<Database>, req: Request<AcceptInvi
         return Response::error(ErrorCode::MemberStatusInvalid);
     }
 
-    // Check if the clan was created for the same platform as the player
-    if clan.platform != platform {
+    // Check if the clan accepts players of the same platform as the player
+    if !clan.accepts_platform(&platform) {
         return Response::error(ErrorCode::InvalidEnvironment);
     }
 
+    // Check if the clan has room for the player
+    if clan.is_full() {
+        return Response::error(ErrorCode::ClanMemberLimitReached);
+    }
+
     // Check if the player is in too many clans
     let Ok(clans) = jid.clans(database.clone()).await
     else { return Response::error(ErrorCode::InternalServerError) };
@@ -131,10 +194,20 @@ pub async fn accept_invitation(database: Data<Database>, req: Request<AcceptInvi
         return Response::error(ErrorCode::ClanJoinedLimitReached);
     }
 
+    // Check if the player already belongs to a clan on the same platform, if they can only be in one
+    if Clan::already_in_clan_on(&jid, &platform, &clans) {
+        return Response::error(ErrorCode::ClanJoinedLimitReached);
+    }
+
     // Accept the invitation
+    let allow_msg = clan.default_allow_msg;
     let player = clan.members.iter_mut().find(|p| p.jid == jid).unwrap();
     player.status = Status::Member;
     player.role = Role::Member;
+    player.allow_msg = allow_msg;
+
+    // Record the author's activity
+    clan.touch(&jid);
 
     // Update the clan
     if let Err(e) = clan.save(&database).await { return Response::error(e); }
@@ -148,9 +221,9 @@ pub async fn accept_invitation(database: Data<Database>, req: Request<AcceptInvi
 ///     - Have been invited
 #[post("/clan_manager_update/sec/decline_invitation")]
 pub async fn decline_invitation(database: Data<Database>, req: Request<DeclineInvitation>) -> Response<()> {
-    let jid = Jid::from(req.request.ticket);
+    let jid = Jid::from(req.request.ticket.clone());
 
-    let mut clan = match Clan::resolve(req.request.id, &database).await {
+    let mut clan = match Clan::resolve_active(req.request.id, &req.request.ticket, &database).await {
         Ok(clan) => clan,
         Err(e) => return Response::error(e),
     };
@@ -175,12 +248,17 @@ pub async fn decline_invitation(database: Data<Database>, req: Request<DeclineIn
 ///     - Not be a member of the clan
 ///     - Not have requested to join
 ///     - Not be blacklisted
+///     - Not be waiting to join too many clans
+///     - Not have left the clan too recently
+/// 
+/// The clan needs to:
+///     - Not have too many pending requests
 #[post("/clan_manager_update/sec/request_membership")]
 pub async fn request_membership(database: Data<Database>, req: Request<RequestMembership>) -> Response<()> {
     let jid = Jid::from(req.request.ticket.clone());
-    let platform = Platform::from(req.request.ticket);
+    let platform = Platform::from(req.request.ticket.clone());
 
-    let mut clan = match Clan::resolve(req.request.id, &database).await {
+    let mut clan = match Clan::resolve_active(req.request.id, &req.request.ticket, &database).await {
         Ok(clan) => clan,
         Err(e) => return Response::error(e),
     };
@@ -195,8 +273,13 @@ pub async fn request_membership(database: Data<Database>, req: Request<RequestMe
         return Response::error(ErrorCode::Blacklisted);
     }
 
-    // Check if the clan was created for the same platform as the player
-    if clan.platform != platform {
+    // Check if the player has left the clan too recently
+    if clan.left_recently(&jid) {
+        return Response::error(ErrorCode::MemberStatusInvalid);
+    }
+
+    // Check if the clan accepts players of the same platform as the player
+    if !clan.accepts_platform(&platform) {
         return Response::error(ErrorCode::InvalidEnvironment);
     }
 
@@ -211,19 +294,43 @@ pub async fn request_membership(database: Data<Database>, req: Request<RequestMe
         return Response::error(ErrorCode::ClanJoinedLimitReached);
     }
 
+    // Check if the player already belongs to a clan on the same platform, if they can only be in one
+    if Clan::already_in_clan_on(&jid, &platform, &clans) {
+        return Response::error(ErrorCode::ClanJoinedLimitReached);
+    }
+
     // Determine the player's role and status based on the clan's auto-accept setting
     let (role, status) = if clan.auto_accept { (Role::Member, Status::Member) } else { (Role::NonMember, Status::Pending) };
 
+    // Check if the clan has room for the player, if they'd join right away
+    if status == Status::Member && clan.is_full() {
+        return Response::error(ErrorCode::ClanMemberLimitReached);
+    }
+
+    // Check if the clan has too many requests waiting to be processed
+    if status == Status::Pending && clan.has_too_many_requests() {
+        return Response::error(ErrorCode::ClanMemberLimitReached);
+    }
+
+    // Check if the player is waiting to join too many clans
+    if status == Status::Pending && Clan::has_too_many_requests_from(&jid, &clans) {
+        return Response::error(ErrorCode::ClanJoinedLimitReached);
+    }
+
     // Request membership
     let player = Player {
-        jid,
+        jid: jid.clone(),
         role,
         status,
+        allow_msg: clan.default_allow_msg,
         ..Default::default()
     };
 
     clan.members.push(player);
 
+    // Record the author's activity
+    clan.touch(&jid);
+
     // Update the clan
     if let Err(e) = clan.save(&database).await { return Response::error(e); }
 
@@ -236,9 +343,9 @@ pub async fn request_membership(database: Data<Database>, req: Request<RequestMe
 ///     - Be a pending member
 #[post("/clan_manager_update/sec/cancel_request_membership")]
 pub async fn cancel_request_membership(database: Data<Database>, req: Request<CancelRequestMembership>) -> Response<()> {
-    let jid = Jid::from(req.request.ticket);
+    let jid = Jid::from(req.request.ticket.clone());
 
-    let mut clan = match Clan::resolve(req.request.id, &database).await {
+    let mut clan = match Clan::resolve_active(req.request.id, &req.request.ticket, &database).await {
         Ok(clan) => clan,
         Err(e) => return Response::error(e),
     };
@@ -269,13 +376,13 @@ pub async fn cancel_request_membership(database: Data<Database>, req: Request<Ca
 pub async fn accept_membership_request(database: Data<Database>, req: Request<AcceptMembershipRequest>) -> Response<()> {
     let jid = Jid::from(req.request.ticket.clone());
 
-    let mut clan = match Clan::resolve(req.request.id, &database).await {
+    let mut clan = match Clan::resolve_active(req.request.id, &req.request.ticket, &database).await {
         Ok(clan) => clan,
         Err(e) => return Response::error(e),
     };
 
     // Check if the author has permissions to accept the player
-    if clan.role_of(&jid).is_none_or(|role| role < &Role::Member) {
+    if !Operation::ManageRequests.allows(clan.role_of(&jid)) {
         return Response::error(ErrorCode::PermissionDenied);
     }
 
@@ -289,10 +396,20 @@ pub async fn accept_membership_request(database: Data<Database>, req: Request<Ac
         return Response::error(ErrorCode::MemberStatusInvalid);
     }
 
+    // Check if the clan has room for the player
+    if clan.is_full() {
+        return Response::error(ErrorCode::ClanMemberLimitReached);
+    }
+
     // Accept the request
+    let allow_msg = clan.default_allow_msg;
     let player = clan.members.iter_mut().find(|p| p.jid == req.request.jid).unwrap();
     player.status = Status::Member;
     player.role = Role::Member;
+    player.allow_msg = allow_msg;
+
+    // Record the author's activity
+    clan.touch(&jid);
 
     // Update the clan
     if let Err(e) = clan.save(&database).await { return Response::error(e); }
@@ -303,15 +420,15 @@ pub async fn accept_membership_request(database: Data<Database>, req: Request<Ac
 /// Decline a player's request to join a clan.
 #[post("/clan_manager_update/sec/decline_membership_request")]
 pub async fn decline_membership_request(database: Data<Database>, req: Request<DeclineMembershipRequest>) -> Response<()> {
-    let jid = Jid::from(req.request.ticket);
+    let jid = Jid::from(req.request.ticket.clone());
 
-    let mut clan = match Clan::resolve(req.request.id, &database).await {
+    let mut clan = match Clan::resolve_active(req.request.id, &req.request.ticket, &database).await {
         Ok(clan) => clan,
         Err(e) => return Response::error(e),
     };
 
     // Check if the author has permissions to decline the player
-    if clan.role_of(&jid).is_none_or(|role| role < &Role::Member) {
+    if !Operation::ManageRequests.allows(clan.role_of(&jid)) {
         return Response::error(ErrorCode::PermissionDenied);
     }
 
@@ -323,6 +440,9 @@ pub async fn decline_membership_request(database: Data<Database>, req: Request<D
     // Decline the request
     clan.members.retain(|p| p.jid != req.request.jid);
 
+    // Record the author's activity
+    clan.touch(&jid);
+
     // Update the clan
     if let Err(e) = clan.save(&database).await { return Response::error(e); }
 
@@ -6,30 +6,32 @@
 //! - ...
 
 use actix_web::{post, web::Data};
+use chrono::Utc;
 use mongodb::bson::doc;
 
 use crate::{database::Database, structs::{
-    entities::{clan::{Clan, Platform, MAX_CLAN_MEMBERSHIP}, player::{Jid, Player, Role, Status}}, requests::{base::Request, members::{ChangeMemberRole, GetMemberInfo, GetMemberList, JoinClan, KickMember, LeaveClan, UpdateMemberInfo}}, responses::{
+    entities::{audit::{Action, AuditEntry}, clan::{Clan, Platform, MAX_CLAN_MEMBERSHIP}, permission::Operation, player::{Jid, Player, Role, Status}}, requests::{base::Request, members::{ChangeMemberRole, GetMemberInfo, GetMemberList, JoinClan, KickMember, LeaveClan, TransferLeadership, UpdateMemberInfo}}, responses::{
         base::{Content, List, Response},
         entities::{PlayerBasicInfo, PlayerInfo}, error::ErrorCode,
     }
-}};
+}, utils};
 
 /// Get a clan's members.
 #[post("/clan_manager_view/sec/get_member_list")]
 #[allow(clippy::cast_possible_truncation)]
 pub async fn get_member_list(database: Data<Database>, req: Request<GetMemberList>) -> Response<PlayerBasicInfo> {
-    let clan = match Clan::resolve(req.request.id, &database).await {
+    let clan = match Clan::resolve_active(req.request.id, &req.request.ticket, &database).await {
         Ok(clan) => clan,
         Err(e) => return Response::error(e),
     };
 
-    // Collect all valid entries
+    // Collect all valid entries, as they should be presented to the author
+    let author = Jid::from(req.request.ticket);
     let items = clan.members
         .iter()
-        .skip((req.request.start - 1).max(0) as usize)
+        .skip(utils::pagination::skip(req.request.start) as usize)
         .take(req.request.max as usize)
-        .map(|m| PlayerBasicInfo::from(m.to_owned()))
+        .map(|m| PlayerBasicInfo::from(clan.present_member(m, &author)))
         .collect::<Vec<PlayerBasicInfo>>();
 
     let list = List {
@@ -37,6 +39,7 @@ pub async fn get_member_list(database: Data<Database>, req: Request<GetMemberLis
         total: clan.members.len() as u32,
 
         items,
+        next: None,
     };
 
     Response::success(Content::List(list))
@@ -48,9 +51,9 @@ pub async fn get_member_list(database: Data<Database>, req: Request<GetMemberLis
 ///    - Be a member of the clan
 #[post("/clan_manager_view/sec/get_member_info")]
 pub async fn get_member_info(database: Data<Database>, req: Request<GetMemberInfo>) -> Response<PlayerInfo> {
-    let author = Jid::from(req.request.ticket);
+    let author = Jid::from(req.request.ticket.clone());
 
-    let clan = match Clan::resolve(req.request.id, &database).await {
+    let clan = match Clan::resolve_active(req.request.id, &req.request.ticket, &database).await {
         Ok(clan) => clan,
         Err(e) => return Response::error(e),
     };
@@ -67,7 +70,9 @@ pub async fn get_member_info(database: Data<Database>, req: Request<GetMemberInf
     let Some(player) = clan.members.iter().find(|p| p.jid == target)
     else { return Response::error(ErrorCode::NoSuchClanMember) };
 
-    Response::success(Content::Item(PlayerInfo::from(player.to_owned())))
+    let info = PlayerInfo::from(clan.present_member(player, &author)).with_role_name(&clan);
+
+    Response::success(Content::Item(info))
 }
 
 /// Kick a member from a clan.
@@ -79,17 +84,17 @@ pub async fn get_member_info(database: Data<Database>, req: Request<GetMemberInf
 ///     - Not be a `SubLeader` or higher
 #[post("/clan_manager_update/sec/kick_member")]
 pub async fn kick_member(database: Data<Database>, req: Request<KickMember>) -> Response<()> {
-    let author = Jid::from(req.request.ticket);
+    let author = Jid::from(req.request.ticket.clone());
     let Ok(target) = Jid::try_from(req.request.jid.clone())
     else { return Response::error(ErrorCode::InvalidNpId) };
 
-    let mut clan = match Clan::resolve(req.request.id, &database).await {
+    let mut clan = match Clan::resolve_active(req.request.id, &req.request.ticket, &database).await {
         Ok(clan) => clan,
         Err(e) => return Response::error(e),
     };
 
     // Check if the user is allowed to kick the player
-    if clan.role_of(&author).is_none_or(|role| role < &Role::SubLeader) {
+    if !Operation::KickMember.allows(clan.role_of(&author)) {
         return Response::error(ErrorCode::PermissionDenied);
     }
 
@@ -106,6 +111,9 @@ pub async fn kick_member(database: Data<Database>, req: Request<KickMember>) ->
     // Remove the player
     clan.members.retain(|p| p.jid != target);
 
+    // Record the author's activity
+    clan.touch(&author);
+
     // Update the clan
     if let Err(e) = clan.save(&database).await { return Response::error(e); }
 
@@ -119,19 +127,23 @@ pub async fn kick_member(database: Data<Database>, req: Request<KickMember>) ->
 /// 
 /// The player needs to:
 ///     - Be a member of the clan
+/// 
+/// The role needs to:
+///     - Not be `Unknown`
+///     - Not be `Leader`, if the clan already has one
 #[post("/clan_manager_update/sec/change_member_role")]
 pub async fn change_member_role(database: Data<Database>, req: Request<ChangeMemberRole>) -> Response<()> {
-    let author = Jid::from(req.request.ticket);
+    let author = Jid::from(req.request.ticket.clone());
     let Ok(target) = Jid::try_from(req.request.jid.clone())
     else { return Response::error(ErrorCode::InvalidNpId) };
 
-    let mut clan = match Clan::resolve(req.request.id, &database).await {
+    let mut clan = match Clan::resolve_active(req.request.id, &req.request.ticket, &database).await {
         Ok(clan) => clan,
         Err(e) => return Response::error(e),
     };
 
     // Check if the user is allowed to change the player's role
-    if clan.role_of(&author).is_none_or(|role| role < &Role::SubLeader) {
+    if !Operation::ChangeMemberRole.allows(clan.role_of(&author)) {
         return Response::error(ErrorCode::PermissionDenied);
     }
 
@@ -140,14 +152,90 @@ pub async fn change_member_role(database: Data<Database>, req: Request<ChangeMem
         return Response::error(ErrorCode::MemberStatusInvalid);
     }
 
+    // Check if the player can be given the role
+    let role = req.request.role;
+    if let Err(e) = clan.validate_role(&target, role) {
+        return Response::error(e);
+    }
+
     // Change the player's role
-    let role = Role::from(req.request.role);
+    let old_leader = clan.owner().map(|p| p.jid.clone());
     let index = clan.members.iter().position(|p| p.jid == target).unwrap();
     clan.members.get_mut(index).unwrap().role = role;
 
+    // Stamp the clan if the player was made its leader
+    let leadership_changed = role == Role::Leader && old_leader.as_ref() != Some(&target);
+    if leadership_changed {
+        clan.leadership_changed_at = Some(Utc::now());
+    }
+
+    // Record the author's activity
+    clan.touch(&author);
+
+    // Update the clan
+    if let Err(e) = clan.save(&database).await { return Response::error(e); }
+
+    // Keep track of who took over the clan
+    if leadership_changed {
+        let action = Action::LeadershipChanged { old_leader, new_leader: target };
+        AuditEntry::new(clan.id(), Some(author), action).record(&database).await;
+    }
+
+    Response::success(Content::Empty)
+}
+
+/// Hand a clan's leadership to another member.
+///
+/// The author needs to:
+///     - Be the clan's `Leader`
+///
+/// The player needs to:
+///     - Be a member of the clan
+///
+/// The author is demoted to `SubLeader`, so that they can leave the clan afterwards.
+#[post("/clan_manager_update/sec/transfer_leadership")]
+pub async fn transfer_leadership(database: Data<Database>, req: Request<TransferLeadership>) -> Response<()> {
+    let author = Jid::from(req.request.ticket.clone());
+    let Ok(target) = Jid::try_from(req.request.jid.clone())
+    else { return Response::error(ErrorCode::InvalidNpId) };
+
+    let mut clan = match Clan::resolve_active(req.request.id, &req.request.ticket, &database).await {
+        Ok(clan) => clan,
+        Err(e) => return Response::error(e),
+    };
+
+    // Check if the user is the clan's leader
+    if clan.role_of(&author) != Some(&Role::Leader) {
+        return Response::error(ErrorCode::PermissionDenied);
+    }
+
+    // Check if the player is in the clan
+    let Some(status) = clan.status_of(&target)
+    else { return Response::error(ErrorCode::NoSuchClanMember) };
+
+    // Check if the player is a member of the clan
+    if status != &Status::Member {
+        return Response::error(ErrorCode::MemberStatusInvalid);
+    }
+
+    // Check if there's anything to hand over
+    if target == author {
+        return Response::success(Content::Empty);
+    }
+
+    // Swap the roles
+    clan.hand_leadership(&author, &target);
+
+    // Record the author's activity
+    clan.touch(&author);
+
     // Update the clan
     if let Err(e) = clan.save(&database).await { return Response::error(e); }
 
+    // Keep track of who took over the clan
+    let action = Action::LeadershipChanged { old_leader: Some(author.clone()), new_leader: target };
+    AuditEntry::new(clan.id(), Some(author), action).record(&database).await;
+
     Response::success(Content::Empty)
 }
 
@@ -156,11 +244,18 @@ pub async fn change_member_role(database: Data<Database>, req: Request<ChangeMem
 /// The author needs to:
 ///     - Be the player
 ///     - Be a member of the clan
+///     - Not use another member's username, or a reserved marker, as their name
+/// 
+/// The clan needs to:
+///     - Have room for the player's data
+/// 
+/// The player's role is never changed here, even if the game sends one.
+/// See: [`change_member_role`]
 #[post("/clan_manager_update/sec/update_member_info")]
 pub async fn update_member_info(database: Data<Database>, req: Request<UpdateMemberInfo>) -> Response<()> {
-    let author = Jid::from(req.request.ticket);
+    let author = Jid::from(req.request.ticket.clone());
 
-    let mut clan = match Clan::resolve(req.request.id, &database).await {
+    let mut clan = match Clan::resolve_active(req.request.id, &req.request.ticket, &database).await {
         Ok(clan) => clan,
         Err(e) => return Response::error(e),
     };
@@ -170,13 +265,33 @@ pub async fn update_member_info(database: Data<Database>, req: Request<UpdateMem
         return Response::error(ErrorCode::PermissionDenied);
     }
 
+    // Check if the player is passing themselves off as someone else
+    if let Err(e) = utils::validation::impersonation(&req.request.onlinename, &author, &clan.members) {
+        return Response::error(e);
+    }
+
+    // Roles can't be changed here, so say so rather than dropping it silently
+    if req.request.role.is_some() {
+        log::debug!("Ignoring the role sent by `{author}` to update their info");
+    }
+
+    // Check if the player's data matches its declared type
+    let bin_data_type = req.request.bin_data_type.unwrap_or_default();
+    if let Err(e) = bin_data_type.validate(&req.request.bin_attr1) {
+        return Response::error(e);
+    }
+
     // Update the player's info
     let member = clan.members.iter_mut().find(|p| p.jid == author).unwrap();
-    member.online_name = req.request.onlinename;
-    member.description = req.request.description;
-    member.allow_msg = req.request.allowmsg;
-    member.bin_data = req.request.bin_attr1;
-    member.size = req.request.size;
+    req.request.apply_to(member);
+
+    // Check if the members' data fits in the clan's budget
+    if clan.has_too_much_member_data() {
+        return Response::error(ErrorCode::BadRequest);
+    }
+
+    // Record the author's activity
+    clan.touch(&author);
 
     // Update the clan
     if let Err(e) = clan.save(&database).await { return Response::error(e); }
@@ -190,30 +305,22 @@ pub async fn update_member_info(database: Data<Database>, req: Request<UpdateMem
 ///     - Not be a member of a clan
 /// 
 /// The clan needs to:
+///     - Have room for another member
 ///     - Have the ``auto_accept`` attribute set to ``true``.
+///     - Not have been left by the author too recently
 #[post("/clan_manager_update/sec/join_clan")]
 pub async fn join_clan(database: Data<Database>, req: Request<JoinClan>) -> Response<()> {
     let jid = Jid::from(req.request.ticket.clone());
-    let platform = Platform::from(req.request.ticket);
+    let platform = Platform::from(req.request.ticket.clone());
 
-    let mut clan = match Clan::resolve(req.request.id, &database).await {
+    let mut clan = match Clan::resolve_active(req.request.id, &req.request.ticket, &database).await {
         Ok(clan) => clan,
         Err(e) => return Response::error(e),
     };
 
-    // Check if the player is already in a clan
-    if clan.members.iter().any(|p| p.jid == jid) {
-        return Response::error(ErrorCode::MemberStatusInvalid);
-    }
-
-    // Check if the clan accepts new members
-    if !clan.auto_accept {
-        return Response::error(ErrorCode::PermissionDenied);
-    }
-
-    // Check if the clan was created for the player's platform
-    if clan.platform != platform {
-        return Response::error(ErrorCode::InvalidEnvironment);
+    // Check if the clan lets the player join
+    if let Err(e) = clan.validate_join(&jid, &platform) {
+        return Response::error(e);
     }
 
     // Check if the player is in too many clans
@@ -227,10 +334,17 @@ pub async fn join_clan(database: Data<Database>, req: Request<JoinClan>) -> Resp
         return Response::error(ErrorCode::ClanJoinedLimitReached);
     }
 
+    // Check if the player already belongs to a clan on the same platform, if they can only be in one
+    if Clan::already_in_clan_on(&jid, &platform, &clans) {
+        return Response::error(ErrorCode::ClanJoinedLimitReached);
+    }
+
     // Add the player
     clan.members.push(Player {
         jid,
         role: Role::Member,
+        status: Status::Member,
+        allow_msg: clan.default_allow_msg,
         ..Default::default()
     });
 
@@ -244,11 +358,16 @@ pub async fn join_clan(database: Data<Database>, req: Request<JoinClan>) -> Resp
 /// 
 /// The author needs to:
 ///     - Be a member of the clan
+/// 
+/// If the author is leading the clan, a member needs to take over,
+/// as the game breaks on clans without a leader. Either the author hands
+/// the clan over first, or a successor is promoted as they leave.
+/// See: [`transfer_leadership`], [`Clan::leave`]
 #[post("/clan_manager_update/sec/leave_clan")]
 pub async fn leave_clan(database: Data<Database>, req: Request<LeaveClan>) -> Response<()> {
-    let author = Jid::from(req.request.ticket);
+    let author = Jid::from(req.request.ticket.clone());
 
-    let mut clan = match Clan::resolve(req.request.id, &database).await {
+    let mut clan = match Clan::resolve_active(req.request.id, &req.request.ticket, &database).await {
         Ok(clan) => clan,
         Err(e) => return Response::error(e),
     };
@@ -258,11 +377,21 @@ pub async fn leave_clan(database: Data<Database>, req: Request<LeaveClan>) -> Re
         return Response::error(ErrorCode::MemberStatusInvalid);
     }
 
-    // Remove the player
-    clan.members.retain(|p| p.jid != author);
+    // Remove the player, handing the clan over if they were leading it
+    let old_leader = clan.owner().map(|p| p.jid.clone());
+    let successor = match clan.leave(&author) {
+        Ok(successor) => successor,
+        Err(e) => return Response::error(e),
+    };
 
     // Update the clan
     if let Err(e) = clan.save(&database).await { return Response::error(e); }
 
+    // Keep track of who took over the clan
+    if let Some(new_leader) = successor {
+        let action = Action::LeadershipChanged { old_leader, new_leader };
+        AuditEntry::new(clan.id(), None, action).record(&database).await;
+    }
+
     Response::success(Content::Empty)
 }
\ No newline at end of file
@@ -2,9 +2,11 @@
 
 pub mod announcements;
 pub mod blacklist;
+pub mod capabilities;
 pub mod clans;
 pub mod invites;
 pub mod members;
+pub mod stats;
 
 #[cfg(feature = "admin")]
 pub mod admin;
@@ -0,0 +1,99 @@
+//! Routes exposing aggregate stats about the server,
+//! for companion clients such as a status page.
+
+use std::sync::Mutex;
+
+use actix_web::{get, web::Data, Either, HttpResponse};
+use chrono::{DateTime, Utc};
+use futures_util::StreamExt;
+use mongodb::bson::{doc, Bson, Document};
+
+use crate::{database::Database, structs::responses::stats::Stats, utils};
+
+/// Environment variable name for how long stats are cached, in seconds.
+const STATS_CACHE_TTL_ENV: &str = "STATS_CACHE_TTL";
+
+/// Default duration stats are cached for, in seconds.
+/// See: [`STATS_CACHE_TTL_ENV`]
+const DEFAULT_STATS_CACHE_TTL: i64 = 60;
+
+/// The last computed stats, shared between workers so that
+/// the aggregations don't run on every request.
+#[derive(Debug, Default)]
+pub struct StatsCache {
+    /// The stats, along with the date they were computed.
+    entry: Mutex<Option<(DateTime<Utc>, Stats)>>,
+}
+
+/// Read a count from an aggregation result, whatever its integer type.
+fn count(document: &Document, key: &str) -> u64 {
+    match document.get(key) {
+        Some(Bson::Int32(n)) => u64::try_from(*n).unwrap_or_default(),
+        Some(Bson::Int64(n)) => u64::try_from(*n).unwrap_or_default(),
+        _ => 0,
+    }
+}
+
+/// Compute the stats from the database.
+#[allow(clippy::cast_precision_loss)]
+async fn compute(database: &Database) -> Result<Stats, mongodb::error::Error> {
+    let mut stats = Stats {
+        players: database.players.count_documents(doc! {}).await?,
+        ..Default::default()
+    };
+
+    // Count the clans and their members, per platform
+    let pipeline = [doc! { "$group": {
+        "_id": "$platform",
+        "clans": { "$sum": 1 },
+        "members": { "$sum": { "$size": { "$filter": {
+            "input": "$members",
+            "cond": { "$eq": ["$$this.status", "Member"] },
+        } } } },
+    } }];
+
+    let mut members = 0;
+    let mut cursor = database.clans.aggregate(pipeline).await?;
+    while let Some(group) = cursor.next().await {
+        let group = group?;
+        let clans = count(&group, "clans");
+
+        stats.clans += clans;
+        members += count(&group, "members");
+        stats.platforms.insert(group.get_str("_id").unwrap_or_default().to_string(), clans);
+    }
+
+    if stats.clans > 0 {
+        stats.average_members = members as f64 / stats.clans as f64;
+    }
+
+    Ok(stats)
+}
+
+/// Get aggregate stats about the clans on the server.
+#[get("/stats")]
+pub async fn get_stats(
+    database: Data<Database>,
+    cache: Data<StatsCache>,
+) -> Either<Stats, HttpResponse> {
+    let ttl = utils::env::value(STATS_CACHE_TTL_ENV).unwrap_or(DEFAULT_STATS_CACHE_TTL);
+
+    // Serve the cached stats, if they're recent enough
+    if let Some((date, stats)) = cache.entry.lock().unwrap().as_ref() {
+        if Utc::now() - *date < chrono::Duration::seconds(ttl) {
+            return Either::Left(stats.clone());
+        }
+    }
+
+    let stats = match compute(&database).await {
+        Ok(stats) => stats,
+        Err(e) => {
+            log::error!("Failed to compute the stats: {e}");
+            return Either::Right(HttpResponse::InternalServerError().finish());
+        }
+    };
+
+    *cache.entry.lock().unwrap() = Some((Utc::now(), stats.clone()));
+
+    Either::Left(stats)
+}
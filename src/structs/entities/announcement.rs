@@ -4,13 +4,51 @@ use chrono::{DateTime, Utc};
 use rand::Rng;
 use serde::{Deserialize, Serialize};
 
-use crate::structs::requests::announcements::PostAnnouncement;
+use crate::{structs::requests::announcements::PostAnnouncement, utils};
 
-use super::player::Jid;
+use super::{bin_data::BinDataType, player::Jid};
 
 /// Maximum number of announcements that can exist in the game.
 const MAX_ANNOUNCEMENT_COUNT: u32 = 1_000_000;
 
+/// Environment variable name for the maximum number of pinned announcements per clan.
+pub const MAX_PINNED_ANNOUNCEMENTS_ENV: &str = "MAX_PINNED_ANNOUNCEMENTS";
+
+/// Default maximum number of pinned announcements per clan.
+/// See: [`MAX_PINNED_ANNOUNCEMENTS_ENV`]
+pub const DEFAULT_MAX_PINNED_ANNOUNCEMENTS: usize = 3;
+
+/// Environment variable name for showing the author's online
+/// name in announcements, instead of their JID.
+pub const ANNOUNCEMENT_AUTHOR_NAME_ENV: &str = "ANNOUNCEMENT_AUTHOR_NAME";
+
+/// Environment variable name for how long announcements last when
+/// the client doesn't say, in seconds.
+pub const DEFAULT_ANNOUNCEMENT_EXPIRY_ENV: &str = "DEFAULT_ANNOUNCEMENT_EXPIRY";
+
+/// Default duration of an announcement, in seconds (30 days).
+/// See: [`DEFAULT_ANNOUNCEMENT_EXPIRY_ENV`]
+pub const DEFAULT_ANNOUNCEMENT_EXPIRY: u64 = 30 * 24 * 60 * 60;
+
+/// Environment variable name for how long a clan has to wait
+/// between announcements, in seconds.
+///
+/// If unset, clans can post announcements back to back.
+pub const ANNOUNCEMENT_COOLDOWN_ENV: &str = "ANNOUNCEMENT_COOLDOWN";
+
+/// Environment variable name for the maximum number of bytes
+/// of an announcement's ``bin-data``.
+pub const MAX_ANNOUNCEMENT_DATA_SIZE_ENV: &str = "MAX_ANNOUNCEMENT_DATA_SIZE";
+
+/// Default maximum number of bytes of an announcement's ``bin-data``.
+/// See: [`MAX_ANNOUNCEMENT_DATA_SIZE_ENV`]
+pub const DEFAULT_MAX_ANNOUNCEMENT_DATA_SIZE: usize = 1024;
+
+/// The ``from-id`` announcements refer to when they don't refer to another one.
+///
+/// The game breaks on a ``from-id`` of ``0``.
+pub const DEFAULT_FROM_ID: Id = 1;
+
 /// An announcement ID.
 /// 
 /// Should be limited to [`MAX_ANNOUNCEMENT_COUNT`], as the game
@@ -32,6 +70,10 @@ pub struct Announcement {
     /// The player who posted the announcement.
     pub author: Jid,
 
+    /// The author's online name, at the time the announcement was posted.
+    #[serde(default)]
+    pub author_online_name: Option<String>,
+
     /// The date the announcement was posted.
     #[serde(with = "chrono::serde::ts_seconds")]
     pub date_created: DateTime<Utc>,
@@ -43,8 +85,24 @@ pub struct Announcement {
     /// Unknown use.
     pub bin_data: String,
 
+    /// The kind of data held by ``bin_data``, if the client declared it.
+    #[serde(default)]
+    pub bin_data_type: BinDataType,
+
     /// Unknown use.
     pub from_id: Id,
+
+    /// The kind of announcement (e.g. events, rules, news), if any.
+    #[serde(default)]
+    pub category: Option<String>,
+
+    /// The players who have read the announcement.
+    #[serde(default)]
+    pub read_by: Vec<Jid>,
+
+    /// Whether the announcement should be listed before the others.
+    #[serde(default)]
+    pub pinned: bool,
 }
 
 impl Default for Announcement {
@@ -57,10 +115,15 @@ impl Default for Announcement {
             subject: String::new(),
             msg: String::new(),
             author: Jid::default(),
+            author_online_name: None,
             date_created: Utc::now(),
             date_expire: Utc::now(),
             bin_data: String::new(),
-            from_id: 1 // 0 would break the game
+            bin_data_type: BinDataType::Untyped,
+            from_id: DEFAULT_FROM_ID,
+            category: None,
+            read_by: Vec::new(),
+            pinned: false,
         }
     }
 }
@@ -68,11 +131,19 @@ impl Default for Announcement {
 #[allow(clippy::cast_possible_wrap)]
 impl From<PostAnnouncement> for Announcement {
     fn from(request: PostAnnouncement) -> Self {
+        let expire_date = request.expire_date.unwrap_or_else(|| {
+            utils::env::value(DEFAULT_ANNOUNCEMENT_EXPIRY_ENV).unwrap_or(DEFAULT_ANNOUNCEMENT_EXPIRY)
+        });
+
         Self {
             subject: request.subject,
             msg: request.msg,
-            date_expire: Utc::now() + chrono::Duration::seconds(request.expire_date as i64),
+            date_expire: Utc::now() + chrono::Duration::seconds(expire_date as i64),
             author: Jid::from(request.ticket),
+            category: request.category.filter(|category| !category.is_empty()),
+            bin_data: request.bin_data.unwrap_or_default(),
+            bin_data_type: request.bin_data_type.unwrap_or_default(),
+            from_id: request.from_id.unwrap_or(DEFAULT_FROM_ID),
             ..Default::default()
         }
     }
@@ -84,8 +155,172 @@ impl Announcement {
         self.id
     }
 
+    /// Returns the name to show for the author.
+    ///
+    /// This is their online name if [`ANNOUNCEMENT_AUTHOR_NAME_ENV`]
+    /// is enabled and it's known, or their JID otherwise.
+    pub fn author_name(&self) -> String {
+        match &self.author_online_name {
+            Some(name) if utils::env::flag(ANNOUNCEMENT_AUTHOR_NAME_ENV, false) => name.clone(),
+            _ => self.author.to_string(),
+        }
+    }
+
+    /// Returns whether the announcement belongs to the given category.
+    ///
+    /// Without a category, or with an empty one, every announcement belongs to it.
+    pub fn is_in(&self, category: Option<&str>) -> bool {
+        category
+            .filter(|category| !category.is_empty())
+            .is_none_or(|category| self.category.as_deref() == Some(category))
+    }
+
+    /// Record that a player has read the announcement.
+    ///
+    /// Returns whether they hadn't read it before.
+    pub fn mark_read(&mut self, jid: Jid) -> bool {
+        if self.read_by.contains(&jid) {
+            return false;
+        }
+
+        self.read_by.push(jid);
+        true
+    }
+
     /// Returns whether the announcement has expired.
     pub fn has_expired(&self) -> bool {
         self.date_expire < Utc::now()
     }
+
+    /// Returns whether the announcement's ``bin-data`` is over the limit.
+    /// See: [`MAX_ANNOUNCEMENT_DATA_SIZE_ENV`]
+    pub fn has_too_much_data(&self) -> bool {
+        let max = utils::env::value(MAX_ANNOUNCEMENT_DATA_SIZE_ENV).unwrap_or(DEFAULT_MAX_ANNOUNCEMENT_DATA_SIZE);
+
+        self.bin_data.len() > max
+    }
+
+    /// Make the announcement refer to one of the given announcements,
+    /// or to [`DEFAULT_FROM_ID`] if its ``from-id`` isn't among them.
+    pub fn refer_within(&mut self, announcements: &[Self]) {
+        if !announcements.iter().any(|m| m.id() == self.from_id) {
+            self.from_id = DEFAULT_FROM_ID;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    //! Tests for the announcements' bookkeeping.
+
+    use super::{
+        Announcement, Jid, DEFAULT_ANNOUNCEMENT_EXPIRY, DEFAULT_ANNOUNCEMENT_EXPIRY_ENV, DEFAULT_FROM_ID,
+        DEFAULT_MAX_ANNOUNCEMENT_DATA_SIZE, MAX_ANNOUNCEMENT_DATA_SIZE_ENV,
+    };
+    use crate::structs::{requests::announcements::PostAnnouncement, ticket::Ticket};
+
+    /// Post an announcement, expiring after the given number of seconds, if any.
+    fn post(expire_date: Option<u64>) -> Announcement {
+        Announcement::from(PostAnnouncement {
+            ticket: Ticket::default(),
+            id: 1,
+            subject: "Subject".to_string(),
+            msg: "Message".to_string(),
+            expire_date,
+            category: Some(String::new()),
+            bin_data: None,
+            bin_data_type: None,
+            from_id: None,
+        })
+    }
+
+    /// Returns how many seconds an announcement lasts.
+    fn lifetime(announcement: &Announcement) -> i64 {
+        (announcement.date_expire - announcement.date_created).num_seconds()
+    }
+
+    /// Announcements are only filtered by category when one is given.
+    #[test]
+    fn is_in() {
+        let events = Announcement { category: Some("events".to_string()), ..Default::default() };
+        let news = Announcement { category: Some("news".to_string()), ..Default::default() };
+        let uncategorized = Announcement::default();
+
+        let listed = |category: Option<&str>| [&events, &news, &uncategorized].map(|m| m.is_in(category));
+
+        assert_eq!(listed(Some("events")), [true, false, false]);
+        assert_eq!(listed(None), [true, true, true]);
+        assert_eq!(listed(Some("")), [true, true, true]);
+    }
+
+    /// Each reader is only recorded once.
+    #[test]
+    fn mark_read() {
+        let jid = Jid { username: "player".to_string(), domain: "a1".to_string(), region: "us".to_string() };
+        let mut announcement = Announcement::default();
+
+        assert!(announcement.mark_read(jid.clone()));
+        assert!(!announcement.mark_read(jid.clone()));
+        assert_eq!(announcement.read_by.len(), 1);
+        assert_eq!(announcement.read_by[0].to_string(), jid.to_string());
+    }
+
+    /// Announcements last as long as the client says, or the configured default otherwise.
+    ///
+    /// The default is read from the environment, so every case runs in this single test.
+    #[allow(clippy::cast_possible_wrap)]
+    #[test]
+    fn default_expiry() {
+        std::env::remove_var(DEFAULT_ANNOUNCEMENT_EXPIRY_ENV);
+        let default = post(None);
+        std::env::set_var(DEFAULT_ANNOUNCEMENT_EXPIRY_ENV, "60");
+        let configured = post(None);
+        let requested = post(Some(120));
+        std::env::remove_var(DEFAULT_ANNOUNCEMENT_EXPIRY_ENV);
+
+        // Allow for the time passing while the announcement is built
+        assert!(lifetime(&default).abs_diff(DEFAULT_ANNOUNCEMENT_EXPIRY as i64) <= 1);
+        assert!(lifetime(&configured).abs_diff(60) <= 1);
+        assert!(lifetime(&requested).abs_diff(120) <= 1);
+
+        // Optional fields fall back to what the game expects
+        assert_eq!(default.from_id, DEFAULT_FROM_ID);
+        assert_eq!(default.category, None);
+        assert!(default.bin_data.is_empty());
+    }
+
+    /// The ``bin-data`` is limited to the configured number of bytes.
+    ///
+    /// The limit is read from the environment, so every case runs in this single test.
+    #[test]
+    fn has_too_much_data() {
+        let sized = |size: usize| Announcement { bin_data: "a".repeat(size), ..Default::default() };
+
+        std::env::remove_var(MAX_ANNOUNCEMENT_DATA_SIZE_ENV);
+        assert!(!sized(DEFAULT_MAX_ANNOUNCEMENT_DATA_SIZE).has_too_much_data());
+        assert!(sized(DEFAULT_MAX_ANNOUNCEMENT_DATA_SIZE + 1).has_too_much_data());
+
+        std::env::set_var(MAX_ANNOUNCEMENT_DATA_SIZE_ENV, "4");
+        assert!(!sized(4).has_too_much_data());
+        assert!(sized(5).has_too_much_data());
+        std::env::remove_var(MAX_ANNOUNCEMENT_DATA_SIZE_ENV);
+    }
+
+    /// Announcements can only refer to another announcement of the clan.
+    #[test]
+    fn refer_within() {
+        let existing = [Announcement { id: 42, ..Default::default() }];
+
+        let mut reply = Announcement { from_id: 42, ..Default::default() };
+        reply.refer_within(&existing);
+        assert_eq!(reply.from_id, 42);
+
+        let mut dangling = Announcement { from_id: 7, ..Default::default() };
+        dangling.refer_within(&existing);
+        assert_eq!(dangling.from_id, DEFAULT_FROM_ID);
+
+        let mut zero = Announcement { from_id: 0, ..Default::default() };
+        zero.refer_within(&[]);
+        assert_eq!(zero.from_id, DEFAULT_FROM_ID);
+    }
 }
\ No newline at end of file
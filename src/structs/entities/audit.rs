@@ -0,0 +1,116 @@
+//! Represents an entry in the audit log.
+//!
+//! Sensitive changes to a clan, such as its leadership changing hands,
+//! are recorded here so that moderators can hold players accountable.
+
+use actix_web::web::Data;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::database::Database;
+
+use super::{clan::Id, player::Jid};
+
+/// A change made to a clan.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Action {
+    /// The clan's leadership changed hands.
+    LeadershipChanged {
+        /// The previous leader, if the clan had one.
+        old_leader: Option<Jid>,
+
+        /// The new leader.
+        new_leader: Jid,
+    },
+
+    /// The clan was moved to another ID by a moderator.
+    IdReassigned {
+        /// The ID the clan had before.
+        old_id: Id,
+    },
+
+    /// Another clan was merged into the clan by a moderator.
+    ClanMerged {
+        /// The ID of the clan that was merged, and deleted.
+        from: Id,
+    },
+}
+
+/// An entry in the audit log.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditEntry {
+    /// The ID of the clan that was changed.
+    pub clan_id: Id,
+
+    /// The player who made the change, if it wasn't made by the server.
+    pub actor: Option<Jid>,
+
+    /// The change that was made.
+    pub action: Action,
+
+    /// The date the change was made, in UTC.
+    #[serde(with = "chrono::serde::ts_seconds")]
+    pub date: DateTime<Utc>,
+}
+
+impl AuditEntry {
+    /// Create an entry for a change made right now.
+    pub fn new(clan_id: Id, actor: Option<Jid>, action: Action) -> Self {
+        Self {
+            clan_id,
+            actor,
+            action,
+            date: Utc::now(),
+        }
+    }
+
+    /// Write the entry to the audit log.
+    ///
+    /// The change has already been made at this point,
+    /// so failures are logged rather than returned.
+    pub async fn record(self, database: &Data<Database>) {
+        if let Err(e) = database.audit_log.insert_one(&self).await {
+            log::error!("Failed to record audit entry {self:?}: {e}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    //! Tests for the shape of the audit log.
+
+    use super::{Action, AuditEntry, Jid};
+
+    /// Build a console player's JID.
+    fn jid(username: &str) -> Jid {
+        Jid { username: username.to_string(), domain: "a1".to_string(), region: "us".to_string() }
+    }
+
+    /// Entries are stored with their action's type next to its details,
+    /// so that moderators can query them.
+    #[test]
+    fn leadership_changed() {
+        let action = Action::LeadershipChanged { old_leader: Some(jid("old")), new_leader: jid("new") };
+        let entry = AuditEntry::new(42, Some(jid("old")), action);
+
+        let document = mongodb::bson::to_document(&entry).unwrap();
+        let action = document.get_document("action").unwrap();
+
+        assert_eq!(document.get_i64("clan_id"), Ok(42));
+        assert_eq!(action.get_str("type"), Ok("leadership_changed"));
+        assert_eq!(action.get_str("old_leader"), Ok("old@a1.us.np.playstation.net"));
+        assert_eq!(action.get_str("new_leader"), Ok("new@a1.us.np.playstation.net"));
+        assert!(document.get_i64("date").is_ok());
+    }
+
+    /// Leaderless clans get a new leader without a previous one.
+    #[test]
+    fn leadership_changed_without_leader() {
+        let action = Action::LeadershipChanged { old_leader: None, new_leader: jid("new") };
+        let document = mongodb::bson::to_document(&AuditEntry::new(42, None, action)).unwrap();
+
+        assert!(document.is_null("actor"));
+        assert!(document.get_document("action").unwrap().is_null("old_leader"));
+    }
+}
@@ -0,0 +1,76 @@
+//! Represents the kind of data held by the ``bin_data`` fields.
+//!
+//! The game's use of these fields is unknown, so they're opaque strings
+//! by default. Clients that embed structured data can declare its type,
+//! so that the server checks the payload before storing it.
+
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+
+use crate::structs::responses::error::ErrorCode;
+
+/// The kind of data a ``bin_data`` field holds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BinDataType {
+    /// Any string, as the game sends it.
+    #[default]
+    Untyped,
+
+    /// Standard base64, with padding.
+    Base64,
+
+    /// Pairs of hexadecimal digits.
+    Hex,
+}
+
+impl BinDataType {
+    /// Make sure `data` is a valid payload of this type.
+    pub fn validate(self, data: &str) -> Result<(), ErrorCode> {
+        let valid = match self {
+            Self::Untyped => true,
+            Self::Base64 => base64::engine::general_purpose::STANDARD.decode(data).is_ok(),
+            Self::Hex => data.len() % 2 == 0 && data.chars().all(|c| c.is_ascii_hexdigit()),
+        };
+
+        if !valid {
+            return Err(ErrorCode::BadRequest);
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    //! Tests for checking payloads against their declared type.
+
+    use super::BinDataType;
+    use crate::structs::responses::error::ErrorCode;
+
+    /// Untyped data is taken as it is.
+    #[test]
+    fn untyped() {
+        for data in ["", "anything", "é!"] {
+            assert_eq!(BinDataType::Untyped.validate(data), Ok(()));
+        }
+    }
+
+    /// Base64 must be standard and padded.
+    #[test]
+    fn base64() {
+        assert_eq!(BinDataType::Base64.validate(""), Ok(()));
+        assert_eq!(BinDataType::Base64.validate("AAEC/+8="), Ok(()));
+        assert_eq!(BinDataType::Base64.validate("AAEC/+8"), Err(ErrorCode::BadRequest));
+        assert_eq!(BinDataType::Base64.validate("AAEC_-8="), Err(ErrorCode::BadRequest));
+    }
+
+    /// Hex must be whole bytes, in either case.
+    #[test]
+    fn hex() {
+        assert_eq!(BinDataType::Hex.validate(""), Ok(()));
+        assert_eq!(BinDataType::Hex.validate("00aBfF"), Ok(()));
+        assert_eq!(BinDataType::Hex.validate("abc"), Err(ErrorCode::BadRequest));
+        assert_eq!(BinDataType::Hex.validate("0g"), Err(ErrorCode::BadRequest));
+    }
+}
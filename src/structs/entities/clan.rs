@@ -3,24 +3,34 @@
 //!
 //! They are what's stored into the database.
 
-use std::fmt::Display;
+use std::{
+    collections::HashMap,
+    fmt::Display,
+    hash::{DefaultHasher, Hash, Hasher},
+    ops::RangeInclusive,
+    str::FromStr,
+};
 
 use actix_web::web::Data;
 use chrono::{DateTime, Utc};
-use mongodb::bson::doc;
+use mongodb::bson::{doc, Document};
 use rand::Rng;
 use serde::{Deserialize, Serialize};
 
 use crate::{
-    database::Database,
+    database::{duplicate_key_index, Database, CLAN_ID_INDEX, CLAN_NAME_INDEX, CLAN_TAG_INDEX},
     structs::{
-        responses::error::ErrorCode,
-        ticket::{Signature, Ticket},
+        responses::{entities::ClanPlayerInfo, error::ErrorCode},
+        ticket::{Signature, Ticket, DEFAULT_DOMAIN, DEFAULT_REGION},
     },
+    utils,
 };
 
 use super::{
-    announcement::Announcement,
+    announcement::{
+        self, Announcement, ANNOUNCEMENT_COOLDOWN_ENV, DEFAULT_MAX_PINNED_ANNOUNCEMENTS, MAX_PINNED_ANNOUNCEMENTS_ENV,
+    },
+    failed_write::FailedWrite,
     player::{Jid, Player, Role, Status},
 };
 
@@ -42,12 +52,137 @@ pub const MAX_CLAN_DESCRIPTION_LENGTH: usize = 255;
 /// Maximum number of clans someone can own, at the same time.
 pub const MAX_CLAN_OWNERSHIP: usize = 2;
 
+/// Maximum number of players a clan can blacklist.
+pub const MAX_CLAN_BLACKLIST_SIZE: usize = 100;
+
+/// Environment variable name for the maximum number of bytes the
+/// members of a clan can use for their names, descriptions and data.
+pub const MAX_MEMBER_DATA_SIZE_ENV: &str = "MAX_MEMBER_DATA_SIZE";
+
+/// Default maximum number of bytes for the members' data, across a clan.
+/// See: [`MAX_MEMBER_DATA_SIZE_ENV`]
+pub const DEFAULT_MAX_MEMBER_DATA_SIZE: usize = 256 * 1024;
+
+/// Maximum size of a clan's document, in bytes.
+///
+/// ``MongoDB`` rejects documents over 16MB, so this leaves some room to spare.
+const MAX_CLAN_DOCUMENT_SIZE: usize = 15 * 1024 * 1024;
+
+/// Environment variable name for requiring clans created
+/// from the game to be approved by a moderator.
+pub const REQUIRE_CLAN_APPROVAL_ENV: &str = "REQUIRE_CLAN_APPROVAL";
+
+/// Environment variable name for only letting players
+/// be in a single clan per platform.
+pub const SINGLE_CLAN_PER_PLATFORM_ENV: &str = "SINGLE_CLAN_PER_PLATFORM";
+
+/// Environment variable name for only counting the clans on the author's
+/// platform towards [`MAX_CLAN_MEMBERSHIP`], when creating a clan.
+///
+/// Memberships on the other platform are hidden from the game, so this lets
+/// a player who's in too many Console clans still create an Emulator one.
+pub const PER_PLATFORM_MEMBERSHIP_LIMIT_ENV: &str = "PER_PLATFORM_MEMBERSHIP_LIMIT";
+
+/// Environment variable name for how long players have to wait
+/// before joining a clan they've left again, in seconds.
+///
+/// If unset, players can re-join right away.
+pub const REJOIN_COOLDOWN_ENV: &str = "REJOIN_COOLDOWN";
+
+/// Environment variable name for how long a disbanded clan can still
+/// be recovered by its leader, in seconds.
+///
+/// If unset, clans are deleted as soon as they're disbanded.
+pub const DISBAND_GRACE_PERIOD_ENV: &str = "DISBAND_GRACE_PERIOD";
+
+/// Environment variable name for the maximum number of members in a clan.
+///
+/// If unset, clans can have any number of members.
+pub const MAX_CLAN_MEMBERS_ENV: &str = "MAX_CLAN_MEMBERS";
+
+/// Environment variable name for the flag that scopes clans by game title,
+/// so that several titles can be served without sharing their clans.
+///
+/// The title is the ``service_id`` of the player's ticket. Clan names and tags,
+/// as well as the number of clans a player can be in, still span every title.
+/// See: [`Clan::title_of`]
+pub const MULTIPLE_TITLES_ENV: &str = "MULTIPLE_TITLES";
+
+/// Environment variable name for the ``service_id`` of the title that
+/// existing clans belong to, e.g. ``PlayStation Home``'s.
+///
+/// Clans of this title are stored without one, so they're kept visible
+/// when serving multiple titles. See: [`MULTIPLE_TITLES_ENV`]
+pub const DEFAULT_TITLE_ENV: &str = "DEFAULT_TITLE";
+
+/// Environment variable name for the maximum number of
+/// pending requests to join a clan, at the same time.
+pub const MAX_PENDING_REQUESTS_ENV: &str = "MAX_PENDING_REQUESTS";
+
+/// Default maximum number of pending requests to join a clan.
+/// See: [`MAX_PENDING_REQUESTS_ENV`]
+pub const DEFAULT_MAX_PENDING_REQUESTS: usize = 50;
+
+/// Environment variable name for the maximum number of clans
+/// a player can be waiting to join, at the same time.
+pub const MAX_PLAYER_PENDING_REQUESTS_ENV: &str = "MAX_PLAYER_PENDING_REQUESTS";
+
+/// Default maximum number of clans a player can be waiting to join.
+/// See: [`MAX_PLAYER_PENDING_REQUESTS_ENV`]
+pub const DEFAULT_MAX_PLAYER_PENDING_REQUESTS: usize = 10;
+
+/// Environment variable name for how a new leader is picked
+/// when the leader leaves, so that clans aren't left without one.
+/// See: [`Succession`]
+///
+/// If unset, nobody is promoted.
+pub const LEADER_SUCCESSION_ENV: &str = "LEADER_SUCCESSION";
+
+/// A rule for picking the member that takes over a clan without a leader.
+///
+/// Ties are broken in favor of the member who joined first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Succession {
+    /// The member with the highest role.
+    HighestRole,
+
+    /// The member who joined first.
+    Oldest,
+
+    /// The member who most recently interacted with the clan.
+    MostActive,
+}
+
+impl FromStr for Succession {
+    type Err = String;
+
+    fn from_str(rule: &str) -> Result<Self, Self::Err> {
+        match rule.to_lowercase().as_str() {
+            "highest_role" => Ok(Self::HighestRole),
+            "oldest" => Ok(Self::Oldest),
+            "most_active" => Ok(Self::MostActive),
+
+            _ => Err(format!(
+                "Unknown succession rule `{rule}`, expected `highest_role`, `oldest` or `most_active`"
+            )),
+        }
+    }
+}
+
 /// A clan ID.
 ///
 /// Should be limited to [`MAX_CLAN_COUNT`], as the game
 /// rejects any ID that surpasses it.
 pub type Id = u32;
 
+/// Range of IDs the game can handle.
+/// See: [`Clan::validate_id`]
+const CLAN_ID_RANGE: RangeInclusive<Id> = 1..=MAX_CLAN_COUNT - 1;
+
+/// How many random IDs to try when creating a clan, before giving up.
+/// See: [`Clan::create`]
+const MAX_ID_ATTEMPTS: usize = 16;
+
 /// A platform the game can be played on.
 #[derive(Debug, Clone, Serialize, PartialEq, Eq, Default)]
 pub enum Platform {
@@ -58,14 +193,15 @@ pub enum Platform {
     Emulator,
 }
 
-#[allow(clippy::match_same_arms)]
-impl From<String> for Platform {
-    fn from(platform: String) -> Self {
+impl FromStr for Platform {
+    type Err = String;
+
+    fn from_str(platform: &str) -> Result<Self, Self::Err> {
         match platform.to_lowercase().as_str() {
-            "emulator" | "rpcs3" | "rpcn" | "pc" => Self::Emulator,
-            "console" | "psn" | "ps3" => Self::Console,
+            "emulator" | "rpcs3" | "rpcn" | "pc" => Ok(Self::Emulator),
+            "console" | "psn" | "ps3" => Ok(Self::Console),
 
-            _ => Self::Console,
+            _ => Err(format!("Unknown platform `{platform}`, expected `Console` or `Emulator`")),
         }
     }
 }
@@ -76,7 +212,29 @@ impl<'a> Deserialize<'a> for Platform {
         D: serde::Deserializer<'a>,
     {
         let platform = String::deserialize(deserializer)?;
-        Ok(Self::from(platform))
+        Self::from_str(&platform).map_err(serde::de::Error::custom)
+    }
+}
+
+impl Platform {
+    /// Derive a platform from a ticket, if there is one,
+    /// falling back to the default platform otherwise.
+    pub const fn from_ticket_or_default(ticket: Option<&Ticket>) -> Self {
+        match ticket {
+            Some(ticket) => match ticket.signature {
+                Signature::Console(_) => Self::Console,
+                Signature::Emulator(_) => Self::Emulator,
+            },
+            None => Self::Console,
+        }
+    }
+
+    /// Returns the name of the platform, as it's stored in the database.
+    pub const fn name(&self) -> &'static str {
+        match self {
+            Self::Console => "Console",
+            Self::Emulator => "Emulator",
+        }
     }
 }
 
@@ -89,13 +247,49 @@ impl Display for Platform {
     }
 }
 
+/// Derive a platform from a player's JID.
+///
+/// RPCN gives every player the same default domain and region,
+/// which no ``PlayStation Network`` account uses.
+impl From<&Jid> for Platform {
+    fn from(jid: &Jid) -> Self {
+        if jid.domain == DEFAULT_DOMAIN && jid.region == DEFAULT_REGION {
+            Self::Emulator
+        } else {
+            Self::Console
+        }
+    }
+}
+
+#[cfg(feature = "admin")]
+impl Platform {
+    /// Build a filter for the ``players`` collection, matching
+    /// the accounts with the given username on this platform.
+    ///
+    /// Console accounts may share either the domain or the region of
+    /// RPCN's defaults, just not both. See: [`Platform::from`]
+    pub fn player_filter(&self, username: &str) -> Document {
+        match self {
+            Self::Console => doc! {
+                "username": username,
+                "$or": [
+                    { "domain": { "$ne": DEFAULT_DOMAIN } },
+                    { "region": { "$ne": DEFAULT_REGION } },
+                ],
+            },
+            Self::Emulator => doc! {
+                "username": username,
+                "domain": DEFAULT_DOMAIN,
+                "region": DEFAULT_REGION,
+            },
+        }
+    }
+}
+
 /// Derive a platform from a ticket.
 impl From<Ticket> for Platform {
     fn from(ticket: Ticket) -> Self {
-        match ticket.signature {
-            Signature::Console(_) => Self::Console,
-            Signature::Emulator(_) => Self::Emulator,
-        }
+        Self::from_ticket_or_default(Some(&ticket))
     }
 }
 
@@ -163,15 +357,91 @@ pub struct Clan {
     /// Members of the opposite platform should NOT be allowed to join it,
     /// as the game will crash trying to fetch the clan leader.
     pub platform: Platform,
+
+    /// If this flag is `true`, the clan is waiting for a moderator's
+    /// approval, and is hidden from clan searches and lists.
+    ///
+    /// See: [`REQUIRE_CLAN_APPROVAL_ENV`]
+    #[serde(default)]
+    pub pending_approval: bool,
+
+    /// The ``allowMsg`` flag given to players when they become members.
+    ///
+    /// Members can still change their own flag afterwards.
+    #[serde(default)]
+    pub default_allow_msg: bool,
+
+    /// The last time the clan's leadership changed hands, in UTC.
+    #[serde(default, with = "chrono::serde::ts_seconds_option")]
+    pub leadership_changed_at: Option<DateTime<Utc>>,
+
+    /// Players who have recently left the clan.
+    ///
+    /// Entries are only kept for as long as the
+    /// re-join cooldown lasts. See: [`REJOIN_COOLDOWN_ENV`]
+    #[serde(default)]
+    pub departures: Vec<Departure>,
+
+    /// The date the clan will be deleted, in UTC, if it has been disbanded.
+    ///
+    /// Until then, the leader can still cancel the disband.
+    /// See: [`DISBAND_GRACE_PERIOD_ENV`]
+    #[serde(default, with = "chrono::serde::ts_seconds_option")]
+    pub disband_at: Option<DateTime<Utc>>,
+
+    /// If this flag is `true`, players of every platform can join the clan.
+    ///
+    /// The game crashes fetching a leader from the other platform,
+    /// so the leader is presented to them with a JID on their own platform.
+    /// See: [`Clan::present_member`]
+    #[serde(default)]
+    pub cross_platform: bool,
+
+    /// Custom names the clan displays its roles with, e.g. "Officer" for sub-leaders.
+    ///
+    /// Only the label changes: the game still receives the numeric role.
+    /// See: [`Clan::role_name`]
+    #[serde(default)]
+    pub role_names: HashMap<Role, String>,
+
+    /// The game title the clan belongs to, or `None` for the default title.
+    ///
+    /// Clans are only visible to players of the same title. Filtering on a
+    /// `null` title also matches clans stored before titles were introduced.
+    /// See: [`MULTIPLE_TITLES_ENV`]
+    #[serde(default)]
+    pub title: Option<String>,
+
+    /// The last time the clan was written to the database, in UTC.
+    ///
+    /// This lets other services sync only the clans that changed.
+    #[serde(default, with = "chrono::serde::ts_seconds_option")]
+    pub updated_at: Option<DateTime<Utc>>,
+
+    /// How many times the clan has been written to the database.
+    ///
+    /// Writers can compare it to tell whether the clan changed in the meantime.
+    #[serde(default)]
+    pub version: u64,
+}
+
+/// A player leaving a clan.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Departure {
+    /// The player who left.
+    pub jid: Jid,
+
+    /// The date the player left, in UTC.
+    #[serde(with = "chrono::serde::ts_seconds")]
+    pub date: DateTime<Utc>,
 }
 
 impl Default for Clan {
     fn default() -> Self {
-        let range = 1..MAX_CLAN_COUNT;
         let rng = &mut rand::thread_rng();
 
         Self {
-            id: rng.gen_range(range),
+            id: rng.gen_range(CLAN_ID_RANGE),
             name: String::new(),
             tag: String::new(),
             description: String::new(),
@@ -184,34 +454,390 @@ impl Default for Clan {
             int_attr2: 0,
             int_attr3: 0,
             size: 0,
-            platform: Platform::default(),
+            platform: Platform::from_ticket_or_default(None),
+            pending_approval: false,
+            default_allow_msg: false,
+            leadership_changed_at: None,
+            departures: Vec::new(),
+            disband_at: None,
+            cross_platform: false,
+            role_names: HashMap::new(),
+            title: None,
+            updated_at: None,
+            version: 0,
         }
     }
 }
 
 impl Clan {
+    /// Make sure an ID is in the range the game can handle.
+    ///
+    /// Any other ID can't belong to a clan, so it's reported as
+    /// [`ErrorCode::NoSuchClan`].
+    pub fn validate_id(id: u32) -> Result<Id, ErrorCode> {
+        if !CLAN_ID_RANGE.contains(&id) {
+            return Err(ErrorCode::NoSuchClan);
+        }
+
+        Ok(id)
+    }
+
     /// Fetch the clan from the database.
+    ///
+    /// IDs outside of the valid range are rejected without a look-up.
+    ///
+    /// Disbanded clans whose grace period is over are deleted
+    /// on the spot, and reported as [`ErrorCode::NoSuchClan`].
     pub async fn resolve(id: Id, database: &Data<Database>) -> Result<Self, ErrorCode> {
-        database
+        let id = Self::validate_id(id)?;
+
+        let clan = database
             .clans
             .find_one(doc! { "id": id })
             .await
             .map_err(|_| ErrorCode::InternalServerError)?
-            .ok_or(ErrorCode::NoSuchClan)
+            .ok_or(ErrorCode::NoSuchClan)?;
+
+        if clan.is_disbanded() {
+            clan.delete(database).await?;
+            return Err(ErrorCode::NoSuchClan);
+        }
+
+        Ok(clan)
+    }
+
+    /// Fetch the clan from the database, as seen by the ticket's owner.
+    ///
+    /// Clans of another title are reported as [`ErrorCode::NoSuchClan`].
+    /// See: [`Clan::resolve`]
+    pub async fn resolve_for(id: Id, ticket: &Ticket, database: &Data<Database>) -> Result<Self, ErrorCode> {
+        let clan = Self::resolve(id, database).await?;
+
+        if !clan.belongs_to(ticket) {
+            return Err(ErrorCode::NoSuchClan);
+        }
+
+        Ok(clan)
+    }
+
+    /// Fetch a clan the game can operate on, as seen by the ticket's owner.
+    ///
+    /// Clans that have been disbanded, even if they can still be recovered,
+    /// are reported as [`ErrorCode::NoSuchClan`]. Admin routes use
+    /// [`Clan::resolve`] instead, so they can still reach them.
+    pub async fn resolve_active(id: Id, ticket: &Ticket, database: &Data<Database>) -> Result<Self, ErrorCode> {
+        let clan = Self::resolve_for(id, ticket, database).await?;
+
+        if clan.is_archived() {
+            return Err(ErrorCode::NoSuchClan);
+        }
+
+        Ok(clan)
+    }
+
+    /// Returns the game title of the ticket, as clans are scoped by it.
+    ///
+    /// This is always `None` unless multiple titles are served,
+    /// or if the ticket is for the default title.
+    /// See: [`MULTIPLE_TITLES_ENV`]
+    pub fn title_of(ticket: &Ticket) -> Option<String> {
+        if !utils::env::flag(MULTIPLE_TITLES_ENV, false) || ticket.service_id.is_empty() {
+            return None;
+        }
+
+        let default = std::env::var(DEFAULT_TITLE_ENV).ok();
+        if default.as_deref() == Some(ticket.service_id.as_str()) {
+            return None;
+        }
+
+        Some(ticket.service_id.clone())
+    }
+
+    /// Returns whether the clan belongs to the ticket's game title.
+    pub fn belongs_to(&self, ticket: &Ticket) -> bool {
+        self.title == Self::title_of(ticket)
+    }
+
+    /// Check that the clan's document fits in the database, with some room to spare.
+    /// See: [`MAX_CLAN_DOCUMENT_SIZE`]
+    fn validate_size(&self) -> Result<(), ErrorCode> {
+        let size = mongodb::bson::to_vec(self).map_or(0, |bytes| bytes.len());
+        if size > MAX_CLAN_DOCUMENT_SIZE {
+            log::warn!("Refusing to save clan `{}`, as its document is {size} bytes", self.id);
+            return Err(ErrorCode::BadRequest);
+        }
+
+        Ok(())
     }
 
     /// Save the clan in the database.
     ///
     /// This will replace the clan's document altogether and,
     /// if the clan doesn't exist, it will create a new one.
-    pub async fn save(&self, database: &Data<Database>) -> Result<(), ErrorCode> {
+    ///
+    /// If another clan already uses the same name or tag, this returns
+    /// [`ErrorCode::DuplicatedClanName`] or [`ErrorCode::DuplicatedClanTag`].
+    ///
+    /// If the clan's document would be too large for ``MongoDB`` to store,
+    /// this returns [`ErrorCode::BadRequest`] without attempting the write.
+    ///
+    /// If the write fails for any other reason, the clan is recorded
+    /// in the ``failed_writes`` collection, so the change isn't lost.
+    ///
+    /// See [`Clan::write`] for the bookkeeping done on every write.
+    pub async fn save(&mut self, database: &Data<Database>) -> Result<(), ErrorCode> {
+        // Check the size up-front, as retrying the write would never succeed
+        self.validate_size()?;
+
+        let Err(e) = self.write(database).await else {
+            return Ok(());
+        };
+
+        match duplicate_key_index(&e) {
+            Some(CLAN_NAME_INDEX) => return Err(ErrorCode::DuplicatedClanName),
+            Some(CLAN_TAG_INDEX) => return Err(ErrorCode::DuplicatedClanTag),
+            _ => {}
+        }
+
+        log::error!("Failed to save clan `{}`: {e}", self.id);
+
+        // Keep the attempted state aside, so it can be replayed later
+        let failed_write = FailedWrite::new(self.clone(), &e);
+        if let Err(e) = database.failed_writes.insert_one(&failed_write).await {
+            log::error!("Failed to record the failed write, the change is lost: {e}");
+            log::error!("Lost state: {failed_write:?}");
+        }
+
+        Err(ErrorCode::InternalServerError)
+    }
+
+    /// Insert the clan into the database, as a new clan.
+    ///
+    /// Unlike [`Clan::save`], this never replaces another clan: if the clan's
+    /// random ID is already taken, another one is picked, up to [`MAX_ID_ATTEMPTS`] times.
+    ///
+    /// If every ID the game can handle is taken, or no free one could be found,
+    /// this returns [`ErrorCode::ClanLimitReached`].
+    pub async fn create(&mut self, database: &Data<Database>) -> Result<(), ErrorCode> {
+        let count = database
+            .clans
+            .count_documents(doc! {})
+            .await
+            .map_err(|_| ErrorCode::InternalServerError)?;
+
+        if count >= u64::from(*CLAN_ID_RANGE.end()) {
+            return Err(ErrorCode::ClanLimitReached);
+        }
+
+        for _ in 0..MAX_ID_ATTEMPTS {
+            let clan = self.stamped();
+
+            let Err(e) = database.clans.insert_one(&clan).await else {
+                *self = clan;
+                return Ok(());
+            };
+
+            match duplicate_key_index(&e) {
+                Some(CLAN_ID_INDEX) => self.id = rand::thread_rng().gen_range(CLAN_ID_RANGE),
+                Some(CLAN_NAME_INDEX) => return Err(ErrorCode::DuplicatedClanName),
+                Some(CLAN_TAG_INDEX) => return Err(ErrorCode::DuplicatedClanTag),
+                _ => {
+                    log::error!("Failed to create clan `{}`: {e}", self.id);
+                    return Err(ErrorCode::InternalServerError);
+                }
+            }
+        }
+
+        log::error!("Failed to find a free ID for a new clan after {MAX_ID_ATTEMPTS} attempts");
+        Err(ErrorCode::ClanLimitReached)
+    }
+
+    /// Write the clan to the database, replacing its document altogether.
+    ///
+    /// Unlike [`Clan::save`], failures are returned as they are.
+    ///
+    /// Every write stamps the clan's [`updated_at`](Clan::updated_at) date
+    /// and bumps its [`version`](Clan::version), which are only kept if it succeeds.
+    pub async fn write(&mut self, database: &Database) -> Result<(), mongodb::error::Error> {
+        let clan = self.stamped();
+
         database
             .clans
-            .replace_one(doc! { "id": self.id }, self.clone())
+            .replace_one(doc! { "id": self.id }, &clan)
             .upsert(true) // Create the document if it doesn't exist
+            .await?;
+
+        *self = clan;
+
+        Ok(())
+    }
+
+    /// Returns the clan as it's written to the database,
+    /// with its date stamped and its version bumped.
+    fn stamped(&self) -> Self {
+        Self {
+            updated_at: Some(Utc::now()),
+            version: self.version + 1,
+            ..self.clone()
+        }
+    }
+
+    /// Move the clan to another ID, keeping everything else as it is.
+    ///
+    /// The document is updated in place, so the unique index on ``id`` rejects
+    /// an ID that is already taken, even by a clan created in the meantime.
+    /// IDs outside of the valid range are rejected with [`ErrorCode::BadRequest`],
+    /// and IDs that are taken with [`ErrorCode::Forbidden`].
+    pub async fn reassign_id(&mut self, id: Id, database: &Data<Database>) -> Result<(), ErrorCode> {
+        let id = Self::validate_id(id).map_err(|_| ErrorCode::BadRequest)?;
+
+        let result = database
+            .clans
+            .update_one(
+                doc! { "id": self.id },
+                doc! {
+                    "$set": { "id": id, "updated_at": Utc::now().timestamp() },
+                    "$inc": { "version": 1 },
+                },
+            )
+            .await;
+
+        match result {
+            Ok(result) if result.matched_count == 0 => return Err(ErrorCode::NoSuchClan),
+            Ok(_) => {}
+            Err(e) if duplicate_key_index(&e).is_some() => return Err(ErrorCode::Forbidden),
+            Err(e) => {
+                log::error!("Failed to move clan `{}` to ID `{id}`: {e}", self.id);
+                return Err(ErrorCode::InternalServerError);
+            }
+        }
+
+        // Keep the clan's history attached to it
+        let history = database
+            .audit_log
+            .update_many(doc! { "clan_id": self.id }, doc! { "$set": { "clan_id": id } })
+            .await;
+
+        if let Err(e) = history {
+            log::error!("Failed to move the audit log of clan `{}` to ID `{id}`: {e}", self.id);
+        }
+
+        self.id = id;
+
+        Ok(())
+    }
+
+    /// Add a player to the clan in the database, unless the same JID
+    /// has been added in the meantime, returning whether it was added.
+    ///
+    /// Unlike [`Clan::save`], only the new entry is written, so concurrent
+    /// requests can't both add the same player. The author's activity
+    /// is recorded afterwards, see: [`Clan::record_activity`]
+    pub async fn push_member(
+        &self,
+        player: &Player,
+        author: &Jid,
+        database: &Data<Database>,
+    ) -> Result<bool, ErrorCode> {
+        let (filter, update) = self.push_member_query(player)?;
+
+        let result = database
+            .clans
+            .update_one(filter, update)
             .await
-            .map_err(|_| ErrorCode::InternalServerError)
-            .map(|_| ())
+            .map_err(|_| ErrorCode::InternalServerError)?;
+
+        if result.modified_count == 0 {
+            return Ok(false);
+        }
+
+        self.record_activity(author, database).await;
+
+        Ok(true)
+    }
+
+    /// Returns the filter and the update that add a player to the clan,
+    /// unless the same JID is already in it.
+    ///
+    /// The update only pushes to the members: updating one of them in the
+    /// same operation would conflict with the push, and be rejected.
+    fn push_member_query(&self, player: &Player) -> Result<(Document, Document), ErrorCode> {
+        let member = mongodb::bson::to_bson(player).map_err(|_| ErrorCode::InternalServerError)?;
+
+        let filter = doc! { "id": self.id, "members.jid": { "$ne": player.jid.to_string() } };
+        let update = doc! {
+            "$push": { "members": member },
+            "$set": { "updated_at": Utc::now().timestamp() },
+            "$inc": { "version": 1 },
+        };
+
+        Ok((filter, update))
+    }
+
+    /// Record the activity of a member of the clan in the database.
+    ///
+    /// Failing to do so isn't fatal to the request that caused it,
+    /// so errors are only logged.
+    async fn record_activity(&self, jid: &Jid, database: &Data<Database>) {
+        let result = database
+            .clans
+            .update_one(
+                doc! { "id": self.id },
+                doc! { "$set": { "members.$[author].last_seen": Utc::now().timestamp() } },
+            )
+            .array_filters(vec![doc! {
+                "author.jid": jid.to_string(),
+                "author.status": "Member",
+            }])
+            .await;
+
+        if let Err(e) = result {
+            log::error!("Failed to record the activity of `{jid}` in clan `{}`: {e}", self.id);
+        }
+    }
+
+    /// Add a player to the clan's blacklist in the database, unless they're
+    /// already in it, returning whether they were added.
+    ///
+    /// Like [`Clan::push_member`], only the new entry is written, so the same
+    /// player can't end up in the blacklist twice. The author's activity
+    /// is recorded as part of the same update.
+    pub async fn push_blacklist(
+        &self,
+        jid: &Jid,
+        author: &Jid,
+        database: &Data<Database>,
+    ) -> Result<bool, ErrorCode> {
+        let (filter, update) = self.push_blacklist_query(jid);
+
+        let result = database
+            .clans
+            .update_one(filter, update)
+            .array_filters(vec![doc! {
+                "author.jid": author.to_string(),
+                "author.status": "Member",
+            }])
+            .await
+            .map_err(|_| ErrorCode::InternalServerError)?;
+
+        Ok(result.modified_count > 0)
+    }
+
+    /// Returns the filter and the update that add a player to the clan's blacklist,
+    /// unless they're already in it, recording the activity of the member
+    /// matched by the ``author`` array filter.
+    fn push_blacklist_query(&self, jid: &Jid) -> (Document, Document) {
+        let filter = doc! { "id": self.id, "blacklist": { "$ne": jid.to_string() } };
+        let update = doc! {
+            "$addToSet": { "blacklist": jid.to_string() },
+            "$set": {
+                "members.$[author].last_seen": Utc::now().timestamp(),
+                "updated_at": Utc::now().timestamp(),
+            },
+            "$inc": { "version": 1 },
+        };
+
+        (filter, update)
     }
 
     /// Delete the clan from the database.
@@ -224,6 +850,22 @@ impl Clan {
             .map(|_| ())
     }
 
+    /// Returns a hash of the clan's state, to be used as an ``ETag``.
+    ///
+    /// Players see their own role and status in the clan, so the viewer,
+    /// if any, is part of the hash. See: [`Clan::view_for`]
+    pub fn etag(&self, viewer: Option<(&Jid, &Platform)>) -> String {
+        let mut hasher = DefaultHasher::new();
+        mongodb::bson::to_vec(self).unwrap_or_default().hash(&mut hasher);
+
+        if let Some((jid, platform)) = viewer {
+            let view = self.view_for(jid, platform);
+            (jid.to_string(), view.role, view.status).hash(&mut hasher);
+        }
+
+        format!("{:016x}", hasher.finish())
+    }
+
     /// Returns the clan's ID.
     pub const fn id(&self) -> Id {
         self.id
@@ -254,8 +896,1256 @@ impl Clan {
             .map(|player| &player.status)
     }
 
-    /// Returns whether a player is blacklisted from the clan.
-    pub fn is_blacklisted(&self, jid: &Jid) -> bool {
-        self.blacklist.iter().any(|blacklisted| blacklisted == jid)
+    /// Returns the clan from the perspective of a player on the given platform.
+    ///
+    /// - Members on the clan's platform see their own role and status.
+    /// - Members on another platform are shown as non-members, so that the
+    ///   game doesn't know they're in a clan it couldn't load.
+    /// - Everyone else sees their status as it is, if any.
+    pub fn view_for(&self, jid: &Jid, platform: &Platform) -> ClanPlayerInfo {
+        let mut info = ClanPlayerInfo::from((self.clone(), jid.clone()));
+
+        if self.status_of(jid) == Some(&Status::Member) && !self.accepts_platform(platform) {
+            info.role = Role::NonMember as u32;
+            info.status = Status::Unknown as u32;
+        }
+
+        info
+    }
+
+    /// Returns whether the clan has no room for another pending request.
+    /// See: [`MAX_PENDING_REQUESTS_ENV`]
+    pub fn has_too_many_requests(&self) -> bool {
+        let max = utils::env::value(MAX_PENDING_REQUESTS_ENV).unwrap_or(DEFAULT_MAX_PENDING_REQUESTS);
+
+        self.members.iter().filter(|p| p.status == Status::Pending).count() >= max
+    }
+
+    /// Returns whether the player is already a member of one of the given clans on the platform,
+    /// when players can only be in a single clan per platform.
+    /// See: [`SINGLE_CLAN_PER_PLATFORM_ENV`]
+    pub fn already_in_clan_on(jid: &Jid, platform: &Platform, clans: &[Self]) -> bool {
+        utils::env::flag(SINGLE_CLAN_PER_PLATFORM_ENV, false)
+            && clans.iter().any(|clan| clan.platform == *platform && clan.status_of(jid) == Some(&Status::Member))
+    }
+
+    /// Returns how many of the given clans the player is a member of, counting towards
+    /// [`MAX_CLAN_MEMBERSHIP`] when they create a clan on the platform.
+    /// See: [`PER_PLATFORM_MEMBERSHIP_LIMIT_ENV`]
+    pub fn memberships_on(jid: &Jid, platform: &Platform, clans: &[Self]) -> usize {
+        let per_platform = utils::env::flag(PER_PLATFORM_MEMBERSHIP_LIMIT_ENV, false);
+
+        clans
+            .iter()
+            .filter(|clan| clan.status_of(jid) == Some(&Status::Member))
+            .filter(|clan| !per_platform || clan.accepts_platform(platform))
+            .count()
+    }
+
+    /// Returns whether the player is waiting to join too many of the given clans.
+    /// See: [`MAX_PLAYER_PENDING_REQUESTS_ENV`]
+    pub fn has_too_many_requests_from(jid: &Jid, clans: &[Self]) -> bool {
+        let max = utils::env::value(MAX_PLAYER_PENDING_REQUESTS_ENV).unwrap_or(DEFAULT_MAX_PLAYER_PENDING_REQUESTS);
+
+        clans.iter().filter(|clan| clan.status_of(jid) == Some(&Status::Pending)).count() >= max
+    }
+
+    /// Returns whether the members' data is over the clan's budget.
+    /// See: [`MAX_MEMBER_DATA_SIZE_ENV`]
+    pub fn has_too_much_member_data(&self) -> bool {
+        let max = utils::env::value(MAX_MEMBER_DATA_SIZE_ENV).unwrap_or(DEFAULT_MAX_MEMBER_DATA_SIZE);
+
+        self.members.iter().map(Player::data_size).sum::<usize>() > max
+    }
+
+    /// Returns whether the clan has no room for another member.
+    /// See: [`MAX_CLAN_MEMBERS_ENV`]
+    pub fn is_full(&self) -> bool {
+        let Some(max) = utils::env::value::<usize>(MAX_CLAN_MEMBERS_ENV) else {
+            return false;
+        };
+
+        self.members.iter().filter(|p| p.status == Status::Member).count() >= max
+    }
+
+    /// Set the clan's description, limiting its length.
+    ///
+    /// Trailing whitespace is trimmed first, so it can't be used as padding.
+    /// See: [`MAX_CLAN_DESCRIPTION_LENGTH`]
+    pub fn set_description(&mut self, description: &str) {
+        self.description = description.trim_end().chars().take(MAX_CLAN_DESCRIPTION_LENGTH).collect();
+    }
+
+    /// Returns the name the clan displays a role with,
+    /// falling back to the role's default name.
+    pub fn role_name(&self, role: Role) -> &str {
+        self.role_names.get(&role).map_or_else(|| role.default_name(), String::as_str)
+    }
+
+    /// Returns whether players of the given platform can be members of the clan.
+    pub fn accepts_platform(&self, platform: &Platform) -> bool {
+        self.cross_platform || self.platform == *platform
+    }
+
+    /// Returns a member as it should be presented to the given player.
+    ///
+    /// In cross-platform clans, a leader from another platform is presented
+    /// with the viewer's domain and region, so that the game doesn't crash
+    /// trying to fetch them. Players are matched by their username alone,
+    /// so the stub can still be used to refer to the leader.
+    pub fn present_member(&self, member: &Player, viewer: &Jid) -> Player {
+        let mut member = member.clone();
+
+        if self.cross_platform
+            && member.role == Role::Leader
+            && Platform::from(&member.jid) != Platform::from(viewer)
+        {
+            member.jid.domain.clone_from(&viewer.domain);
+            member.jid.region.clone_from(&viewer.region);
+        }
+
+        member
+    }
+
+    /// Make sure a player can be given a role in the clan.
+    ///
+    /// - [`Role::Unknown`] would hide the player from the member list.
+    /// - A clan can only have a single [`Role::Leader`].
+    pub fn validate_role(&self, target: &Jid, role: Role) -> Result<(), ErrorCode> {
+        match role {
+            Role::Unknown => Err(ErrorCode::InvalidRolePriority),
+            Role::Leader if self.owner().is_some_and(|owner| owner.jid != *target) => {
+                Err(ErrorCode::InvalidRolePriority)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Make sure a player can join the clan without an invitation.
+    ///
+    /// - The player can't be in the clan already, or have left it too recently.
+    /// - The clan needs room for the player, whether it accepts new members or not.
+    /// - The clan needs to accept new members without approval,
+    ///   and players of the player's platform.
+    pub fn validate_join(&self, jid: &Jid, platform: &Platform) -> Result<(), ErrorCode> {
+        if self.members.iter().any(|p| p.jid == *jid) {
+            return Err(ErrorCode::MemberStatusInvalid);
+        }
+
+        if self.is_full() {
+            return Err(ErrorCode::ClanMemberLimitReached);
+        }
+
+        if !self.auto_accept {
+            return Err(ErrorCode::PermissionDenied);
+        }
+
+        if self.left_recently(jid) {
+            return Err(ErrorCode::MemberStatusInvalid);
+        }
+
+        if !self.accepts_platform(platform) {
+            return Err(ErrorCode::InvalidEnvironment);
+        }
+
+        Ok(())
+    }
+
+    /// Make sure the author can add a player to the clan's blacklist,
+    /// given the clans the author is in.
+    ///
+    /// - Nobody can blacklist themselves, the clan's leader, a player
+    ///   that outranks them, or the leader of any clan they lead.
+    /// - Other members need to be kicked first.
+    pub fn validate_blacklist_entry(&self, author: &Jid, target: &Jid, clans: &[Self]) -> Result<(), ErrorCode> {
+        let leads = |clan: &Self, jid: &Jid| clan.role_of(jid) == Some(&Role::Leader);
+
+        if target == author
+            || leads(self, target)
+            || self.role_of(target) > self.role_of(author)
+            || clans.iter().any(|clan| leads(clan, author) && leads(clan, target))
+        {
+            return Err(ErrorCode::PermissionDenied);
+        }
+
+        if self.status_of(target) == Some(&Status::Member) {
+            return Err(ErrorCode::MemberStatusInvalid);
+        }
+
+        Ok(())
+    }
+
+    /// Promote a member to leader if the clan has none, according to the given rule.
+    ///
+    /// Returns the JID of the new leader, if any.
+    fn promote_successor_by(&mut self, rule: Succession) -> Option<Jid> {
+        if self.owner().is_some() {
+            return None;
+        }
+
+        // Members are stored in the order they joined
+        let successor = self
+            .members
+            .iter_mut()
+            .filter(|p| p.status == Status::Member)
+            .reduce(|best, p| {
+                let better = match rule {
+                    Succession::HighestRole => p.role > best.role,
+                    Succession::Oldest => false,
+                    Succession::MostActive => p.last_seen > best.last_seen,
+                };
+
+                if better { p } else { best }
+            })?;
+
+        successor.role = Role::Leader;
+        let jid = successor.jid.clone();
+
+        self.leadership_changed_at = Some(Utc::now());
+
+        Some(jid)
+    }
+
+    /// Remove a member leaving the clan, promoting a successor if they were leading it,
+    /// according to the configured rule. See: [`LEADER_SUCCESSION_ENV`]
+    ///
+    /// Returns the JID of the new leader, if any. Leaders can't leave a clan
+    /// nobody takes over, as the game breaks on clans without a leader.
+    pub fn leave(&mut self, jid: &Jid) -> Result<Option<Jid>, ErrorCode> {
+        self.leave_by(jid, utils::env::value::<Succession>(LEADER_SUCCESSION_ENV))
+    }
+
+    /// Remove a member leaving the clan, promoting a successor by the given rule, if any.
+    ///
+    /// The clan is left as it was if the member can't leave.
+    fn leave_by(&mut self, jid: &Jid, rule: Option<Succession>) -> Result<Option<Jid>, ErrorCode> {
+        let was_leader = self.owner().is_some_and(|leader| leader.jid == *jid);
+
+        let mut clan = self.clone();
+        clan.members.retain(|p| p.jid != *jid);
+        clan.record_departure(jid);
+
+        // Hand the clan over, if the player was leading it
+        let successor = rule.and_then(|rule| clan.promote_successor_by(rule));
+
+        if was_leader && successor.is_none() {
+            return Err(ErrorCode::ClanLeaderCannotLeave);
+        }
+
+        *self = clan;
+        Ok(successor)
+    }
+
+    /// Hand the clan's leadership from one member to another,
+    /// demoting the former leader to `SubLeader`.
+    pub fn hand_leadership(&mut self, from: &Jid, to: &Jid) {
+        for member in &mut self.members {
+            if member.jid == *from {
+                member.role = Role::SubLeader;
+            } else if member.jid == *to {
+                member.role = Role::Leader;
+            }
+        }
+
+        self.leadership_changed_at = Some(Utc::now());
+    }
+
+    /// Add a player to the clan's blacklist, on a moderator's behalf.
+    ///
+    /// Unlike players, moderators can blacklist anyone outside the clan,
+    /// but duplicates are reported so that imports can be reviewed.
+    #[cfg(feature = "admin")]
+    pub fn import_blacklist_entry(&mut self, jid: Jid) -> Result<(), ErrorCode> {
+        if self.is_blacklisted(&jid) {
+            return Err(ErrorCode::CannotRecordBlacklistEntry);
+        }
+
+        if self.status_of(&jid) == Some(&Status::Member) {
+            return Err(ErrorCode::MemberStatusInvalid);
+        }
+
+        if self.blacklist.len() >= MAX_CLAN_BLACKLIST_SIZE {
+            return Err(ErrorCode::BlacklistLimitReached);
+        }
+
+        self.blacklist.push(jid);
+
+        Ok(())
+    }
+
+    /// Move the clan to another platform, given each member's account on it, in order.
+    ///
+    /// If any member has no such account, nothing is changed and they're returned.
+    /// Invitations and requests without one are dropped instead,
+    /// as they couldn't be accepted anyway.
+    #[cfg(feature = "admin")]
+    pub fn migrate(&mut self, platform: Platform, accounts: Vec<Option<Jid>>) -> Result<(), Vec<Jid>> {
+        let offending: Vec<_> = self
+            .members
+            .iter()
+            .zip(&accounts)
+            .filter(|(member, account)| member.status == Status::Member && account.is_none())
+            .map(|(member, _)| member.jid.clone())
+            .collect();
+
+        if !offending.is_empty() {
+            return Err(offending);
+        }
+
+        let members = std::mem::take(&mut self.members);
+        for (mut member, account) in members.into_iter().zip(accounts) {
+            let Some(jid) = account else {
+                log::info!(
+                    "Dropping `{}` from clan `{}`, as they have no account on the target platform",
+                    member.jid,
+                    self.id
+                );
+                continue;
+            };
+
+            member.jid = jid;
+            self.members.push(member);
+        }
+
+        self.platform = platform;
+
+        Ok(())
+    }
+
+    /// Pin or unpin one of the clan's announcements, so that it's listed before the others.
+    ///
+    /// Only so many announcements can be pinned at once.
+    /// See: [`MAX_PINNED_ANNOUNCEMENTS_ENV`]
+    pub fn pin_announcement(&mut self, id: announcement::Id, pinned: bool) -> Result<(), ErrorCode> {
+        let max_pinned = utils::env::value(MAX_PINNED_ANNOUNCEMENTS_ENV).unwrap_or(DEFAULT_MAX_PINNED_ANNOUNCEMENTS);
+        let others = self.announcements.iter().filter(|m| m.pinned && m.id() != id).count();
+
+        if pinned && others >= max_pinned {
+            return Err(ErrorCode::AnnouncementLimitReached);
+        }
+
+        let announcement = self
+            .announcements
+            .iter_mut()
+            .find(|m| m.id() == id)
+            .ok_or(ErrorCode::NoSuchClanAnnouncement)?;
+
+        announcement.pinned = pinned;
+
+        Ok(())
+    }
+
+    /// Delete the clan's announcements posted before a date,
+    /// returning how many were deleted.
+    pub fn delete_announcements_before(&mut self, cutoff: DateTime<Utc>) -> usize {
+        let count = self.announcements.len();
+        self.announcements.retain(|m| m.date_created >= cutoff);
+
+        count - self.announcements.len()
+    }
+
+    /// Record that a member of the clan has just interacted with it.
+    pub fn touch(&mut self, jid: &Jid) {
+        if let Some(member) = self
+            .members
+            .iter_mut()
+            .find(|p| p.jid == *jid && p.status == Status::Member)
+        {
+            member.last_seen = Some(Utc::now());
+        }
+    }
+
+    /// Returns the re-join cooldown, if there is one.
+    fn rejoin_cooldown() -> Option<chrono::Duration> {
+        utils::env::value::<i64>(REJOIN_COOLDOWN_ENV)
+            .filter(|seconds| *seconds > 0)
+            .map(chrono::Duration::seconds)
+    }
+
+    /// Record that a player has left the clan, forgetting
+    /// the departures the cooldown no longer applies to.
+    pub fn record_departure(&mut self, jid: &Jid) {
+        let Some(cooldown) = Self::rejoin_cooldown() else {
+            self.departures.clear();
+            return;
+        };
+
+        let now = Utc::now();
+        self.departures.retain(|d| d.jid != *jid && now - d.date < cooldown);
+        self.departures.push(Departure { jid: jid.clone(), date: now });
+    }
+
+    /// Returns whether a player has left the clan too recently to re-join it.
+    pub fn left_recently(&self, jid: &Jid) -> bool {
+        let Some(cooldown) = Self::rejoin_cooldown() else {
+            return false;
+        };
+
+        let now = Utc::now();
+        self.departures.iter().any(|d| d.jid == *jid && now - d.date < cooldown)
+    }
+
+    /// Returns whether the clan has posted an announcement too recently to post another.
+    /// See: [`ANNOUNCEMENT_COOLDOWN_ENV`]
+    pub fn posted_recently(&self) -> bool {
+        let Some(cooldown) = utils::env::value::<i64>(ANNOUNCEMENT_COOLDOWN_ENV).filter(|seconds| *seconds > 0) else {
+            return false;
+        };
+
+        let now = Utc::now();
+        self.announcements.iter().any(|m| now - m.date_created < chrono::Duration::seconds(cooldown))
+    }
+
+    /// Returns the disband grace period, if there is one.
+    pub fn disband_grace_period() -> Option<chrono::Duration> {
+        utils::env::value::<i64>(DISBAND_GRACE_PERIOD_ENV)
+            .filter(|seconds| *seconds > 0)
+            .map(chrono::Duration::seconds)
+    }
+
+    /// Returns whether the clan has been disbanded, even if it can still be recovered.
+    ///
+    /// Such clans are hidden from the game. See: [`Clan::resolve_active`]
+    pub const fn is_archived(&self) -> bool {
+        self.disband_at.is_some()
+    }
+
+    /// Returns whether the game can see the clan from the given title.
+    /// See: [`Clan::title_of`]
+    pub fn is_shown_in(&self, title: Option<&str>) -> bool {
+        !self.is_archived() && self.title.as_deref() == title
+    }
+
+    /// Returns whether the clan has been disbanded, and can no longer be recovered.
+    pub fn is_disbanded(&self) -> bool {
+        self.disband_at.is_some_and(|date| date <= Utc::now())
+    }
+
+    /// Returns the last time a member interacted with the clan, if ever.
+    pub fn last_seen_of(&self, jid: &Jid) -> Option<DateTime<Utc>> {
+        self.members
+            .iter()
+            .find(|player| player.jid == *jid)
+            .and_then(|player| player.last_seen)
+    }
+
+    /// Returns whether a player is blacklisted from the clan.
+    pub fn is_blacklisted(&self, jid: &Jid) -> bool {
+        self.blacklist.iter().any(|blacklisted| blacklisted == jid)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    //! Tests for the checks a clan makes on its members.
+
+    use chrono::Utc;
+
+    use super::{
+        Announcement, Clan, ErrorCode, Jid, Platform, Player, Role, Signature, Status, Succession, Ticket,
+        DEFAULT_TITLE_ENV, MULTIPLE_TITLES_ENV,
+    };
+
+    /// Build a console player with the given role and status.
+    fn player(username: &str, role: Role, status: Status) -> Player {
+        Player {
+            jid: Jid { username: username.to_string(), domain: "a1".to_string(), region: "us".to_string() },
+            role,
+            status,
+            ..Default::default()
+        }
+    }
+
+    /// Build a clan with a leader, a sub-leader, a member and the given extra players.
+    fn clan(extra: Vec<Player>) -> Clan {
+        let mut members = vec![
+            player("leader", Role::Leader, Status::Member),
+            player("sub", Role::SubLeader, Status::Member),
+            player("member", Role::Member, Status::Member),
+        ];
+        members.extend(extra);
+
+        Clan { members, ..Clan::default() }
+    }
+
+    /// Returns the JID of the player with the given username.
+    fn jid(username: &str) -> Jid {
+        player(username, Role::Unknown, Status::Unknown).jid
+    }
+
+    /// Members on the clan's platform see their own role and status.
+    #[test]
+    fn view_for_same_platform() {
+        let clan = clan(Vec::new());
+        let info = clan.view_for(&jid("sub"), &Platform::Console);
+
+        assert_eq!((info.role, info.status), (Role::SubLeader as u32, Status::Member as u32));
+    }
+
+    /// Members on another platform are shown as non-members, unless the clan is cross-platform.
+    #[test]
+    fn view_for_cross_platform() {
+        let mut clan = clan(Vec::new());
+        let info = clan.view_for(&jid("sub"), &Platform::Emulator);
+
+        assert_eq!((info.role, info.status), (Role::NonMember as u32, Status::Unknown as u32));
+
+        clan.cross_platform = true;
+        let info = clan.view_for(&jid("sub"), &Platform::Emulator);
+
+        assert_eq!((info.role, info.status), (Role::SubLeader as u32, Status::Member as u32));
+    }
+
+    /// Everyone else sees their status as it is, whatever their platform.
+    #[test]
+    fn view_for_non_members() {
+        let clan = clan(vec![player("invited", Role::NonMember, Status::Invited)]);
+
+        for platform in [Platform::Console, Platform::Emulator] {
+            let info = clan.view_for(&jid("invited"), &platform);
+            assert_eq!((info.role, info.status), (Role::NonMember as u32, Status::Invited as u32));
+
+            let info = clan.view_for(&jid("stranger"), &platform);
+            assert_eq!((info.role, info.status), (Role::NonMember as u32, Status::Unknown as u32));
+        }
+    }
+
+    /// Viewers get their own tag, as the response includes their membership.
+    #[test]
+    fn etag_depends_on_viewer() {
+        let clan = clan(Vec::new());
+        let tag = |username: &str| clan.etag(Some((&jid(username), &Platform::Console)));
+
+        assert_eq!(clan.etag(None), clan.etag(None));
+        assert_eq!(tag("member"), tag("member"));
+
+        assert_ne!(clan.etag(None), tag("member"));
+        assert_ne!(tag("member"), tag("sub"));
+        assert_ne!(tag("member"), clan.etag(Some((&jid("member"), &Platform::Emulator))));
+    }
+
+    /// Players outside the clan can be blacklisted.
+    #[test]
+    fn blacklist_outsiders() {
+        let clan = clan(vec![player("invited", Role::NonMember, Status::Invited)]);
+
+        assert_eq!(clan.validate_blacklist_entry(&jid("sub"), &jid("stranger"), &[]), Ok(()));
+        assert_eq!(clan.validate_blacklist_entry(&jid("sub"), &jid("invited"), &[]), Ok(()));
+    }
+
+    /// Members of the same or a lower rank need to be kicked first.
+    #[test]
+    fn blacklist_members() {
+        let clan = clan(Vec::new());
+
+        assert_eq!(clan.validate_blacklist_entry(&jid("leader"), &jid("sub"), &[]), Err(ErrorCode::MemberStatusInvalid));
+        assert_eq!(clan.validate_blacklist_entry(&jid("sub"), &jid("member"), &[]), Err(ErrorCode::MemberStatusInvalid));
+    }
+
+    /// Players can't blacklist themselves, the leader, or a member that outranks them.
+    #[test]
+    fn blacklist_higher_ranks() {
+        let clan = clan(vec![player("other-sub", Role::SubLeader, Status::Member)]);
+
+        assert_eq!(clan.validate_blacklist_entry(&jid("member"), &jid("sub"), &[]), Err(ErrorCode::PermissionDenied));
+        assert_eq!(clan.validate_blacklist_entry(&jid("member"), &jid("leader"), &[]), Err(ErrorCode::PermissionDenied));
+        assert_eq!(clan.validate_blacklist_entry(&jid("sub"), &jid("leader"), &[]), Err(ErrorCode::PermissionDenied));
+        assert_eq!(clan.validate_blacklist_entry(&jid("sub"), &jid("other-sub"), &[]), Err(ErrorCode::MemberStatusInvalid));
+        assert_eq!(clan.validate_blacklist_entry(&jid("leader"), &jid("leader"), &[]), Err(ErrorCode::PermissionDenied));
+        assert_eq!(clan.validate_blacklist_entry(&jid("stranger"), &jid("stranger"), &[]), Err(ErrorCode::PermissionDenied));
+    }
+
+    /// Players can't blacklist the leader of any clan they lead themselves.
+    #[test]
+    fn blacklist_leaders_of_other_clans() {
+        let clan = clan(Vec::new());
+        let other = Clan {
+            members: vec![
+                player("sub", Role::Leader, Status::Member),
+                player("stranger", Role::Leader, Status::Member),
+            ],
+            ..Clan::default()
+        };
+        let unrelated = Clan { members: vec![player("stranger", Role::Leader, Status::Member)], ..Clan::default() };
+
+        let clans = [clan.clone(), other];
+        assert_eq!(clan.validate_blacklist_entry(&jid("sub"), &jid("stranger"), &clans), Err(ErrorCode::PermissionDenied));
+
+        let clans = [clan.clone(), unrelated];
+        assert_eq!(clan.validate_blacklist_entry(&jid("sub"), &jid("stranger"), &clans), Ok(()));
+    }
+
+    /// A player is only pushed if their JID isn't in the clan yet, so of two
+    /// concurrent invitations, the second one matches nothing and adds no entry.
+    ///
+    /// Running them against a database is out of scope for unit tests,
+    /// so this checks the query they send instead.
+    #[test]
+    fn push_member_query() {
+        let clan = clan(Vec::new());
+        let (filter, update) = clan.push_member_query(&player("new", Role::NonMember, Status::Invited)).unwrap();
+
+        assert_eq!(filter.get_document("members.jid").unwrap().get_str("$ne"), Ok("new@a1.us.np.playstation.net"));
+        assert!(update.contains_key("$push"));
+        assert!(update.get_document("$set").unwrap().keys().all(|key| !key.starts_with("members")));
+        assert!(update.get_document("$set").unwrap().contains_key("updated_at"));
+        assert_eq!(update.get_document("$inc").unwrap().get_i32("version"), Ok(1));
+    }
+
+    /// Players can only join clans that have room for them and accept them without approval.
+    ///
+    /// The member limit is read from the environment, so every case
+    /// runs in this single test.
+    #[test]
+    fn validate_join() {
+        let open = Clan { auto_accept: true, ..clan(Vec::new()) };
+        let closed = clan(Vec::new());
+
+        assert_eq!(open.validate_join(&jid("new"), &Platform::Console), Ok(()));
+        assert_eq!(open.validate_join(&jid("member"), &Platform::Console), Err(ErrorCode::MemberStatusInvalid));
+        assert_eq!(closed.validate_join(&jid("new"), &Platform::Console), Err(ErrorCode::PermissionDenied));
+
+        std::env::set_var(super::MAX_CLAN_MEMBERS_ENV, "3");
+        let full = open.validate_join(&jid("new"), &Platform::Console);
+        std::env::set_var(super::MAX_CLAN_MEMBERS_ENV, "4");
+        let invited = Clan { auto_accept: true, ..clan(vec![player("invited", Role::NonMember, Status::Invited)]) }
+            .validate_join(&jid("new"), &Platform::Console);
+        let closed_with_room = closed.validate_join(&jid("new"), &Platform::Console);
+        std::env::remove_var(super::MAX_CLAN_MEMBERS_ENV);
+
+        assert_eq!(full, Err(ErrorCode::ClanMemberLimitReached));
+        assert_eq!(invited, Ok(()));
+        assert_eq!(closed_with_room, Err(ErrorCode::PermissionDenied));
+    }
+
+    /// Build a clan without a leader, whose members joined in order:
+    /// - `first`, a member last seen a day ago
+    /// - `sub`, a sub-leader never seen since joining
+    /// - `active`, a member seen just now
+    /// - `invited`, invited even more recently, who isn't a member yet
+    fn leaderless() -> Clan {
+        let seen = |username, role, status, last_seen| Player { last_seen, ..player(username, role, status) };
+
+        let members = vec![
+            seen("first", Role::Member, Status::Member, Some(Utc::now() - chrono::Duration::days(1))),
+            seen("sub", Role::SubLeader, Status::Member, None),
+            seen("active", Role::Member, Status::Member, Some(Utc::now())),
+            seen("invited", Role::NonMember, Status::Invited, Some(Utc::now() + chrono::Duration::hours(1))),
+        ];
+
+        Clan { members, ..Clan::default() }
+    }
+
+    /// Each rule picks its own successor among the members, and makes them the leader.
+    #[test]
+    fn promote_successor() {
+        for (rule, expected) in [
+            (Succession::HighestRole, "sub"),
+            (Succession::Oldest, "first"),
+            (Succession::MostActive, "active"),
+        ] {
+            let mut clan = leaderless();
+
+            assert_eq!(clan.promote_successor_by(rule).map(|jid| jid.username), Some(expected.to_string()), "{rule:?}");
+            assert_eq!(clan.role_of(&jid(expected)), Some(&Role::Leader), "{rule:?}");
+            assert!(clan.leadership_changed_at.is_some(), "{rule:?}");
+        }
+    }
+
+    /// Clans that still have a leader, or have no members left, get no successor.
+    #[test]
+    fn promote_no_successor() {
+        let mut clan = clan(Vec::new());
+        assert_eq!(clan.promote_successor_by(Succession::HighestRole), None);
+
+        let mut clan = Clan { members: vec![player("invited", Role::NonMember, Status::Invited)], ..Clan::default() };
+        assert_eq!(clan.promote_successor_by(Succession::Oldest), None);
+    }
+
+    /// Only IDs the game can handle belong to a clan.
+    #[test]
+    fn validate_id() {
+        assert_eq!(Clan::validate_id(0), Err(ErrorCode::NoSuchClan));
+        assert_eq!(Clan::validate_id(1), Ok(1));
+        assert_eq!(Clan::validate_id(999_999), Ok(999_999));
+        assert_eq!(Clan::validate_id(1_000_000), Err(ErrorCode::NoSuchClan));
+        assert_eq!(Clan::validate_id(u32::MAX), Err(ErrorCode::NoSuchClan));
+    }
+
+    /// Players are on the emulator if they have RPCN's default domain and region.
+    #[test]
+    fn platform_from_jid() {
+        let emulator = Jid { username: "player".to_string(), domain: "un".to_string(), region: "br".to_string() };
+        let console = Jid { domain: "a1".to_string(), ..emulator.clone() };
+        let brazilian = Jid { domain: "a1".to_string(), region: "br".to_string(), ..emulator.clone() };
+
+        assert_eq!(Platform::from(&emulator), Platform::Emulator);
+        assert_eq!(Platform::from(&console), Platform::Console);
+        assert_eq!(Platform::from(&brazilian), Platform::Console);
+    }
+
+    /// Platforms are read by any of their names, ignoring case.
+    #[test]
+    fn platform_names() {
+        for name in ["Emulator", "rpcs3", "RPCN", "pc"] {
+            assert_eq!(name.parse::<Platform>(), Ok(Platform::Emulator), "{name}");
+        }
+
+        for name in ["Console", "psn", "PS3"] {
+            assert_eq!(name.parse::<Platform>(), Ok(Platform::Console), "{name}");
+        }
+
+        assert!("ps4".parse::<Platform>().is_err());
+    }
+
+    /// Accounts are looked up on the platform the clan moves to,
+    /// the same way players are assigned to it.
+    #[cfg(feature = "admin")]
+    #[test]
+    fn platform_player_filter() {
+        use mongodb::bson::doc;
+
+        let emulator = Platform::Emulator.player_filter("player");
+        assert_eq!(emulator, doc! { "username": "player", "domain": "un", "region": "br" });
+
+        // A console account may use either default, but not both
+        let console = Platform::Console.player_filter("player");
+        assert_eq!(
+            console,
+            doc! {
+                "username": "player",
+                "$or": [{ "domain": { "$ne": "un" } }, { "region": { "$ne": "br" } }],
+            }
+        );
+    }
+
+    /// Any change to the clan changes its ``ETag``, so that clients don't keep a stale copy.
+    #[test]
+    fn etag_depends_on_contents() {
+        let clan = clan(Vec::new());
+        let described = Clan { description: "New description".to_string(), ..clan.clone() };
+        let joined = Clan {
+            members: [clan.members.clone(), vec![player("new", Role::Member, Status::Member)]].concat(),
+            ..clan.clone()
+        };
+
+        assert_ne!(clan.etag(None), described.etag(None));
+        assert_ne!(clan.etag(None), joined.etag(None));
+    }
+
+    /// Only members' activity is recorded.
+    #[test]
+    fn touch() {
+        let mut clan = clan(vec![player("invited", Role::NonMember, Status::Invited)]);
+
+        clan.touch(&jid("member"));
+        clan.touch(&jid("invited"));
+        clan.touch(&jid("stranger"));
+
+        assert!(clan.last_seen_of(&jid("member")).is_some_and(|date| Utc::now() - date < chrono::Duration::minutes(1)));
+        assert_eq!(clan.last_seen_of(&jid("invited")), None);
+        assert_eq!(clan.last_seen_of(&jid("leader")), None);
+        assert_eq!(clan.last_seen_of(&jid("stranger")), None);
+
+        // Clans the player never interacted with come last
+        assert!(clan.last_seen_of(&jid("member")) > clan.last_seen_of(&jid("leader")));
+    }
+
+    /// New members are given the clan's ``allowMsg`` default, which clans
+    /// stored before it existed read as disabled.
+    #[test]
+    fn default_allow_msg() {
+        let clan = Clan { default_allow_msg: true, ..clan(Vec::new()) };
+        let mut document = mongodb::bson::to_document(&clan).unwrap();
+
+        let read: Clan = mongodb::bson::from_document(document.clone()).unwrap();
+        assert!(read.default_allow_msg);
+
+        document.remove("default_allow_msg");
+        let read: Clan = mongodb::bson::from_document(document).unwrap();
+        assert!(!read.default_allow_msg);
+    }
+
+    /// Players can be given any known role, except leading a clan that already has a leader.
+    #[test]
+    fn validate_role() {
+        let led = clan(Vec::new());
+        let leaderless = Clan { members: vec![player("member", Role::Member, Status::Member)], ..Clan::default() };
+
+        for role in [Role::NonMember, Role::Member, Role::SubLeader] {
+            assert_eq!(led.validate_role(&jid("member"), role), Ok(()), "{role}");
+        }
+
+        assert_eq!(led.validate_role(&jid("member"), Role::Unknown), Err(ErrorCode::InvalidRolePriority));
+        assert_eq!(led.validate_role(&jid("member"), Role::Leader), Err(ErrorCode::InvalidRolePriority));
+        assert_eq!(led.validate_role(&jid("leader"), Role::Leader), Ok(()));
+        assert_eq!(leaderless.validate_role(&jid("member"), Role::Leader), Ok(()));
+    }
+
+    /// Platforms are queried by the same name they're stored with.
+    #[test]
+    fn platform_stored_names() {
+        for platform in [Platform::Console, Platform::Emulator] {
+            assert_eq!(mongodb::bson::to_bson(&platform).unwrap(), mongodb::bson::Bson::from(platform.name()));
+        }
+    }
+
+    /// Announcements can be pinned up to the limit, and unpinned at any time.
+    ///
+    /// The limit is read from the environment, so every case runs in this single test.
+    #[test]
+    fn pin_announcement() {
+        let announcements = (0..4).map(|_| Announcement::default()).collect();
+        let mut clan = Clan { announcements, ..clan(Vec::new()) };
+        let ids: Vec<_> = clan.announcements.iter().map(Announcement::id).collect();
+
+        std::env::set_var(super::MAX_PINNED_ANNOUNCEMENTS_ENV, "2");
+        let first = clan.pin_announcement(ids[0], true);
+        let second = clan.pin_announcement(ids[1], true);
+        let again = clan.pin_announcement(ids[1], true);
+        let third = clan.pin_announcement(ids[2], true);
+        let unpinned = clan.pin_announcement(ids[0], false);
+        let replaced = clan.pin_announcement(ids[2], true);
+        let missing = clan.pin_announcement(0, false);
+        std::env::remove_var(super::MAX_PINNED_ANNOUNCEMENTS_ENV);
+
+        assert_eq!((first, second, again), (Ok(()), Ok(()), Ok(())));
+        assert_eq!(third, Err(ErrorCode::AnnouncementLimitReached));
+        assert_eq!((unpinned, replaced), (Ok(()), Ok(())));
+        assert_eq!(missing, Err(ErrorCode::NoSuchClanAnnouncement));
+
+        let pinned: Vec<_> = clan.announcements.iter().map(|m| m.pinned).collect();
+        assert_eq!(pinned, [false, true, true, false]);
+    }
+
+    /// Imported entries skip duplicates and members, and stop at the blacklist's size limit.
+    #[cfg(feature = "admin")]
+    #[test]
+    fn import_blacklist_entry() {
+        let mut clan = clan(Vec::new());
+
+        assert_eq!(clan.import_blacklist_entry(jid("stranger")), Ok(()));
+        assert_eq!(clan.import_blacklist_entry(jid("stranger")), Err(ErrorCode::CannotRecordBlacklistEntry));
+        assert_eq!(clan.import_blacklist_entry(jid("member")), Err(ErrorCode::MemberStatusInvalid));
+        assert_eq!(clan.blacklist.len(), 1);
+
+        for i in 1..super::MAX_CLAN_BLACKLIST_SIZE {
+            assert_eq!(clan.import_blacklist_entry(jid(&format!("stranger{i}"))), Ok(()));
+        }
+
+        assert_eq!(clan.import_blacklist_entry(jid("late")), Err(ErrorCode::BlacklistLimitReached));
+    }
+
+    /// Only pending requests count towards the clan's limit on them.
+    ///
+    /// The limit is read from the environment, so every case runs in this single test.
+    #[test]
+    fn has_too_many_requests() {
+        let clan = clan(vec![
+            player("pending", Role::NonMember, Status::Pending),
+            player("invited", Role::NonMember, Status::Invited),
+        ]);
+
+        std::env::remove_var(super::MAX_PENDING_REQUESTS_ENV);
+        let default = clan.has_too_many_requests();
+        std::env::set_var(super::MAX_PENDING_REQUESTS_ENV, "2");
+        let below = clan.has_too_many_requests();
+        std::env::set_var(super::MAX_PENDING_REQUESTS_ENV, "1");
+        let reached = clan.has_too_many_requests();
+        std::env::remove_var(super::MAX_PENDING_REQUESTS_ENV);
+
+        assert!(!default);
+        assert!(!below);
+        assert!(reached);
+    }
+
+    /// Only the player's own pending requests count towards their limit on them.
+    ///
+    /// The limit is read from the environment, so every case runs in this single test.
+    #[test]
+    fn has_too_many_requests_from() {
+        let clans = [
+            clan(vec![player("player", Role::NonMember, Status::Pending)]),
+            clan(vec![player("player", Role::NonMember, Status::Invited)]),
+            clan(vec![player("other", Role::NonMember, Status::Pending)]),
+        ];
+
+        std::env::remove_var(super::MAX_PLAYER_PENDING_REQUESTS_ENV);
+        let default = Clan::has_too_many_requests_from(&jid("player"), &clans);
+        std::env::set_var(super::MAX_PLAYER_PENDING_REQUESTS_ENV, "2");
+        let below = Clan::has_too_many_requests_from(&jid("player"), &clans);
+        std::env::set_var(super::MAX_PLAYER_PENDING_REQUESTS_ENV, "1");
+        let reached = Clan::has_too_many_requests_from(&jid("player"), &clans);
+        std::env::remove_var(super::MAX_PLAYER_PENDING_REQUESTS_ENV);
+
+        assert!(!default);
+        assert!(!below);
+        assert!(reached);
+    }
+
+    /// The platform is derived from the ticket's signature,
+    /// falling back to the default one without a ticket.
+    #[test]
+    fn platform_from_ticket() {
+        let ticket = |signature| Ticket { signature, ..Default::default() };
+
+        assert_eq!(Platform::from_ticket_or_default(Some(&ticket(Signature::Emulator(Vec::new())))), Platform::Emulator);
+        assert_eq!(Platform::from_ticket_or_default(Some(&ticket(Signature::Console(Vec::new())))), Platform::Console);
+
+        assert_eq!(Platform::from_ticket_or_default(None), Platform::default());
+        assert_eq!(Clan::default().platform, Platform::Console);
+    }
+
+    /// Clans too large to be stored are refused before writing them.
+    #[test]
+    fn validate_size() {
+        assert_eq!(clan(Vec::new()).validate_size(), Ok(()));
+
+        let large = Clan { description: "a".repeat(super::MAX_CLAN_DOCUMENT_SIZE), ..clan(Vec::new()) };
+        assert_eq!(large.validate_size(), Err(ErrorCode::BadRequest));
+    }
+
+    /// The members' data is budgeted across the whole clan.
+    ///
+    /// The budget is read from the environment, so every case runs in this single test.
+    #[test]
+    fn has_too_much_member_data() {
+        let mut clan = clan(Vec::new());
+        for (member, name) in clan.members.iter_mut().zip(["Leader", "Sub", "Member"]) {
+            member.online_name = name.to_string();
+            member.description = "abcd".to_string();
+        }
+
+        // 6 + 3 + 6 bytes of names, and 12 of descriptions
+        std::env::remove_var(super::MAX_MEMBER_DATA_SIZE_ENV);
+        let default = clan.has_too_much_member_data();
+        std::env::set_var(super::MAX_MEMBER_DATA_SIZE_ENV, "27");
+        let within = clan.has_too_much_member_data();
+        std::env::set_var(super::MAX_MEMBER_DATA_SIZE_ENV, "26");
+        let over = clan.has_too_much_member_data();
+        std::env::remove_var(super::MAX_MEMBER_DATA_SIZE_ENV);
+
+        assert!(!default);
+        assert!(!within);
+        assert!(over);
+    }
+
+    /// Players are only limited to one clan per platform when the policy is enabled,
+    /// and only by the clans they're a member of.
+    ///
+    /// The policy is read from the environment, so every case runs in this single test.
+    #[test]
+    fn already_in_clan_on() {
+        let clans = [
+            Clan { platform: Platform::Console, ..clan(vec![player("player", Role::Member, Status::Member)]) },
+            Clan { platform: Platform::Emulator, ..clan(vec![player("player", Role::NonMember, Status::Pending)]) },
+        ];
+
+        std::env::remove_var(super::SINGLE_CLAN_PER_PLATFORM_ENV);
+        let disabled = Clan::already_in_clan_on(&jid("player"), &Platform::Console, &clans);
+        std::env::set_var(super::SINGLE_CLAN_PER_PLATFORM_ENV, "true");
+        let console = Clan::already_in_clan_on(&jid("player"), &Platform::Console, &clans);
+        let emulator = Clan::already_in_clan_on(&jid("player"), &Platform::Emulator, &clans);
+        let other = Clan::already_in_clan_on(&jid("other"), &Platform::Console, &clans);
+        std::env::remove_var(super::SINGLE_CLAN_PER_PLATFORM_ENV);
+
+        assert!(!disabled);
+        assert!(console);
+        assert!(!emulator);
+        assert!(!other);
+    }
+
+    /// Players can't re-join right after leaving, until the cooldown is over.
+    ///
+    /// The cooldown is read from the environment, so every case runs in this single test.
+    #[test]
+    fn rejoin_cooldown() {
+        let mut clan = clan(Vec::new());
+
+        std::env::set_var(super::REJOIN_COOLDOWN_ENV, "60");
+        clan.record_departure(&jid("left"));
+        let immediately = clan.left_recently(&jid("left"));
+        let other = clan.left_recently(&jid("other"));
+
+        // Pretend the player left before the cooldown
+        clan.departures[0].date -= chrono::Duration::seconds(61);
+        let later = clan.left_recently(&jid("left"));
+
+        // Departures the cooldown no longer applies to are forgotten
+        clan.record_departure(&jid("other"));
+        let remembered: Vec<_> = clan.departures.iter().map(|d| d.jid.username.clone()).collect();
+
+        std::env::remove_var(super::REJOIN_COOLDOWN_ENV);
+        let disabled = clan.left_recently(&jid("other"));
+
+        assert!(immediately);
+        assert!(!other);
+        assert!(!later);
+        assert_eq!(remembered, ["other"]);
+        assert!(!disabled);
+    }
+
+    /// Descriptions are trimmed before being cut to length, and can still be cleared.
+    #[test]
+    fn set_description() {
+        let mut clan = clan(Vec::new());
+
+        clan.set_description(&format!("Welcome!{}", " ".repeat(super::MAX_CLAN_DESCRIPTION_LENGTH)));
+        assert_eq!(clan.description, "Welcome!");
+
+        clan.set_description(&"é".repeat(super::MAX_CLAN_DESCRIPTION_LENGTH + 1));
+        assert_eq!(clan.description.chars().count(), super::MAX_CLAN_DESCRIPTION_LENGTH);
+
+        clan.set_description("   ");
+        assert_eq!(clan.description, "");
+    }
+
+    /// Disbanded clans are archived right away, and only gone for good after the grace period.
+    ///
+    /// The grace period is read from the environment, so every case runs in this single test.
+    #[test]
+    fn disband_grace_period() {
+        std::env::remove_var(super::DISBAND_GRACE_PERIOD_ENV);
+        let unset = Clan::disband_grace_period();
+        std::env::set_var(super::DISBAND_GRACE_PERIOD_ENV, "0");
+        let disabled = Clan::disband_grace_period();
+        std::env::set_var(super::DISBAND_GRACE_PERIOD_ENV, "60");
+        let enabled = Clan::disband_grace_period();
+        std::env::remove_var(super::DISBAND_GRACE_PERIOD_ENV);
+
+        assert_eq!(unset, None);
+        assert_eq!(disabled, None);
+        assert_eq!(enabled, Some(chrono::Duration::seconds(60)));
+
+        let active = clan(Vec::new());
+        let recoverable = Clan { disband_at: Some(Utc::now() + chrono::Duration::seconds(60)), ..clan(Vec::new()) };
+        let disbanded = Clan { disband_at: Some(Utc::now() - chrono::Duration::seconds(60)), ..clan(Vec::new()) };
+
+        assert_eq!([&active, &recoverable, &disbanded].map(Clan::is_archived), [false, true, true]);
+        assert_eq!([&active, &recoverable, &disbanded].map(Clan::is_disbanded), [false, false, true]);
+    }
+
+    /// Members are moved to their accounts on the other platform, unless one of them has none.
+    #[cfg(feature = "admin")]
+    #[test]
+    fn migrate() {
+        let emulator = |username: &str| Jid { domain: "un".to_string(), region: "br".to_string(), ..jid(username) };
+        let members = vec![player("pending", Role::NonMember, Status::Pending)];
+
+        // Requests without an account are dropped
+        let mut migrated = clan(members.clone());
+        let accounts = vec![Some(emulator("leader")), Some(emulator("sub")), Some(emulator("member")), None];
+        assert_eq!(migrated.migrate(Platform::Emulator, accounts), Ok(()));
+
+        let jids: Vec<_> = migrated.members.iter().map(|p| p.jid.to_string()).collect();
+        assert_eq!(
+            jids,
+            ["leader@un.br.np.playstation.net", "sub@un.br.np.playstation.net", "member@un.br.np.playstation.net"]
+        );
+        assert_eq!(migrated.platform, Platform::Emulator);
+
+        // Members without an account are reported, and nothing changes
+        let mut partial = clan(members);
+        let accounts = vec![Some(emulator("leader")), None, Some(emulator("member")), Some(emulator("pending"))];
+        let offending = partial.migrate(Platform::Emulator, accounts).expect_err("a member has no account");
+
+        assert_eq!(offending.iter().map(Jid::to_string).collect::<Vec<_>>(), ["sub@a1.us.np.playstation.net"]);
+        assert_eq!(partial.members[0].jid.to_string(), "leader@a1.us.np.playstation.net");
+        assert_eq!(partial.platform, Platform::Console);
+    }
+
+    /// Clans only accept players of their own platform, unless they're cross-platform.
+    #[test]
+    fn accepts_platform() {
+        let console = clan(Vec::new());
+        let cross_platform = Clan { cross_platform: true, ..clan(Vec::new()) };
+
+        assert!(console.accepts_platform(&Platform::Console));
+        assert!(!console.accepts_platform(&Platform::Emulator));
+        assert!(cross_platform.accepts_platform(&Platform::Console));
+        assert!(cross_platform.accepts_platform(&Platform::Emulator));
+    }
+
+    /// Only leaders of cross-platform clans are presented on the viewer's platform.
+    #[test]
+    fn present_member() {
+        let viewer = Jid { domain: "un".to_string(), region: "br".to_string(), ..jid("viewer") };
+        let console = clan(Vec::new());
+        let cross_platform = Clan { cross_platform: true, ..clan(Vec::new()) };
+
+        let presented = |clan: &Clan, index: usize| clan.present_member(&clan.members[index], &viewer).jid.to_string();
+
+        assert_eq!(presented(&cross_platform, 0), "leader@un.br.np.playstation.net");
+        assert_eq!(presented(&cross_platform, 2), "member@a1.us.np.playstation.net");
+        assert_eq!(presented(&console, 0), "leader@a1.us.np.playstation.net");
+
+        // Leaders on the viewer's own platform are left alone
+        let console_viewer = jid("viewer");
+        assert_eq!(cross_platform.present_member(&cross_platform.members[0], &console_viewer).jid.to_string(), "leader@a1.us.np.playstation.net");
+    }
+
+    /// Clans can't post announcements back to back, until the cooldown is over.
+    ///
+    /// The cooldown is read from the environment, so every case runs in this single test.
+    #[test]
+    fn posted_recently() {
+        let posted = |seconds_ago| Announcement { date_created: Utc::now() - chrono::Duration::seconds(seconds_ago), ..Default::default() };
+
+        let quiet = clan(Vec::new());
+        let recent = Clan { announcements: vec![posted(600), posted(10)], ..clan(Vec::new()) };
+        let old = Clan { announcements: vec![posted(600)], ..clan(Vec::new()) };
+
+        std::env::remove_var(super::ANNOUNCEMENT_COOLDOWN_ENV);
+        let disabled = recent.posted_recently();
+        std::env::set_var(super::ANNOUNCEMENT_COOLDOWN_ENV, "60");
+        let enabled = [&quiet, &recent, &old].map(Clan::posted_recently);
+        std::env::remove_var(super::ANNOUNCEMENT_COOLDOWN_ENV);
+
+        assert!(!disabled);
+        assert_eq!(enabled, [false, true, false]);
+    }
+
+    /// A player is only blacklisted if they aren't yet, so blacklisting them
+    /// again matches nothing and adds no duplicate entry.
+    #[test]
+    fn push_blacklist_query() {
+        let clan = clan(Vec::new());
+        let (filter, update) = clan.push_blacklist_query(&jid("target"));
+
+        assert_eq!(filter.get_document("blacklist").unwrap().get_str("$ne"), Ok("target@a1.us.np.playstation.net"));
+        assert_eq!(update.get_document("$addToSet").unwrap().get_str("blacklist"), Ok("target@a1.us.np.playstation.net"));
+        assert!(update.get_document("$set").unwrap().contains_key("members.$[author].last_seen"));
+        assert!(update.get_document("$set").unwrap().contains_key("updated_at"));
+        assert_eq!(update.get_document("$inc").unwrap().get_i32("version"), Ok(1));
+    }
+
+    /// Roles the clan didn't rename keep their default name.
+    #[test]
+    fn role_name() {
+        let mut clan = clan(vec![]);
+        clan.role_names.insert(Role::Leader, "Captain".to_string());
+
+        assert_eq!(clan.role_name(Role::Leader), "Captain");
+        assert_eq!(clan.role_name(Role::Member), "Member");
+    }
+
+    /// Clans are only scoped by title when serving multiple titles,
+    /// and the default title is stored without one.
+    ///
+    /// The options are read from the environment, so every case runs in this single test.
+    #[test]
+    fn title_of() {
+        let ticket = |service_id: &str| Ticket { service_id: service_id.to_string(), ..Default::default() };
+        let home = ticket("HOME00000");
+        let other = ticket("OTHER0000");
+
+        std::env::remove_var(MULTIPLE_TITLES_ENV);
+        std::env::set_var(DEFAULT_TITLE_ENV, "HOME00000");
+        assert_eq!(Clan::title_of(&other), None);
+
+        std::env::set_var(MULTIPLE_TITLES_ENV, "true");
+        assert_eq!(Clan::title_of(&home), None);
+        assert_eq!(Clan::title_of(&other), Some("OTHER0000".to_string()));
+        assert_eq!(Clan::title_of(&ticket("")), None);
+
+        let clan = Clan { title: Clan::title_of(&other), ..Clan::default() };
+        assert!(clan.belongs_to(&other));
+        assert!(!clan.belongs_to(&home));
+        assert!(Clan::default().belongs_to(&home));
+
+        std::env::remove_var(DEFAULT_TITLE_ENV);
+        assert_eq!(Clan::title_of(&home), Some("HOME00000".to_string()));
+
+        std::env::remove_var(MULTIPLE_TITLES_ENV);
+    }
+
+    /// Only the announcements posted before the cutoff are deleted.
+    #[test]
+    fn delete_announcements_before() {
+        let now = Utc::now();
+        let posted = |days: i64| Announcement { date_created: now - chrono::Duration::days(days), ..Default::default() };
+        let mut clan = Clan { announcements: vec![posted(10), posted(5), posted(1)], ..clan(Vec::new()) };
+        let recent = clan.announcements[2].id();
+
+        assert_eq!(clan.delete_announcements_before(now - chrono::Duration::days(30)), 0);
+        assert_eq!(clan.delete_announcements_before(now - chrono::Duration::days(2)), 2);
+        assert_eq!(clan.announcements.iter().map(Announcement::id).collect::<Vec<_>>(), [recent]);
+    }
+
+    /// Only clans of the player's title that haven't been disbanded are shown to the game.
+    #[test]
+    fn is_shown_in() {
+        let default = Clan::default();
+        let other = Clan { title: Some("OTHER0000".to_string()), ..Clan::default() };
+        let disbanded = Clan { disband_at: Some(Utc::now() + chrono::Duration::days(1)), ..Clan::default() };
+
+        assert_eq!([&default, &other, &disbanded].map(|c| c.is_shown_in(None)), [true, false, false]);
+        assert_eq!([&default, &other, &disbanded].map(|c| c.is_shown_in(Some("OTHER0000"))), [false, true, false]);
+    }
+
+    /// Every write stamps the date and bumps the version, leaving the rest as it is.
+    #[test]
+    fn stamped() {
+        let clan = Clan { version: 41, ..clan(Vec::new()) };
+        let before = Utc::now();
+        let stamped = clan.stamped();
+
+        assert_eq!(stamped.version, 42);
+        assert!(stamped.updated_at.is_some_and(|date| date >= before));
+        assert_eq!((clan.version, clan.updated_at), (41, None));
+        assert_eq!(stamped.members.len(), clan.members.len());
+    }
+
+    /// Only the clans accepting the platform count towards the membership limit, when enabled.
+    ///
+    /// The option is read from the environment, so every case runs in this single test.
+    #[test]
+    fn memberships_on() {
+        let clans = [
+            Clan { platform: Platform::Console, ..clan(vec![player("player", Role::Member, Status::Member)]) },
+            Clan { platform: Platform::Console, cross_platform: true, ..clan(vec![player("player", Role::Member, Status::Member)]) },
+            Clan { platform: Platform::Emulator, ..clan(vec![player("player", Role::Member, Status::Member)]) },
+            Clan { platform: Platform::Emulator, ..clan(vec![player("player", Role::NonMember, Status::Invited)]) },
+        ];
+
+        std::env::remove_var(super::PER_PLATFORM_MEMBERSHIP_LIMIT_ENV);
+        let disabled = Clan::memberships_on(&jid("player"), &Platform::Emulator, &clans);
+        std::env::set_var(super::PER_PLATFORM_MEMBERSHIP_LIMIT_ENV, "true");
+        let emulator = Clan::memberships_on(&jid("player"), &Platform::Emulator, &clans);
+        let console = Clan::memberships_on(&jid("player"), &Platform::Console, &clans);
+        std::env::remove_var(super::PER_PLATFORM_MEMBERSHIP_LIMIT_ENV);
+
+        assert_eq!((disabled, emulator, console), (3, 2, 2));
+    }
+
+    /// Handing over leadership swaps the roles of the leader and the new one.
+    #[test]
+    fn hand_leadership() {
+        let mut clan = clan(Vec::new());
+        clan.hand_leadership(&jid("leader"), &jid("member"));
+
+        assert_eq!(clan.role_of(&jid("leader")), Some(&Role::SubLeader));
+        assert_eq!(clan.role_of(&jid("member")), Some(&Role::Leader));
+        assert_eq!(clan.role_of(&jid("sub")), Some(&Role::SubLeader));
+        assert_eq!(clan.owner().map(|leader| leader.jid.username.as_str()), Some("member"));
+        assert!(clan.leadership_changed_at.is_some());
+    }
+
+    /// Members leave freely, but leaders only if someone takes over.
+    #[test]
+    fn leave() {
+        let mut clan = clan(Vec::new());
+        assert_eq!(clan.leave_by(&jid("member"), None), Ok(None));
+        assert_eq!(clan.role_of(&jid("member")), None);
+
+        assert_eq!(clan.leave_by(&jid("leader"), None), Err(ErrorCode::ClanLeaderCannotLeave));
+        assert_eq!(clan.role_of(&jid("leader")), Some(&Role::Leader));
+
+        assert_eq!(clan.leave_by(&jid("leader"), Some(Succession::HighestRole)), Ok(Some(jid("sub"))));
+        assert_eq!(clan.role_of(&jid("leader")), None);
+        assert_eq!(clan.role_of(&jid("sub")), Some(&Role::Leader));
+
+        assert_eq!(clan.leave_by(&jid("sub"), Some(Succession::Oldest)), Err(ErrorCode::ClanLeaderCannotLeave));
+        assert_eq!(clan.members.len(), 1);
     }
 }
@@ -0,0 +1,68 @@
+//! Represents a clan that could not be saved to the database.
+//!
+//! Rather than dropping the change, the attempted state of the
+//! clan is kept aside, so that it can be replayed later.
+
+use chrono::{DateTime, Utc};
+use mongodb::bson::oid::ObjectId;
+use serde::{Deserialize, Serialize};
+
+use super::clan::Clan;
+
+/// A clan write that failed.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FailedWrite {
+    /// The ID of the record, assigned by the database.
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+
+    /// The state the clan should have been saved with.
+    pub clan: Clan,
+
+    /// The error that caused the write to fail.
+    pub error: String,
+
+    /// The date the write failed, in UTC.
+    #[serde(with = "chrono::serde::ts_seconds")]
+    pub date: DateTime<Utc>,
+}
+
+impl FailedWrite {
+    /// Create a record for a clan that failed to save.
+    pub fn new(clan: Clan, error: &mongodb::error::Error) -> Self {
+        Self {
+            id: None,
+            clan,
+            error: error.to_string(),
+            date: Utc::now(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    //! Tests for keeping failed writes aside.
+
+    use chrono::{TimeZone, Utc};
+
+    use super::{Clan, FailedWrite};
+
+    /// Failed writes survive a round-trip through the database,
+    /// which is left to assign their ID.
+    #[test]
+    fn round_trip() {
+        let write = FailedWrite {
+            id: None,
+            clan: Clan::default(),
+            error: "Connection timed out".to_string(),
+            date: Utc.timestamp_opt(1_700_000_000, 0).unwrap(),
+        };
+
+        let document = mongodb::bson::to_document(&write).unwrap();
+        assert!(!document.contains_key("_id"));
+        assert_eq!(document.get_i64("date"), Ok(1_700_000_000));
+
+        let read: FailedWrite = mongodb::bson::from_document(document).unwrap();
+        assert_eq!((read.clan.id(), &read.error, read.date), (write.clan.id(), &write.error, write.date));
+    }
+}
@@ -3,6 +3,10 @@
 //! 
 //! Examples of entities include clans and players.
 
+pub mod audit;
+pub mod bin_data;
 pub mod clan;
+pub mod failed_write;
+pub mod permission;
 pub mod player;
 pub mod announcement;
\ No newline at end of file
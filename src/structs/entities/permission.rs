@@ -0,0 +1,248 @@
+//! The minimum role each operation on a clan requires.
+//!
+//! The defaults can be overridden with the ``ROLE_PERMISSIONS`` environment
+//! variable, as a comma-separated list of ``<operation>=<role>`` entries
+//! (e.g. ``kick_member=leader``).
+//!
+//! The routes check permissions against this table, and the
+//! ``/capabilities/roles`` endpoint reports it, so clients don't drift from it.
+
+use serde::Serialize;
+
+use super::player::Role;
+
+/// Environment variable name for overriding the minimum roles.
+const ROLE_PERMISSIONS_ENV: &str = "ROLE_PERMISSIONS";
+
+/// An operation on a clan that only some roles can perform.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Operation {
+    /// Post an announcement.
+    PostAnnouncement,
+
+    /// Delete announcements.
+    DeleteAnnouncement,
+
+    /// Pin or unpin an announcement.
+    PinAnnouncement,
+
+    /// See who has read an announcement.
+    ViewAnnouncementReaders,
+
+    /// Kick a member from the clan.
+    KickMember,
+
+    /// Change a member's role.
+    ChangeMemberRole,
+
+    /// Check whether a player is blacklisted.
+    ViewBlacklist,
+
+    /// Add a player to the blacklist.
+    RecordBlacklistEntry,
+
+    /// Remove a player from the blacklist.
+    DeleteBlacklistEntry,
+
+    /// Send, re-send or cancel an invitation.
+    Invite,
+
+    /// Accept or decline a request to join the clan.
+    ManageRequests,
+
+    /// Disband the clan, or recover it.
+    Disband,
+}
+
+impl Operation {
+    /// Every operation.
+    pub const ALL: [Self; 12] = [
+        Self::PostAnnouncement,
+        Self::DeleteAnnouncement,
+        Self::PinAnnouncement,
+        Self::ViewAnnouncementReaders,
+        Self::KickMember,
+        Self::ChangeMemberRole,
+        Self::ViewBlacklist,
+        Self::RecordBlacklistEntry,
+        Self::DeleteBlacklistEntry,
+        Self::Invite,
+        Self::ManageRequests,
+        Self::Disband,
+    ];
+
+    /// The name the operation is configured and reported with.
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::PostAnnouncement => "post_announcement",
+            Self::DeleteAnnouncement => "delete_announcement",
+            Self::PinAnnouncement => "pin_announcement",
+            Self::ViewAnnouncementReaders => "view_announcement_readers",
+            Self::KickMember => "kick_member",
+            Self::ChangeMemberRole => "change_member_role",
+            Self::ViewBlacklist => "view_blacklist",
+            Self::RecordBlacklistEntry => "record_blacklist_entry",
+            Self::DeleteBlacklistEntry => "delete_blacklist_entry",
+            Self::Invite => "invite",
+            Self::ManageRequests => "manage_requests",
+            Self::Disband => "disband",
+        }
+    }
+
+    /// The minimum role the operation requires, unless overridden.
+    const fn default_role(self) -> Role {
+        match self {
+            Self::Invite | Self::ManageRequests => Role::Member,
+            Self::Disband => Role::Leader,
+            _ => Role::SubLeader,
+        }
+    }
+
+    /// The minimum role the operation requires.
+    /// See: [`ROLE_PERMISSIONS_ENV`]
+    pub fn min_role(self) -> Role {
+        std::env::var(ROLE_PERMISSIONS_ENV)
+            .unwrap_or_default()
+            .split(',')
+            .filter_map(|entry| entry.split_once('='))
+            .find(|(operation, _)| operation.trim() == self.name())
+            .and_then(|(_, role)| parse_role(role))
+            .unwrap_or_else(|| self.default_role())
+    }
+
+    /// Returns whether a player with the given role, if any, can perform the operation.
+    pub fn allows(self, role: Option<&Role>) -> bool {
+        role.is_some_and(|role| *role >= self.min_role())
+    }
+}
+
+/// Check the overrides, describing every entry that would be ignored,
+/// so that a typo doesn't silently leave an operation at its default.
+/// See: [`ROLE_PERMISSIONS_ENV`]
+pub fn invalid_overrides() -> Vec<String> {
+    invalid_entries(&std::env::var(ROLE_PERMISSIONS_ENV).unwrap_or_default())
+}
+
+/// Describe every entry of the given overrides that would be ignored.
+fn invalid_entries(overrides: &str) -> Vec<String> {
+    overrides
+        .split(',')
+        .filter(|entry| !entry.trim().is_empty())
+        .filter_map(|entry| {
+            let Some((operation, role)) = entry.split_once('=') else {
+                return Some(format!("Malformed {ROLE_PERMISSIONS_ENV} entry `{entry}`: expected `<operation>=<role>`"));
+            };
+
+            if !Operation::ALL.iter().any(|op| op.name() == operation.trim()) {
+                return Some(format!("Unknown operation `{}` in {ROLE_PERMISSIONS_ENV}", operation.trim()));
+            }
+
+            if parse_role(role).is_none() {
+                return Some(format!("Unknown role `{}` in {ROLE_PERMISSIONS_ENV}", role.trim()));
+            }
+
+            None
+        })
+        .collect()
+}
+
+/// Parse a role by its name, ignoring case and separators (e.g. ``sub_leader``).
+///
+/// [`Role::Unknown`] can't be given, as it would let anyone through.
+fn parse_role(value: &str) -> Option<Role> {
+    let value = value.trim().replace(['_', '-'], "");
+
+    [Role::NonMember, Role::Member, Role::SubLeader, Role::Leader]
+        .into_iter()
+        .find(|role| role.to_string().eq_ignore_ascii_case(&value))
+}
+
+#[cfg(test)]
+mod tests {
+    //! Tests for the minimum roles and their overrides.
+
+    use super::{invalid_entries, invalid_overrides, parse_role, Operation, Role, ROLE_PERMISSIONS_ENV};
+
+    /// Without overrides, members can invite, only the leader can disband,
+    /// and every other operation needs a sub-leader.
+    #[test]
+    fn default_roles() {
+        assert_eq!(Operation::Invite.default_role(), Role::Member);
+        assert_eq!(Operation::ManageRequests.default_role(), Role::Member);
+        assert_eq!(Operation::Disband.default_role(), Role::Leader);
+        assert_eq!(Operation::KickMember.default_role(), Role::SubLeader);
+        assert_eq!(Operation::ViewBlacklist.default_role(), Role::SubLeader);
+    }
+
+    /// Roles are parsed ignoring case and separators, but never as ``Unknown``.
+    #[test]
+    fn parse_roles() {
+        assert_eq!(parse_role("leader"), Some(Role::Leader));
+        assert_eq!(parse_role(" sub_leader "), Some(Role::SubLeader));
+        assert_eq!(parse_role("Sub-Leader"), Some(Role::SubLeader));
+        assert_eq!(parse_role("nonmember"), Some(Role::NonMember));
+        assert_eq!(parse_role("unknown"), None);
+        assert_eq!(parse_role("admin"), None);
+    }
+
+    /// Overrides replace the defaults of their operations, and invalid entries
+    /// are reported and ignored.
+    ///
+    /// The overrides are read from the environment, so every case
+    /// runs in this single test.
+    #[test]
+    fn overrides() {
+        std::env::set_var(ROLE_PERMISSIONS_ENV, "kick_member=leader, invite = sub_leader");
+        let kick = Operation::KickMember.min_role();
+        let invite = Operation::Invite.min_role();
+        let disband = Operation::Disband.min_role();
+        let valid = invalid_overrides();
+
+        std::env::set_var(ROLE_PERMISSIONS_ENV, "kick_member=unknown,ban=leader,disband,,");
+        let ignored = Operation::KickMember.min_role();
+        let invalid = invalid_overrides();
+        std::env::remove_var(ROLE_PERMISSIONS_ENV);
+
+        assert_eq!((kick, invite, disband), (Role::Leader, Role::SubLeader, Role::Leader));
+        assert!(valid.is_empty(), "{valid:?}");
+
+        assert_eq!(ignored, Role::SubLeader);
+        assert_eq!(invalid.len(), 3, "{invalid:?}");
+        assert!(invalid[0].contains("Unknown role `unknown`"));
+        assert!(invalid[1].contains("Unknown operation `ban`"));
+        assert!(invalid[2].contains("Malformed"));
+    }
+
+    /// Players need a role, and a high enough one.
+    ///
+    /// Disbanding isn't overridden by any other test, so its minimum role
+    /// doesn't depend on the order they run in.
+    #[test]
+    fn allows() {
+        assert!(!Operation::Disband.allows(None));
+        assert!(!Operation::Disband.allows(Some(&Role::SubLeader)));
+        assert!(Operation::Disband.allows(Some(&Role::Leader)));
+    }
+
+    /// Every operation can be overridden with any role, and only blank entries are skipped.
+    #[test]
+    fn entries() {
+        for operation in Operation::ALL {
+            for role in ["non_member", "member", "sub_leader", "leader"] {
+                let entry = format!(" {} = {role} ", operation.name());
+                assert!(invalid_entries(&entry).is_empty(), "{entry}");
+            }
+        }
+
+        assert!(invalid_entries("").is_empty());
+        assert!(invalid_entries(" , ,disband=leader,").is_empty());
+
+        let invalid = invalid_entries("=leader,disband=,disband:leader,disband=leader=member");
+        assert_eq!(invalid.len(), 4, "{invalid:?}");
+        assert!(invalid[0].contains("Unknown operation ``"));
+        assert!(invalid[1].contains("Unknown role ``"));
+        assert!(invalid[2].contains("Malformed"));
+        assert!(invalid[3].contains("Unknown role `leader=member`"));
+    }
+}
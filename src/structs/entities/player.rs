@@ -3,6 +3,7 @@
 use std::fmt::Display;
 
 use actix_web::web::Data;
+use chrono::{DateTime, Utc};
 use futures_util::StreamExt;
 use mongodb::bson::doc;
 use serde::{Deserialize, Serialize};
@@ -13,9 +14,14 @@ use crate::{
         responses::error::ErrorCode,
         ticket::{Ticket, DEFAULT_DOMAIN, DEFAULT_REGION},
     },
+    utils,
 };
 
-use super::clan::Clan;
+use super::{bin_data::BinDataType, clan::Clan};
+
+/// Environment variable name for taking a missing domain or region from
+/// the player's previous sessions, rather than the RPCN defaults.
+pub const STORED_REGION_FALLBACK_ENV: &str = "STORED_REGION_FALLBACK";
 
 /// A JID is an identifier composed of:
 ///
@@ -149,11 +155,7 @@ impl<'de> Deserialize<'de> for Jid {
 
 impl From<Ticket> for Jid {
     fn from(ticket: Ticket) -> Self {
-        Self {
-            username: ticket.username,
-            region: ticket.region,
-            domain: ticket.domain,
-        }
+        Self::from(ExtendedJid::from(ticket))
     }
 }
 
@@ -181,8 +183,76 @@ impl From<Jid> for ExtendedJid {
     }
 }
 
+/// Derive a player's JID from a ticket, without going through [`Jid`].
+///
+/// An empty domain or region falls back to the RPCN defaults,
+/// the same way they're filled in when parsing a JID.
+impl From<&Ticket> for ExtendedJid {
+    fn from(ticket: &Ticket) -> Self {
+        let or_default = |value: &str, default: &str| {
+            let value = if value.is_empty() { default } else { value };
+            value.to_string()
+        };
+
+        Self {
+            username: ticket.username.clone(),
+            domain: or_default(&ticket.domain, DEFAULT_DOMAIN),
+            region: or_default(&ticket.region, DEFAULT_REGION),
+        }
+    }
+}
+
+impl ExtendedJid {
+    /// Derive a player's JID from a ticket, like [`ExtendedJid::from`].
+    ///
+    /// If the ticket lacks a domain or region, and ``STORED_REGION_FALLBACK``
+    /// is enabled, they're taken from the player's record in the ``players``
+    /// collection, matched by username, so that the JID stays consistent
+    /// with the player's previous sessions.
+    pub async fn resolve(ticket: &Ticket, database: &Data<Database>) -> Self {
+        let jid = Self::from(ticket);
+
+        if !Self::needs_stored_region(ticket) {
+            return jid;
+        }
+
+        match database.players.find_one(doc! { "username": ticket.username.as_str() }).await {
+            Ok(Some(stored)) => jid.completed_with(ticket, stored),
+            Ok(None) => jid,
+            Err(e) => {
+                log::error!("Failed to look-up the region of player `{}`: {e}", ticket.username);
+                jid
+            }
+        }
+    }
+
+    /// Returns whether the ticket lacks a domain or region
+    /// that should be taken from the player's stored record.
+    /// See: [`STORED_REGION_FALLBACK_ENV`]
+    fn needs_stored_region(ticket: &Ticket) -> bool {
+        let incomplete = ticket.domain.is_empty() || ticket.region.is_empty();
+
+        incomplete && utils::env::flag(STORED_REGION_FALLBACK_ENV, false)
+    }
+
+    /// Take the domain and region the ticket lacked from the player's stored record.
+    fn completed_with(self, ticket: &Ticket, stored: Self) -> Self {
+        Self {
+            domain: if ticket.domain.is_empty() { stored.domain } else { self.domain },
+            region: if ticket.region.is_empty() { stored.region } else { self.region },
+            username: self.username,
+        }
+    }
+}
+
+impl From<Ticket> for ExtendedJid {
+    fn from(ticket: Ticket) -> Self {
+        Self::from(&ticket)
+    }
+}
+
 /// A player's role in the clan.
-#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
 pub enum Role {
     /// The player's role is unknown.
     ///
@@ -216,6 +286,48 @@ impl From<u32> for Role {
     }
 }
 
+impl Role {
+    /// The name the role is displayed with, unless the clan customized it.
+    /// See: [`Clan::role_name`]
+    pub const fn default_name(self) -> &'static str {
+        match self {
+            Self::Unknown => "Unknown",
+            Self::NonMember => "Non-Member",
+            Self::Member => "Member",
+            Self::SubLeader => "Sub-Leader",
+            Self::Leader => "Leader",
+        }
+    }
+
+    /// Deserialize a role from the number the game represents it with,
+    /// sent either as a number or as a string (e.g. ``3`` or ``"3"``).
+    ///
+    /// Anything that isn't a number becomes [`Role::Unknown`], so that it's
+    /// rejected with [`ErrorCode::InvalidRolePriority`] instead of failing the request.
+    pub fn deserialize_lenient<'de, D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::de::Deserializer<'de>,
+    {
+        /// Either representation of a role.
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Value {
+            /// The role, as a number.
+            Number(u32),
+
+            /// The role, as a string.
+            Text(String),
+        }
+
+        let role = match Value::deserialize(deserializer)? {
+            Value::Number(role) => role,
+            Value::Text(role) => role.trim().trim_matches('"').parse().unwrap_or_default(),
+        };
+
+        Ok(Self::from(role))
+    }
+}
+
 impl Display for Role {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
@@ -290,8 +402,18 @@ pub struct Player {
     /// Unknown use.
     pub bin_data: String,
 
+    /// The kind of data held by ``bin_data``, if the client declared it.
+    #[serde(default)]
+    pub bin_data_type: BinDataType,
+
     /// Unknown use.
     pub size: u32,
+
+    /// The last time the player interacted with the clan, in UTC.
+    ///
+    /// This is only tracked for members, when they make changes to the clan.
+    #[serde(default, with = "chrono::serde::ts_seconds_option")]
+    pub last_seen: Option<DateTime<Utc>>,
 }
 
 impl Default for Player {
@@ -304,7 +426,135 @@ impl Default for Player {
             description: String::new(),
             allow_msg: false,
             bin_data: String::new(),
+            bin_data_type: BinDataType::Untyped,
             size: 0,
+            last_seen: None,
         }
     }
 }
+
+impl Player {
+    /// Returns how many bytes the player's free-form data takes up.
+    pub const fn data_size(&self) -> usize {
+        self.online_name.len() + self.description.len() + self.bin_data.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    //! Tests for the players' JIDs, roles, the way the game sends them, and data.
+
+    use mongodb::bson::{doc, Document};
+    use serde::Deserialize;
+
+    use super::{ExtendedJid, Jid, Player, Role, STORED_REGION_FALLBACK_ENV};
+    use crate::structs::{entities::clan::Clan, responses::error::ErrorCode, ticket::Ticket};
+
+    /// A request carrying a role.
+    #[derive(Debug, Deserialize)]
+    struct Probe {
+        /// The role.
+        #[serde(deserialize_with = "Role::deserialize_lenient")]
+        role: Role,
+    }
+
+    /// Read the role of a document.
+    fn role(document: Document) -> Role {
+        mongodb::bson::from_document::<Probe>(document).expect("valid probe").role
+    }
+
+    /// Roles are accepted as numbers, or as numbers in a string.
+    #[test]
+    fn lenient_role() {
+        assert_eq!(role(doc! { "role": 3 }), Role::SubLeader);
+        assert_eq!(role(doc! { "role": "3" }), Role::SubLeader);
+        assert_eq!(role(doc! { "role": " 4 " }), Role::Leader);
+
+        let probe: Probe = serde_xml_rs::from_str("<probe><role>2</role></probe>").expect("valid probe");
+        assert_eq!(probe.role, Role::Member);
+    }
+
+    /// Anything else is an unknown role, which is then rejected as an invalid priority.
+    #[test]
+    fn invalid_role() {
+        let jid = Jid { username: "player".to_string(), domain: "a1".to_string(), region: "us".to_string() };
+
+        for value in [doc! { "role": "leader" }, doc! { "role": "" }, doc! { "role": 9 }] {
+            let role = role(value);
+
+            assert_eq!(role, Role::Unknown);
+            assert_eq!(Clan::default().validate_role(&jid, role), Err(ErrorCode::InvalidRolePriority));
+        }
+    }
+
+    /// Names, descriptions and data all count towards a player's data size, in bytes.
+    #[test]
+    fn data_size() {
+        assert_eq!(Player::default().data_size(), 0);
+
+        let player = Player {
+            online_name: "Name".to_string(),
+            description: "é".to_string(),
+            bin_data: "AAAA".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(player.data_size(), 10);
+    }
+
+    /// JIDs are derived from tickets, falling back to the RPCN defaults
+    /// like when they're parsed.
+    #[test]
+    fn jid_from_ticket() {
+        let ticket = |domain: &str, region: &str| Ticket {
+            username: "player".to_string(),
+            domain: domain.to_string(),
+            region: region.to_string(),
+            ..Default::default()
+        };
+
+        for (domain, region, expected) in [
+            ("a1", "us", "player@a1.us.np.playstation.net"),
+            ("", "", "player@un.br.np.playstation.net"),
+            ("b7", "", "player@b7.br.np.playstation.net"),
+        ] {
+            let jid = Jid::from(ExtendedJid::from(&ticket(domain, region)));
+            assert_eq!(jid.to_string(), expected);
+
+            let parsed = Jid::try_from(format!("player@{domain}.{region}.np.playstation.net")).expect("valid JID");
+            assert_eq!(parsed.to_string(), expected);
+        }
+    }
+
+    /// Stored records only fill in the parts of a JID the ticket lacked, when enabled.
+    ///
+    /// The option is read from the environment, so every case runs in this single test.
+    #[test]
+    fn stored_region_fallback() {
+        let ticket = |domain: &str, region: &str| Ticket {
+            username: "player".to_string(),
+            domain: domain.to_string(),
+            region: region.to_string(),
+            ..Default::default()
+        };
+        let stored = || ExtendedJid { username: "player".to_string(), domain: "c9".to_string(), region: "jp".to_string() };
+
+        std::env::remove_var(STORED_REGION_FALLBACK_ENV);
+        let disabled = ExtendedJid::needs_stored_region(&ticket("", ""));
+        std::env::set_var(STORED_REGION_FALLBACK_ENV, "true");
+        let needed = [ticket("", ""), ticket("b7", ""), ticket("a1", "us")].map(|ticket| ExtendedJid::needs_stored_region(&ticket));
+        std::env::remove_var(STORED_REGION_FALLBACK_ENV);
+
+        assert!(!disabled);
+        assert_eq!(needed, [true, true, false]);
+
+        for (domain, region, expected) in [
+            ("", "", "player@c9.jp.np.playstation.net"),
+            ("b7", "", "player@b7.jp.np.playstation.net"),
+            ("", "us", "player@c9.us.np.playstation.net"),
+        ] {
+            let ticket = ticket(domain, region);
+            let jid = ExtendedJid::from(&ticket).completed_with(&ticket, stored());
+            assert_eq!(Jid::from(jid).to_string(), expected);
+        }
+    }
+}
\ No newline at end of file
@@ -1,8 +1,11 @@
 //! Request structs for the Admin endpoints
+use std::collections::HashMap;
+
+use mongodb::bson::{doc, Document};
 use serde::Deserialize;
 
 use crate::structs::entities::{
-    clan::{Clan, Platform},
+    clan::{Clan, Id, Platform},
     player::{Jid, Player, Role, Status},
 };
 
@@ -21,14 +24,119 @@ pub struct CreateClan {
 
     /// The platform the clan is on
     pub clan_platform: Platform,
+
+    /// Whether to let the user create the clan even if they've
+    /// been creating clans too often, as a moderator vouches for them.
+    #[serde(default)]
+    pub bypass_rate_limit: bool,
+}
+
+/// Request to validate a clan's name and tag, without creating it.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ValidateName {
+    /// The desired clan's name
+    pub clan_name: String,
+
+    /// The desired clan's tag
+    pub clan_tag: String,
+}
+
+/// Request to move a clan to another platform.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TransferPlatform {
+    /// The platform to move the clan to
+    pub platform: Platform,
+}
+
+/// Request to move a clan to another ID.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReassignId {
+    /// The ID to move the clan to
+    pub id: Id,
+}
+
+/// Request to let players of every platform join a clan, or stop them from doing so.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SetCrossPlatform {
+    /// Whether the clan should accept players of every platform
+    pub enabled: bool,
+}
+
+/// Request to set the custom names a clan displays its roles with.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SetRoleNames {
+    /// The custom name of each role, e.g. ``{ "SubLeader": "Officer" }``
+    ///
+    /// Roles that are left out go back to their default names.
+    pub names: HashMap<Role, String>,
+}
+
+/// Request to add a player to a clan, bypassing invitations.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AddMember {
+    /// The username of the player to add.
+    pub username: String,
+
+    /// The role to give the player, as the game represents it.
+    pub role: u32,
+}
+
+/// Request to add players to a clan's blacklist, in bulk.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportBlacklist {
+    /// The JIDs of the players to blacklist.
+    pub jids: Vec<String>,
+}
+
+/// Request to merge another clan into a clan.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MergeClan {
+    /// The ID of the clan to merge, which is deleted afterwards
+    pub from: Id,
+}
+
+/// Query to list the clans, optionally filtered.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ListClans {
+    /// Only list the clans on this platform
+    pub platform: Option<Platform>,
+
+    /// Only list the clans updated after this date, in seconds since the UNIX epoch
+    pub updated_since: Option<i64>,
+}
+
+impl ListClans {
+    /// Build the filter for the ``clans`` collection matching the query.
+    pub fn filter(&self) -> Document {
+        let mut filter = self
+            .platform
+            .as_ref()
+            .map_or_else(|| doc! {}, |platform| doc! { "platform": platform.name() });
+
+        if let Some(since) = self.updated_since {
+            filter.insert("updated_at", doc! { "$gt": since });
+        }
+
+        filter
+    }
 }
 
 impl From<(CreateClan, Jid)> for Clan {
     fn from((request, author): (CreateClan, Jid)) -> Self {
         let mut clan = Self::default();
 
-        clan.name = request.clan_name;
-        clan.tag = request.clan_tag;
+        // Names and tags are stored trimmed, so that the unique indexes catch padded duplicates
+        clan.name = request.clan_name.trim().to_string();
+        clan.tag = request.clan_tag.trim().to_string();
         clan.platform = request.clan_platform;
 
         clan.members = vec![Player {
@@ -41,3 +149,101 @@ impl From<(CreateClan, Jid)> for Clan {
         clan
     }
 }
+
+#[cfg(test)]
+mod tests {
+    //! Tests for parsing the Admin API's requests.
+
+    use actix_web::{
+        test::TestRequest,
+        web::{Json, Query},
+        FromRequest,
+    };
+
+    use mongodb::bson::doc;
+
+    use super::{CreateClan, ListClans, ReassignId};
+    use crate::structs::entities::{
+        clan::{Clan, Platform},
+        player::Jid,
+    };
+
+    /// Clans can be listed by platform, under any of its names.
+    #[test]
+    fn list_clans_by_platform() {
+        let query = |query: &str| Query::<ListClans>::from_query(query).map(|query| query.into_inner().platform);
+
+        assert_eq!(query("").unwrap(), None);
+        assert_eq!(query("platform=emulator").unwrap(), Some(Platform::Emulator));
+        assert_eq!(query("platform=RPCS3").unwrap(), Some(Platform::Emulator));
+        assert_eq!(query("platform=console").unwrap(), Some(Platform::Console));
+        assert!(query("platform=xbox").is_err());
+    }
+
+    /// Clans can be listed by the last time they changed, for incremental syncs.
+    #[test]
+    fn list_clans_updated_since() {
+        let filter = |query: &str| Query::<ListClans>::from_query(query).unwrap().filter();
+
+        assert_eq!(filter(""), doc! {});
+        assert_eq!(filter("updatedSince=1700000000"), doc! { "updated_at": { "$gt": 1_700_000_000_i64 } });
+        assert_eq!(
+            filter("platform=console&updatedSince=1700000000"),
+            doc! { "platform": Platform::Console.name(), "updated_at": { "$gt": 1_700_000_000_i64 } }
+        );
+        assert!(Query::<ListClans>::from_query("updatedSince=yesterday").is_err());
+    }
+
+    /// Clans can only be created on a known platform.
+    #[actix_web::test]
+    async fn create_clan_platform() {
+        let create = |platform: &str| {
+            let body = format!(r#"{{"username":"player","clanName":"Clan","clanTag":"TAG","clanPlatform":"{platform}"}}"#);
+            let (req, mut payload) = TestRequest::post()
+                .insert_header(("Content-Type", "application/json"))
+                .set_payload(body)
+                .to_http_parts();
+
+            async move { Json::<CreateClan>::from_request(&req, &mut payload).await.map(|json| json.into_inner().clan_platform) }
+        };
+
+        assert_eq!(create("Emulator").await.unwrap(), Platform::Emulator);
+        assert_eq!(create("psn").await.unwrap(), Platform::Console);
+        assert!(create("xbox").await.is_err());
+    }
+
+    /// Clans created by moderators are stored with their name and tag trimmed too.
+    #[test]
+    fn create_clan_trimmed() {
+        let request = CreateClan {
+            username: "player".to_string(),
+            clan_name: "  My Clan \t".to_string(),
+            clan_tag: " TAG ".to_string(),
+            clan_platform: Platform::Emulator,
+            bypass_rate_limit: false,
+        };
+        let author = Jid { username: "player".to_string(), domain: "un".to_string(), region: "br".to_string() };
+        let clan = Clan::from((request, author));
+
+        assert_eq!((clan.name.as_str(), clan.tag.as_str()), ("My Clan", "TAG"));
+        assert_eq!(clan.platform, Platform::Emulator);
+    }
+
+    /// Clans can only be moved to an ID the game could send.
+    #[actix_web::test]
+    async fn reassign_id() {
+        let reassign = |body: &'static str| {
+            let (req, mut payload) = TestRequest::put()
+                .insert_header(("Content-Type", "application/json"))
+                .set_payload(body)
+                .to_http_parts();
+
+            async move { Json::<ReassignId>::from_request(&req, &mut payload).await.map(|json| json.into_inner().id) }
+        };
+
+        assert_eq!(reassign(r#"{"id":42}"#).await.unwrap(), 42);
+        assert!(reassign(r#"{"id":-1}"#).await.is_err());
+        assert!(reassign(r#"{"id":"42"}"#).await.is_err());
+        assert!(reassign("{}").await.is_err());
+    }
+}
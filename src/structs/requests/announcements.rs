@@ -2,7 +2,7 @@
 
 use serde::Deserialize;
 
-use crate::structs::{entities::{announcement::Id as AnnouncementId, clan::Id as ClanId}, ticket::Ticket};
+use crate::structs::{entities::{announcement::Id as AnnouncementId, bin_data::BinDataType, clan::Id as ClanId}, ticket::Ticket};
 
 /// Request to get a clan's announcements.
 #[derive(Debug, Deserialize)]
@@ -14,11 +14,16 @@ pub struct RetrieveAnnouncements {
     /// The ID of the clan.
     pub id: ClanId,
 
-    /// How many announcements to skip.
+    /// Position of the first announcement to return, counting from 1.
+    /// See: [`crate::utils::pagination`]
     pub start: i32,
 
     /// How many announcements to return.
     pub max: i32,
+
+    /// Only return the announcements of this category.
+    #[serde(default)]
+    pub category: Option<String>,
 }
 
 /// Request to post a new announcement for a clan.
@@ -39,7 +44,31 @@ pub struct PostAnnouncement {
 
     /// The date the announcement will expire, expressed in
     /// seconds into the future, starting from right now.
-    pub expire_date: u64,
+    ///
+    /// If omitted, the server's default duration is used.
+    #[serde(default)]
+    pub expire_date: Option<u64>,
+
+    /// The kind of announcement (e.g. events, rules, news).
+    #[serde(default)]
+    pub category: Option<String>,
+
+    /// Binary data attached to the announcement.
+    #[serde(default)]
+    pub bin_data: Option<String>,
+
+    /// The kind of data held by ``bin-data``, checked before it's stored.
+    ///
+    /// If omitted, the data is left untyped.
+    #[serde(default)]
+    pub bin_data_type: Option<BinDataType>,
+
+    /// The ID of the announcement this one refers to.
+    ///
+    /// Anything but another announcement of the clan falls back to
+    /// [`DEFAULT_FROM_ID`](crate::structs::entities::announcement::DEFAULT_FROM_ID).
+    #[serde(default)]
+    pub from_id: Option<AnnouncementId>,
 }
 
 /// Request to delete an announcement.
@@ -54,4 +83,72 @@ pub struct DeleteAnnouncement {
 
     /// The ID of the announcement to delete.
     pub msg_id: AnnouncementId
+}
+
+/// Request to delete every announcement posted before a date.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct DeleteAnnouncementsBefore {
+    /// A PSN ticket for authenticating the request.
+    pub ticket: Ticket,
+
+    /// The ID of the clan.
+    pub id: ClanId,
+
+    /// The cutoff date, in seconds since the Unix epoch.
+    ///
+    /// Announcements posted before it are deleted.
+    pub before: i64,
+}
+
+/// Request to pin or unpin an announcement.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct PinAnnouncement {
+    /// A PSN ticket for authenticating the request.
+    pub ticket: Ticket,
+
+    /// The ID of the clan.
+    pub id: ClanId,
+
+    /// The ID of the announcement to pin.
+    pub msg_id: AnnouncementId,
+
+    /// Whether the announcement should be pinned or unpinned.
+    pub pinned: bool,
+}
+
+/// Request to mark an announcement as read.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct MarkAnnouncementRead {
+    /// A PSN ticket for authenticating the request.
+    pub ticket: Ticket,
+
+    /// The ID of the clan.
+    pub id: ClanId,
+
+    /// The ID of the announcement that was read.
+    pub msg_id: AnnouncementId,
+}
+
+/// Request to get the players who have read an announcement.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct GetAnnouncementReaders {
+    /// A PSN ticket for authenticating the request.
+    pub ticket: Ticket,
+
+    /// The ID of the clan.
+    pub id: ClanId,
+
+    /// The ID of the announcement.
+    pub msg_id: AnnouncementId,
+
+    /// Position of the first player to return, counting from 1.
+    /// See: [`crate::utils::pagination`]
+    pub start: i32,
+
+    /// How many players to return.
+    pub max: i32,
 }
\ No newline at end of file
@@ -2,10 +2,102 @@
 
 use std::fmt::Debug;
 
-use actix_web::{web::Buf, FromRequest};
+use actix_web::{
+    http::header::{AUTHORIZATION, CONTENT_TYPE},
+    web::Buf,
+    FromRequest, Responder,
+};
 use serde::Deserialize;
 
+use crate::structs::{
+    responses::{base::Response, error::ErrorCode},
+    ticket::TicketError,
+};
+
+/// Environment variable name for the comma-separated list of accepted content types.
+const ALLOWED_CONTENT_TYPES_ENV: &str = "ALLOWED_CONTENT_TYPES";
+
+/// Content type sent by the game, accepted unless overridden.
+/// See: [`ALLOWED_CONTENT_TYPES_ENV`]
+const DEFAULT_CONTENT_TYPE: &str = "application/x-ps3-clan";
+
+/// Check whether a request's content type is accepted.
+///
+/// Requests without a content type are accepted, as the game may omit it.
+fn is_content_type_allowed(req: &actix_web::HttpRequest) -> bool {
+    let Some(content_type) = req.headers().get(CONTENT_TYPE) else {
+        return true;
+    };
+
+    // Ignore parameters, such as the charset
+    let Ok(content_type) = content_type.to_str() else {
+        return false;
+    };
+    let essence = content_type.split(';').next().unwrap_or_default().trim();
+
+    std::env::var(ALLOWED_CONTENT_TYPES_ENV)
+        .unwrap_or_else(|_| DEFAULT_CONTENT_TYPE.to_string())
+        .split(',')
+        .any(|allowed| allowed.trim().eq_ignore_ascii_case(essence))
+}
+
+/// Read a ticket sent as an ``Authorization: Bearer <base64>`` header.
+///
+/// Only base64 characters are accepted, so that the ticket can be
+/// placed in the body as it is.
+fn bearer_ticket(req: &actix_web::HttpRequest) -> Option<String> {
+    let value = req.headers().get(AUTHORIZATION)?.to_str().ok()?.trim();
+    let (scheme, ticket) = value.split_once(' ')?;
+
+    let ticket = ticket.trim();
+    let is_base64 = |c: char| c.is_ascii_alphanumeric() || matches!(c, '+' | '/' | '=');
+
+    (scheme.eq_ignore_ascii_case("bearer") && !ticket.is_empty() && ticket.chars().all(is_base64))
+        .then(|| ticket.to_string())
+}
+
+/// Insert a ticket as the first child of the body's root element,
+/// unless the body already carries one.
+///
+/// Returns `None` if the body has no root element to insert it into.
+fn insert_ticket(body: &[u8], ticket: &str) -> Option<Vec<u8>> {
+    let text = std::str::from_utf8(body).ok()?;
+    if text.contains("<ticket") {
+        return None;
+    }
+
+    // Skip the declaration, comments and the like, to find the root element
+    let mut start = 0;
+    loop {
+        start += text[start..].find('<')?;
+        if !text[start + 1..].starts_with(['?', '!']) {
+            break;
+        }
+        start += 1;
+    }
+
+    let end = start + text[start..].find('>')?;
+    let element = format!("<ticket>{ticket}</ticket>");
+
+    // A self-closing root element has to be opened up first
+    let body = if text[..end].ends_with('/') {
+        let name: String = text[start + 1..end]
+            .chars()
+            .take_while(|c| !c.is_whitespace() && *c != '/')
+            .collect();
+
+        format!("{}>{element}</{name}>{}", &text[..end - 1], &text[end + 1..])
+    } else {
+        format!("{}{element}{}", &text[..=end], &text[end + 1..])
+    };
+
+    Some(body.into_bytes())
+}
+
 /// Generic wrapper for a request.
+///
+/// Unknown elements are ignored, so the root element only needs
+/// to carry the fields of `T`.
 #[derive(Debug, Deserialize)]
 pub struct Request<T> {
     /// Root element of the request.
@@ -19,17 +111,58 @@ impl<'a, T: Deserialize<'a> + Debug> FromRequest for Request<T> {
         std::pin::Pin<Box<dyn std::future::Future<Output = Result<Self, Self::Error>> + 'static>>;
 
     /// Get the request body from the client and deserialize it.
+    ///
+    /// If the body doesn't carry a ticket, the one sent in an
+    /// ``Authorization: Bearer`` header is used instead, if any.
+    ///
+    /// Rejected tickets are answered with the error code the game expects,
+    /// such as [`ErrorCode::TicketExpired`]. See: [`TicketError`]
     fn from_request(
         req: &actix_web::HttpRequest,
         payload: &mut actix_web::dev::Payload,
     ) -> Self::Future {
+        // Reject obviously wrong clients early
+        let allowed = is_content_type_allowed(req);
+        let ticket = bearer_ticket(req);
+
         let fut = actix_web::web::Bytes::from_request(req, payload);
+        let req = req.clone();
         Box::pin(async move {
-            let bytes = fut.await?;
+            // Answer in a way the game can read
+            if !allowed {
+                let response = Response::<()>::error(ErrorCode::BadRequest).respond_to(&req);
+                return Err(actix_web::error::InternalError::from_response("Invalid content type", response).into());
+            }
+
+            let mut bytes = fut.await?;
+
+            // Let companion clients send the ticket as a header
+            if let Some(body) = ticket.and_then(|ticket| insert_ticket(&bytes, &ticket)) {
+                bytes = body.into();
+            }
 
             // Parse the XML
-            let request = serde_xml_rs::from_reader(bytes.reader())
-                .map_err(actix_web::error::ErrorInternalServerError)?;
+            //
+            // `serde_xml_rs` maps attributes and child elements to fields alike,
+            // so `<clan id="1">` and `<clan><id>1</id></clan>` are both accepted,
+            // and so are ``0`` and ``1`` for booleans.
+            //
+            // Elements the request structs don't model are skipped, along with their
+            // children, so that clients sending extra data keep working. For this to
+            // hold, request structs must never use `#[serde(deny_unknown_fields)]`.
+            let request = match serde_xml_rs::from_reader(bytes.reader()) {
+                Ok(request) => request,
+
+                // Let the game know why its ticket was rejected
+                Err(e) => {
+                    let Some(error) = TicketError::find(&e) else {
+                        return Err(actix_web::error::ErrorInternalServerError(e));
+                    };
+
+                    let response = Response::<()>::error(ErrorCode::from(error)).respond_to(&req);
+                    return Err(actix_web::error::InternalError::from_response(e, response).into());
+                }
+            };
 
             // DEBUG: print the XML's contents
             log::debug!("Request: {request:#?}");
@@ -38,3 +171,156 @@ impl<'a, T: Deserialize<'a> + Debug> FromRequest for Request<T> {
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    //! Tests for extracting requests from the game.
+
+    use actix_web::{
+        body::MessageBody,
+        http::header::{AUTHORIZATION, CONTENT_TYPE},
+        test::TestRequest,
+        FromRequest,
+    };
+    use serde::Deserialize;
+
+    use super::{bearer_ticket, insert_ticket, Request};
+    use crate::structs::{requests::clans::GetClanInfo, ticket::Ticket};
+
+    /// A request with a single field, to check what gets through.
+    #[derive(Debug, Deserialize)]
+    struct Probe {
+        /// Any number.
+        id: u32,
+    }
+
+    /// A request carrying a ticket.
+    #[derive(Debug, Deserialize)]
+    #[allow(dead_code)]
+    struct TicketProbe {
+        /// The ticket.
+        ticket: Ticket,
+    }
+
+    /// A request carrying a ticket, without checking it.
+    #[derive(Debug, Deserialize)]
+    struct RawTicketProbe {
+        /// The ticket, as it was sent.
+        ticket: String,
+    }
+
+    /// Extract a probe sent with the given content type, if any.
+    async fn extract(content_type: Option<&'static str>) -> Result<Request<Probe>, actix_web::Error> {
+        let mut request = TestRequest::post().set_payload("<probe><id>1</id></probe>");
+        if let Some(content_type) = content_type {
+            request = request.insert_header((CONTENT_TYPE, content_type));
+        }
+
+        let (req, mut payload) = request.to_http_parts();
+        Request::<Probe>::from_request(&req, &mut payload).await
+    }
+
+    /// The game's content type is accepted, with or without parameters.
+    #[actix_web::test]
+    async fn correct_content_type() {
+        for content_type in ["application/x-ps3-clan", "application/x-ps3-clan; charset=utf-8"] {
+            let request = extract(Some(content_type)).await.expect("accepted");
+            assert_eq!(request.request.id, 1);
+        }
+    }
+
+    /// Requests without a content type are accepted, as the game may omit it.
+    #[actix_web::test]
+    async fn missing_content_type() {
+        let request = extract(None).await.expect("accepted");
+        assert_eq!(request.request.id, 1);
+    }
+
+    /// Other content types are answered with an error the game can read.
+    #[actix_web::test]
+    async fn wrong_content_type() {
+        let error = extract(Some("text/plain")).await.expect_err("rejected");
+        let response = error.error_response();
+
+        assert!(response.status().is_success());
+        assert_eq!(response.headers().get(CONTENT_TYPE).and_then(|value| value.to_str().ok()), Some("application/x-ps3-clan"));
+
+        let body = response.into_body().try_into_bytes().unwrap_or_default();
+        assert!(String::from_utf8_lossy(&body).contains(r#"result="01""#));
+    }
+
+    /// Rejected tickets are answered with the reason's error code.
+    #[actix_web::test]
+    async fn rejected_ticket() {
+        let (req, mut payload) = TestRequest::post()
+            .set_payload("<probe><ticket>not a ticket</ticket></probe>")
+            .to_http_parts();
+
+        let error = Request::<TicketProbe>::from_request(&req, &mut payload).await.expect_err("rejected");
+        let body = error.error_response().into_body().try_into_bytes().unwrap_or_default();
+
+        // Malformed tickets are reported as invalid
+        assert!(String::from_utf8_lossy(&body).contains(r#"result="02""#));
+    }
+
+    /// Fields may be sent as attributes rather than child elements.
+    #[actix_web::test]
+    async fn attribute_fields() {
+        for body in [r#"<clan id="7"/>"#, r#"<clan id="7"></clan>"#, "<clan><id>7</id></clan>"] {
+            let (req, mut payload) = TestRequest::post().set_payload(body).to_http_parts();
+            let request = Request::<GetClanInfo>::from_request(&req, &mut payload).await.expect(body);
+
+            assert_eq!(request.request.id, 7);
+            assert!(request.request.ticket.is_none());
+        }
+    }
+
+    /// Unknown elements are skipped, along with their children.
+    #[actix_web::test]
+    async fn unknown_elements() {
+        let body = "<probe><extra>1</extra><nested><id>2</id><deeper><x/></deeper></nested><id>7</id><tail/></probe>";
+        let (req, mut payload) = TestRequest::post().set_payload(body).to_http_parts();
+        let request = Request::<Probe>::from_request(&req, &mut payload).await.expect("accepted");
+
+        assert_eq!(request.request.id, 7);
+    }
+
+    /// Only base64 bearer tokens are read as tickets.
+    #[test]
+    fn bearer_tickets() {
+        let ticket = |value: &'static str| bearer_ticket(&TestRequest::default().insert_header((AUTHORIZATION, value)).to_http_request());
+
+        assert_eq!(ticket("Bearer AAEC/+8="), Some("AAEC/+8=".to_string()));
+        assert_eq!(ticket("bearer  AAEC "), Some("AAEC".to_string()));
+        assert_eq!(ticket("Basic AAEC"), None);
+        assert_eq!(ticket("Bearer <ticket>"), None);
+        assert_eq!(ticket("Bearer "), None);
+        assert_eq!(bearer_ticket(&TestRequest::default().to_http_request()), None);
+    }
+
+    /// The ticket is inserted into the root element, unless the body already carries one.
+    #[test]
+    fn inserted_tickets() {
+        let insert = |body: &str| insert_ticket(body.as_bytes(), "AAEC").map(|body| String::from_utf8(body).unwrap());
+
+        assert_eq!(insert("<clan><id>1</id></clan>").as_deref(), Some("<clan><ticket>AAEC</ticket><id>1</id></clan>"));
+        assert_eq!(
+            insert(r#"<?xml version="1.0"?><clan id="1"/>"#).as_deref(),
+            Some(r#"<?xml version="1.0"?><clan id="1"><ticket>AAEC</ticket></clan>"#)
+        );
+        assert_eq!(insert("<clan><ticket>BBBB</ticket></clan>"), None);
+        assert_eq!(insert("no root element"), None);
+    }
+
+    /// Tickets sent as bearer tokens are used when the body omits one.
+    #[actix_web::test]
+    async fn header_ticket() {
+        let (req, mut payload) = TestRequest::post()
+            .insert_header((AUTHORIZATION, "Bearer AAEC"))
+            .set_payload("<clan><id>1</id></clan>")
+            .to_http_parts();
+
+        let request = Request::<RawTicketProbe>::from_request(&req, &mut payload).await.expect("accepted");
+        assert_eq!(request.request.ticket, "AAEC");
+    }
+}
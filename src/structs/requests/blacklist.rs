@@ -15,7 +15,8 @@ pub struct GetBlacklist {
     /// The ID of the clan.
     pub id: Id,
 
-    /// How many members to skip.
+    /// Position of the first member to return, counting from 1.
+    /// See: [`crate::utils::pagination`]
     pub start: i32,
 
     /// How many members to return.
@@ -46,4 +47,27 @@ pub struct DeleteBlacklistEntry {
 
     /// The JID of the player to remove from the blacklist.
     pub jid: String,
-}
\ No newline at end of file
+}
+
+/// Request to check whether a player is in a clan's blacklist.
+#[derive(Debug, Deserialize)]
+pub struct IsBlacklisted {
+    /// A PSN ticket for authenticating the request.
+    pub ticket: Ticket,
+
+    /// The ID of the clan.
+    pub id: Id,
+
+    /// The JID of the player to look for.
+    pub jid: String,
+}
+
+/// Request to count the entries in a clan's blacklist.
+#[derive(Debug, Deserialize)]
+pub struct GetBlacklistCount {
+    /// A PSN ticket for authenticating the request.
+    pub ticket: Ticket,
+
+    /// The ID of the clan.
+    pub id: Id,
+}
@@ -27,9 +27,11 @@ impl From<CreateClan> for Clan {
     fn from(request: CreateClan) -> Self {
         let mut clan = Self::default();
 
-        clan.name = request.name;
-        clan.tag = request.tag;
+        // Names and tags are stored trimmed, so that the unique indexes catch padded duplicates
+        clan.name = request.name.trim().to_string();
+        clan.tag = request.tag.trim().to_string();
         clan.platform = Platform::from(request.ticket.clone());
+        clan.title = Clan::title_of(&request.ticket);
 
         clan.members = vec![Player {
             jid: Jid::from(request.ticket),
@@ -48,7 +50,65 @@ pub struct GetClanList {
     /// A PSN ticket for authenticating the request.
     pub ticket: Ticket,
 
-    /// How many clans to skip.
+    /// Position of the first clan to return, counting from 1.
+    /// See: [`crate::utils::pagination`]
+    pub start: i32,
+
+    /// How many clans to return.
+    pub max: i32,
+
+    /// Only return the clans after this cursor, ignoring `start`.
+    ///
+    /// An empty cursor requests the first page, and each full page
+    /// returns the cursor of the next. See: [`crate::utils::cursor`]
+    ///
+    /// The game never sends this; it's for companion clients.
+    #[serde(default)]
+    pub after: Option<String>,
+
+    /// The order to return the clans in, ignored when paging with `after`.
+    ///
+    /// If omitted, clans are returned in the order they're stored.
+    #[serde(default)]
+    pub sort: Option<ClanSort>,
+}
+
+impl GetClanList {
+    /// Build the filter for the clans listed to the player, on their title.
+    ///
+    /// Clans that haven't been approved yet, or have been disbanded, are left out.
+    pub fn filter(&self, jid: &Jid) -> mongodb::bson::Document {
+        use mongodb::bson::doc;
+
+        doc! {
+            "members.jid": jid.to_string(),
+            "pending_approval": { "$ne": true },
+            "disband_at": null,
+            "title": Clan::title_of(&self.ticket),
+        }
+    }
+
+    /// Returns how many clans to return, which is at least one.
+    pub fn page_size(&self) -> i64 {
+        i64::from(self.max.max(1))
+    }
+}
+
+/// Request to get the clan a player most recently interacted with.
+#[derive(Debug, Deserialize)]
+pub struct GetLastActiveClan {
+    /// A PSN ticket for authenticating the request.
+    pub ticket: Ticket,
+}
+
+/// Request to get the clans with the most members.
+#[derive(Debug, Deserialize)]
+pub struct GetLeaderboard {
+    /// A PSN ticket for authenticating the request.
+    pub ticket: Ticket,
+
+    /// Position of the first clan to return, counting from 1.
+    /// See: [`crate::utils::pagination`]
     pub start: i32,
 
     /// How many clans to return.
@@ -58,16 +118,71 @@ pub struct GetClanList {
 /// Request to get a list of clans.
 #[derive(Debug, Deserialize)]
 pub struct ClanSearch {
-    /// How many clans to skip.
+    /// A PSN ticket for the player searching, if any.
+    ///
+    /// When given, the clans of the player's game title are searched,
+    /// rather than those of the default title.
+    #[serde(default)]
+    pub ticket: Option<Ticket>,
+
+    /// Position of the first clan to return, counting from 1.
+    /// See: [`crate::utils::pagination`]
     pub start: i32,
 
     /// How many clans to return.
     pub max: i32,
 
+    /// Only return the clans after this cursor, ignoring `start`.
+    ///
+    /// An empty cursor requests the first page, and each full page
+    /// returns the cursor of the next. See: [`crate::utils::cursor`]
+    ///
+    /// The game never sends this; it's for companion clients.
+    #[serde(default)]
+    pub after: Option<String>,
+
+    /// The order to return the clans in, ignored when paging with `after`.
+    ///
+    /// If omitted, clans are returned in the order they're stored.
+    #[serde(default)]
+    pub sort: Option<ClanSort>,
+
     /// A custom filter to apply to the search.
     pub filter: Option<ClanSearchFilter>,
 }
 
+/// An order to list clans in, such as ``members_desc``.
+///
+/// The game never sends this; it's for companion clients.
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ClanSort {
+    /// The clans with the most members first.
+    MembersDesc,
+
+    /// The most recently created clans first.
+    DateCreatedDesc,
+
+    /// The clans in alphabetical order.
+    NameAsc,
+}
+
+impl ClanSort {
+    /// Convert the order to a BSON sort, breaking ties by ID.
+    ///
+    /// Member counts aren't stored, so the clans need a ``member_count``
+    /// field to be sorted by them. See: [`crate::routes::clans`]
+    pub fn to_sort(self) -> mongodb::bson::Document {
+        use mongodb::bson::doc;
+
+        match self {
+            Self::MembersDesc => doc! { "member_count": -1, "id": 1 },
+            Self::DateCreatedDesc => doc! { "date_created": -1, "id": 1 },
+            Self::NameAsc => doc! { "name": 1, "id": 1 },
+        }
+    }
+}
+
 /// Enum of operators to apply to a clan search filter.
 #[derive(Debug, Default)]
 pub enum ClanSearchFilterOperator {
@@ -75,25 +190,25 @@ pub enum ClanSearchFilterOperator {
     #[default]
     All,
 
-    /// Name equal to.
+    /// Equal to.
     Equal,
 
-    /// Name different from.
+    /// Different from.
     NotEqual,
 
-    /// Name starts with.
+    /// Starts with.
     GreaterThan,
 
-    /// Name starts with, or equal to.
+    /// Starts with, or equal to.
     GreaterThanOrEqual,
 
-    /// Name ends with.
+    /// Ends with.
     LessThan,
 
-    /// Name ends with, or equal to.
+    /// Ends with, or equal to.
     LessThanOrEqual,
 
-    /// Name contains.
+    /// Contains.
     Like,
 }
 
@@ -117,8 +232,48 @@ impl TryFrom<&str> for ClanSearchFilterOperator {
 /// A custom filter to apply to a clan search.
 #[derive(Debug, Default, Deserialize)]
 pub struct ClanSearchFilter {
-    /// The name of the filter.
+    /// The filter on the clans' names.
+    ///
+    /// If omitted, clans of any name match.
+    #[serde(default)]
     pub name: ClanSearchFilterName,
+
+    /// The filter on the clans' tags, combined with the one on their names.
+    ///
+    /// The game never sends this; it's for companion clients.
+    #[serde(default)]
+    pub tag: Option<ClanSearchFilterName>,
+}
+
+impl ClanSearchFilter {
+    /// Convert the filter to a BSON filter on the clans.
+    pub fn to_filter(&self) -> mongodb::bson::Document {
+        let value = self.name.value.trim().to_lowercase();
+
+        // Clan names are actually returned from the API with a suffix
+        // indicating their platform of creation. This is so players
+        // in-game can know which platform a clan is from.
+        //
+        // We want clans of all platforms to be visible, but not cross-joinable,
+        // so we do support searching but will block joining in the other endpoints.
+        let (value, platform) = [("[ps3]", "Console"), ("[pc]", "Emulator")]
+            .into_iter()
+            .find_map(|(suffix, platform)| Some((value.strip_suffix(suffix)?.trim_end(), Some(platform))))
+            .unwrap_or((value.as_str(), None));
+
+        let mut filter = self.name.operator.to_filter("name", value);
+
+        if let Some(p) = platform {
+            filter.insert("platform", p);
+        }
+
+        // Narrow it down by tag, if asked to
+        if let Some(tag) = &self.tag {
+            filter.extend(tag.operator.to_filter("tag", tag.value.trim()));
+        }
+
+        filter
+    }
 }
 
 /// The inner filter's properties.
@@ -134,8 +289,8 @@ pub struct ClanSearchFilterName {
 }
 
 impl ClanSearchFilterOperator {
-    /// Convert the operator to a BSON filter.
-    pub fn to_filter(&self, value: &str) -> mongodb::bson::Document {
+    /// Convert the operator to a BSON filter on the given field, such as ``name`` or ``tag``.
+    pub fn to_filter(&self, field: &str, value: &str) -> mongodb::bson::Document {
         use mongodb::bson::doc;
 
         // Escape regex characters to prevent injection
@@ -144,13 +299,13 @@ impl ClanSearchFilterOperator {
         match self {
             // Case-insensitive exact match
             Self::Equal => doc! {
-                "name": {
+                field: {
                     "$regex": format!("^{escaped_value}$"),
                     "$options": "i"
                 }
             },
             Self::NotEqual => doc! {
-                "name": {
+                field: {
                     "$not": {
                         "$regex": format!("^{escaped_value}$"),
                         "$options": "i"
@@ -159,21 +314,21 @@ impl ClanSearchFilterOperator {
             },
             // Current "GreaterThan" behavior: Starts With (Case Insensitive)
             Self::GreaterThan | Self::GreaterThanOrEqual => doc! {
-                "name": {
+                field: {
                     "$regex": format!("^{escaped_value}"),
                     "$options": "i"
                 }
             },
             // Current "LessThan" behavior: Ends With (Case Insensitive)
             Self::LessThan | Self::LessThanOrEqual => doc! {
-                "name": {
+                field: {
                     "$regex": format!("{escaped_value}$"),
                     "$options": "i"
                 }
             },
             // Contains (Case Insensitive)
             Self::Like => doc! {
-                "name": {
+                field: {
                     "$regex": escaped_value,
                     "$options": "i"
                 }
@@ -203,6 +358,12 @@ impl<'de> Deserialize<'de> for ClanSearchFilterOperator {
 /// Request to get info about a clan.
 #[derive(Debug, Deserialize)]
 pub struct GetClanInfo {
+    /// A PSN ticket for the player viewing the clan, if any.
+    ///
+    /// When given, the player's own role and status are included.
+    #[serde(default)]
+    pub ticket: Option<Ticket>,
+
     /// The ID of the clan.
     pub id: Id,
 }
@@ -229,3 +390,127 @@ pub struct DisbandClan {
     /// The ID of the clan.
     pub id: Id,
 }
+
+/// Request to cancel the disband of a clan, within its grace period.
+#[derive(Debug, Deserialize)]
+pub struct CancelDisband {
+    /// A PSN ticket for authenticating the request.
+    pub ticket: Ticket,
+
+    /// The ID of the clan.
+    pub id: Id,
+}
+
+#[cfg(test)]
+mod tests {
+    //! Tests for turning clan requests into what's stored and queried.
+
+    use mongodb::bson::{doc, Bson, Document};
+    use serde::{
+        de::{value::Error, IntoDeserializer},
+        Deserialize,
+    };
+
+    use super::{ClanSearchFilter, ClanSearchFilterName, ClanSearchFilterOperator, ClanSort, CreateClan, GetClanList};
+    use crate::structs::{
+        entities::{
+            clan::Clan,
+            player::{Jid, Role},
+        },
+        ticket::Ticket,
+    };
+
+    /// Build a search filter on the clans' names.
+    fn search(operator: ClanSearchFilterOperator, value: &str) -> ClanSearchFilter {
+        ClanSearchFilter { name: ClanSearchFilterName { operator, value: value.to_string() }, tag: None }
+    }
+
+    /// Request a page of the clan list, of the given size.
+    fn list(max: i32) -> GetClanList {
+        GetClanList { ticket: Ticket::default(), start: 1, max, after: None, sort: None }
+    }
+
+    /// New clans are stored with their name and tag trimmed, led by their creator.
+    #[test]
+    fn create_clan() {
+        let ticket = Ticket { username: "player".to_string(), ..Default::default() };
+        let clan = Clan::from(CreateClan { ticket, name: "  My Clan \t".to_string(), tag: " TAG ".to_string() });
+
+        assert_eq!((clan.name.as_str(), clan.tag.as_str()), ("My Clan", "TAG"));
+        assert_eq!(clan.owner().map(|leader| (leader.jid.username.as_str(), leader.role)), Some(("player", Role::Leader)));
+    }
+
+    /// Players are listed the visible clans they're in, at least one at a time.
+    #[test]
+    fn clan_list() {
+        let jid = Jid { username: "player".to_string(), domain: "a1".to_string(), region: "us".to_string() };
+        let filter = list(10).filter(&jid);
+
+        assert_eq!(filter.get_str("members.jid"), Ok("player@a1.us.np.playstation.net"));
+        assert_eq!(filter.get_document("pending_approval"), Ok(&doc! { "$ne": true }));
+        assert_eq!(filter.get("disband_at"), Some(&Bson::Null));
+        assert_eq!(filter.get("title"), Some(&Bson::Null));
+
+        assert_eq!([-3, 0, 1, 10].map(|max| list(max).page_size()), [1, 1, 1, 10]);
+    }
+
+    /// The platform suffix shown after clan names is searched as the platform, not the name.
+    #[test]
+    fn search_platform_suffix() {
+        let name = |filter: &ClanSearchFilter| filter.to_filter().get_document("name").unwrap().get_str("$regex").unwrap().to_string();
+
+        let console = search(ClanSearchFilterOperator::Equal, " My Clan [PS3] ");
+        assert_eq!(name(&console), "^my clan$");
+        assert_eq!(console.to_filter().get_str("platform"), Ok("Console"));
+
+        let emulator = search(ClanSearchFilterOperator::Equal, "My Clan [PC]");
+        assert_eq!(name(&emulator), "^my clan$");
+        assert_eq!(emulator.to_filter().get_str("platform"), Ok("Emulator"));
+
+        let plain = search(ClanSearchFilterOperator::Equal, "My Clan [PS4]");
+        assert_eq!(name(&plain), r"^my clan \[ps4\]$");
+        assert!(!plain.to_filter().contains_key("platform"));
+    }
+
+    /// Each operator matches the value case-insensitively, with its regex characters escaped.
+    #[test]
+    fn search_operators() {
+        let pattern = |pattern: &str| doc! { "tag": { "$regex": pattern, "$options": "i" } };
+        let filter = |operator: &str| ClanSearchFilterOperator::try_from(operator).unwrap().to_filter("tag", "a.b*");
+
+        assert_eq!(filter("eq"), pattern(r"^a\.b\*$"));
+        assert_eq!(filter("ne"), doc! { "tag": { "$not": { "$regex": r"^a\.b\*$", "$options": "i" } } });
+        assert_eq!(filter("gt"), pattern(r"^a\.b\*"));
+        assert_eq!(filter("ge"), pattern(r"^a\.b\*"));
+        assert_eq!(filter("lt"), pattern(r"a\.b\*$"));
+        assert_eq!(filter("le"), pattern(r"a\.b\*$"));
+        assert_eq!(filter("lk"), pattern(r"a\.b\*"));
+        assert_eq!(ClanSearchFilterOperator::All.to_filter("tag", "a.b*"), Document::new());
+        assert!(ClanSearchFilterOperator::try_from("regex").is_err());
+    }
+
+    /// Tags narrow down the search on names.
+    #[test]
+    fn search_by_tag() {
+        let tag = || Some(ClanSearchFilterName { operator: ClanSearchFilterOperator::Equal, value: " TAG ".to_string() });
+
+        let filter = ClanSearchFilter { tag: tag(), ..search(ClanSearchFilterOperator::Like, "clan") }.to_filter();
+        assert_eq!(filter.get_document("name").unwrap().get_str("$regex"), Ok("clan"));
+        assert_eq!(filter.get_document("tag").unwrap().get_str("$regex"), Ok("^TAG$"));
+
+        let any_name = ClanSearchFilter { name: ClanSearchFilterName::default(), tag: tag() }.to_filter();
+        assert_eq!(any_name.keys().collect::<Vec<_>>(), ["tag"]);
+    }
+
+    /// Orders are named in snake case, and ties are broken by ID.
+    #[test]
+    fn clan_sort() {
+        let sort = |name: &str| ClanSort::deserialize(name.into_deserializer()).map(ClanSort::to_sort);
+
+        assert_eq!(sort("members_desc"), Ok::<_, Error>(doc! { "member_count": -1, "id": 1 }));
+        assert_eq!(sort("date_created_desc"), Ok(doc! { "date_created": -1, "id": 1 }));
+        assert_eq!(sort("name_asc"), Ok(doc! { "name": 1, "id": 1 }));
+        assert!(sort("MembersDesc").is_err());
+        assert!(sort("members").is_err());
+    }
+}
@@ -18,6 +18,19 @@ pub struct SendInvitation {
     pub jid: Jid,
 }
 
+/// Request to send an existing invitation to a player again.
+#[derive(Debug, Deserialize)]
+pub struct ResendInvitation {
+    /// A PSN ticket for authenticating the request.
+    pub ticket: Ticket,
+
+    /// The ID of the clan.
+    pub id: Id,
+
+    /// The JID of the player to remind.
+    pub jid: Jid,
+}
+
 /// Request to cancel an invitation to a player.
 #[derive(Debug, Deserialize)]
 pub struct CancelInvitation {
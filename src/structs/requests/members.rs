@@ -1,8 +1,8 @@
 //! Request structs for member related requests.
 
-use serde::Deserialize;
+use serde::{de::IgnoredAny, Deserialize};
 
-use crate::structs::{entities::clan::Id, ticket::Ticket};
+use crate::structs::{entities::{bin_data::BinDataType, clan::Id, player::{Player, Role}}, ticket::Ticket};
 
 /// Request to get a list of members.
 #[derive(Debug, Deserialize)]
@@ -14,7 +14,8 @@ pub struct GetMemberList {
     /// The ID of the clan.
     pub id: Id,
 
-    /// How many members to skip.
+    /// Position of the first member to return, counting from 1.
+    /// See: [`crate::utils::pagination`]
     pub start: i32,
 
     /// How many members to return.
@@ -60,7 +61,21 @@ pub struct ChangeMemberRole {
     pub jid: String,
 
     /// The new role for the player.
-    pub role: u32,
+    #[serde(deserialize_with = "Role::deserialize_lenient")]
+    pub role: Role,
+}
+
+/// Request to hand a clan's leadership to another member.
+#[derive(Debug, Deserialize)]
+pub struct TransferLeadership {
+    /// A PSN ticket for authenticating the request.
+    pub ticket: Ticket,
+
+    /// The ID of the clan.
+    pub id: Id,
+
+    /// The JID of the player to make the leader.
+    pub jid: String,
 }
 
 /// Request to update a member's information.
@@ -85,8 +100,35 @@ pub struct UpdateMemberInfo {
     /// Unknown use.
     pub bin_attr1: String,
 
+    /// The kind of data held by ``bin-attr1``, checked before it's stored.
+    ///
+    /// If omitted, the data is left untyped.
+    #[serde(default)]
+    pub bin_data_type: Option<BinDataType>,
+
     /// Unknown use.
     pub size: u32,
+
+    /// A role, if the game sends one.
+    ///
+    /// It's deliberately ignored: roles are only changed with ``change_member_role``,
+    /// which checks the author's permissions, so members can't promote themselves.
+    #[serde(default)]
+    pub role: Option<IgnoredAny>,
+}
+
+impl UpdateMemberInfo {
+    /// Update a member's info with the request's.
+    ///
+    /// The member's role is left as it is, even if the game sent one.
+    pub fn apply_to(self, member: &mut Player) {
+        member.online_name = self.onlinename;
+        member.description = self.description;
+        member.allow_msg = self.allowmsg;
+        member.bin_data = self.bin_attr1;
+        member.bin_data_type = self.bin_data_type.unwrap_or_default();
+        member.size = self.size;
+    }
 }
 
 /// Request to join a clan.
@@ -107,4 +149,42 @@ pub struct LeaveClan {
 
     /// The ID of the clan.
     pub id: Id,
+}
+
+#[cfg(test)]
+mod tests {
+    //! Tests for applying member requests.
+
+    use serde::de::IgnoredAny;
+
+    use super::UpdateMemberInfo;
+    use crate::structs::{
+        entities::{bin_data::BinDataType, player::{Player, Role}},
+        ticket::Ticket,
+    };
+
+    /// Members update their own info, but never their role.
+    #[test]
+    fn update_member_info() {
+        let request = UpdateMemberInfo {
+            ticket: Ticket::default(),
+            id: 1,
+            onlinename: "Player".to_string(),
+            description: "Hello".to_string(),
+            allowmsg: true,
+            bin_attr1: "AAEC".to_string(),
+            bin_data_type: Some(BinDataType::Base64),
+            size: 3,
+            role: Some(IgnoredAny),
+        };
+        let mut member = Player { role: Role::Member, ..Default::default() };
+
+        request.apply_to(&mut member);
+
+        assert_eq!(member.role, Role::Member);
+        assert_eq!((member.online_name.as_str(), member.description.as_str()), ("Player", "Hello"));
+        assert!(member.allow_msg);
+        assert_eq!((member.bin_data.as_str(), member.bin_data_type), ("AAEC", BinDataType::Base64));
+        assert_eq!(member.size, 3);
+    }
 }
\ No newline at end of file
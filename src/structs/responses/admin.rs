@@ -1,9 +1,19 @@
 //! Response structs for the Admin API endpoints.
 
 use actix_web::{body::BoxBody, HttpResponse, Responder};
+use mongodb::bson::oid::ObjectId;
 use serde::Serialize;
 
-use super::error::ErrorCode;
+use crate::{
+    structs::entities::{
+        clan::{Clan, Id, Platform, MAX_CLAN_BLACKLIST_SIZE},
+        failed_write::FailedWrite,
+        player::{Player, Role, Status},
+    },
+    utils,
+};
+
+use super::error::{ErrorCode, SUCCESS};
 
 /// Base response structure for the Admin API.
 #[derive(Debug, Serialize)]
@@ -41,4 +51,731 @@ impl Responder for Response {
     fn respond_to(self, _req: &actix_web::HttpRequest) -> actix_web::HttpResponse {
         self.into()
     }
-}
\ No newline at end of file
+}
+
+/// Response for the Admin API carrying data, next to the status of the request.
+///
+/// The data's fields are flattened into the response, so that it reads
+/// like ``{ "statusCode": 0, "clans": [...] }``. If the request fails,
+/// the data is left empty.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DataResponse<T> {
+    /// The status of the request.
+    pub status_code: u8,
+
+    /// The data of the response.
+    #[serde(flatten)]
+    pub data: T,
+}
+
+impl<T: Default> From<u8> for DataResponse<T> {
+    fn from(code: u8) -> Self {
+        Self {
+            status_code: code,
+            data: T::default(),
+        }
+    }
+}
+
+impl<T: Default> From<ErrorCode> for DataResponse<T> {
+    fn from(code: ErrorCode) -> Self {
+        Self::from(code as u8)
+    }
+}
+
+impl<T: Serialize> Responder for DataResponse<T> {
+    type Body = BoxBody;
+
+    fn respond_to(self, _req: &actix_web::HttpRequest) -> actix_web::HttpResponse {
+        HttpResponse::Ok().json(self)
+    }
+}
+
+/// A validation rule a clan's name or tag is checked against.
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Rule {
+    /// The value fits in the maximum length.
+    Length,
+
+    /// The value isn't blank and has no control characters.
+    Charset,
+
+    /// The value contains no blocked words.
+    Profanity,
+
+    /// The value isn't a reserved name.
+    Reserved,
+
+    /// No other clan uses the value.
+    Duplicate,
+}
+
+/// The outcome of checking a single [`Rule`].
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RuleResult {
+    /// The field that was checked (``name`` or ``tag``).
+    pub field: &'static str,
+
+    /// The rule that was checked.
+    pub rule: Rule,
+
+    /// Whether the value passed the rule.
+    pub passed: bool,
+
+    /// The error the game would receive, if the rule failed.
+    pub status_code: u8,
+}
+
+/// Response for a clan name validation.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NameValidation {
+    /// The status of the request.
+    pub status_code: u8,
+
+    /// Whether every rule passed.
+    pub valid: bool,
+
+    /// The outcome of every rule.
+    pub rules: Vec<RuleResult>,
+}
+
+impl Default for NameValidation {
+    fn default() -> Self {
+        Self {
+            status_code: SUCCESS,
+            valid: true,
+            rules: Vec::new(),
+        }
+    }
+}
+
+impl NameValidation {
+    /// Record the outcome of a rule.
+    pub fn push(&mut self, field: &'static str, rule: Rule, result: Result<(), ErrorCode>) {
+        let status_code = result.err().map_or(SUCCESS, |code| code as u8);
+
+        self.valid &= status_code == SUCCESS;
+        self.rules.push(RuleResult {
+            field,
+            rule,
+            passed: status_code == SUCCESS,
+            status_code,
+        });
+    }
+
+    /// Returns the error of the first rule that failed, if any.
+    pub fn first_error(&self) -> Option<u8> {
+        self.rules.iter().find(|rule| !rule.passed).map(|rule| rule.status_code)
+    }
+}
+
+impl Responder for NameValidation {
+    type Body = BoxBody;
+
+    fn respond_to(self, _req: &actix_web::HttpRequest) -> actix_web::HttpResponse {
+        HttpResponse::Ok().json(self)
+    }
+}
+
+/// Data of the response for moving a clan to another platform.
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PlatformTransfer {
+    /// JIDs of the members that are not on the target platform.
+    pub offending: Vec<String>,
+}
+
+/// Data of the response for migrating a clan and its members to another platform.
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PlatformMigration {
+    /// JIDs the members were migrated to, on the target platform.
+    pub migrated: Vec<String>,
+
+    /// JIDs of the members without an account on the target platform.
+    pub offending: Vec<String>,
+}
+
+/// Data of the response for merging a clan into another.
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ClanMerge {
+    /// JIDs of the members that were moved over.
+    pub merged: Vec<String>,
+
+    /// JIDs of the players that were left out, as they're already
+    /// in the clan, blacklisted from it, or on another platform.
+    pub conflicts: Vec<String>,
+
+    /// JIDs of the members that didn't fit within the member limit.
+    pub overflow: Vec<String>,
+}
+
+impl ClanMerge {
+    /// Move the other clan's members, blacklist and announcements into the clan.
+    ///
+    /// The clan keeps its leader, so the other clan's leader becomes a sub-leader.
+    /// If the members wouldn't fit, [`ErrorCode::ClanMemberLimitReached`]
+    /// is returned and the clan mustn't be saved.
+    pub fn perform(&mut self, clan: &mut Clan, other: &mut Clan) -> Result<(), ErrorCode> {
+        for mut member in std::mem::take(&mut other.members) {
+            let conflicting = clan.status_of(&member.jid).is_some()
+                || clan.is_blacklisted(&member.jid)
+                || !clan.accepts_platform(&Platform::from(&member.jid));
+
+            if conflicting {
+                self.conflicts.push(member.jid.to_string());
+                continue;
+            }
+
+            // Invitations and requests don't count towards the limit
+            if member.status == Status::Member {
+                if clan.is_full() {
+                    self.overflow.push(member.jid.to_string());
+                    continue;
+                }
+
+                self.merged.push(member.jid.to_string());
+            }
+
+            // A clan can only have a single leader
+            if member.role == Role::Leader {
+                member.role = Role::SubLeader;
+            }
+
+            clan.members.push(member);
+        }
+
+        if !self.overflow.is_empty() {
+            self.merged.clear();
+            return Err(ErrorCode::ClanMemberLimitReached);
+        }
+
+        // Members of the clan can't be blacklisted from it
+        for jid in std::mem::take(&mut other.blacklist) {
+            if clan.blacklist.len() >= MAX_CLAN_BLACKLIST_SIZE {
+                break;
+            }
+
+            if !clan.is_blacklisted(&jid) && clan.status_of(&jid).is_none() {
+                clan.blacklist.push(jid);
+            }
+        }
+
+        clan.announcements.append(&mut other.announcements);
+
+        Ok(())
+    }
+}
+
+/// A clan that failed to be saved.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FailedWriteInfo {
+    /// The ID of the record.
+    pub id: String,
+
+    /// The ID of the clan.
+    pub clan_id: Id,
+
+    /// The error that caused the write to fail.
+    pub error: String,
+
+    /// The date the write failed, in ISO 8601 format.
+    pub date: String,
+}
+
+impl From<FailedWrite> for FailedWriteInfo {
+    fn from(failed_write: FailedWrite) -> Self {
+        Self {
+            id: failed_write.id.map(ObjectId::to_hex).unwrap_or_default(),
+            clan_id: failed_write.clan.id(),
+            error: failed_write.error,
+            date: utils::date_format::iso8601(&failed_write.date),
+        }
+    }
+}
+
+/// Data of the response for listing the clans that failed to be saved.
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FailedWrites {
+    /// The failed writes.
+    pub failed_writes: Vec<FailedWriteInfo>,
+}
+
+/// The outcome of importing a single blacklist entry.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BlacklistImportEntry {
+    /// The JID, as it was sent.
+    pub jid: String,
+
+    /// Whether the player was blacklisted.
+    pub imported: bool,
+
+    /// The reason the player wasn't blacklisted, if any.
+    pub status_code: u8,
+}
+
+/// Data of the response for importing players to a clan's blacklist.
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BlacklistImport {
+    /// The outcome of every entry, in the order they were sent.
+    pub entries: Vec<BlacklistImportEntry>,
+}
+
+impl BlacklistImport {
+    /// Record the outcome of an entry.
+    pub fn push(&mut self, jid: String, result: Result<(), ErrorCode>) {
+        let status_code = result.err().map_or(SUCCESS, |code| code as u8);
+
+        self.entries.push(BlacklistImportEntry {
+            jid,
+            imported: status_code == SUCCESS,
+            status_code,
+        });
+    }
+}
+
+/// Data of the response for counting the clans with broken invariants.
+///
+/// A clan may be counted in more than one category.
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Diagnostics {
+    /// Number of clans that were checked.
+    pub clans: u64,
+
+    /// Number of clans without a leader.
+    pub no_leader: u64,
+
+    /// Number of clans with more than one leader.
+    pub multiple_leaders: u64,
+
+    /// Number of clans listing the same player more than once.
+    pub duplicate_members: u64,
+
+    /// Number of clans that blacklisted one of their members.
+    pub blacklisted_members: u64,
+}
+
+impl Diagnostics {
+    /// Check a clan, counting it in every category it falls into.
+    pub fn record(&mut self, clan: &Clan) {
+        let members = || clan.members.iter().filter(|p| p.status == Status::Member);
+        let leaders = members().filter(|p| p.role == Role::Leader).count();
+
+        self.clans += 1;
+        self.no_leader += u64::from(leaders == 0);
+        self.multiple_leaders += u64::from(leaders > 1);
+        self.duplicate_members += u64::from(
+            clan.members
+                .iter()
+                .enumerate()
+                .any(|(i, p)| clan.members[..i].iter().any(|other| other.jid == p.jid)),
+        );
+        self.blacklisted_members += u64::from(members().any(|p| clan.is_blacklisted(&p.jid)));
+    }
+}
+
+/// Summary of a clan.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ClanSummary {
+    /// The ID of the clan.
+    pub id: Id,
+
+    /// The name of the clan.
+    pub name: String,
+
+    /// The tag of the clan.
+    pub tag: String,
+
+    /// The platform the clan was created for.
+    pub platform: Platform,
+
+    /// Number of members in the clan.
+    pub members: usize,
+}
+
+impl From<Clan> for ClanSummary {
+    fn from(clan: Clan) -> Self {
+        Self {
+            id: clan.id(),
+            members: clan.members.iter().filter(|p| p.status == Status::Member).count(),
+            name: clan.name,
+            tag: clan.tag,
+            platform: clan.platform,
+        }
+    }
+}
+
+/// Data of the response for listing the clans.
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Clans {
+    /// The clans.
+    pub clans: Vec<ClanSummary>,
+}
+
+/// A player's membership in a clan.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PlayerClan {
+    /// The ID of the clan.
+    pub id: Id,
+
+    /// The name of the clan.
+    pub name: String,
+
+    /// The tag of the clan.
+    pub tag: String,
+
+    /// The platform the clan was created for.
+    pub platform: Platform,
+
+    /// The player's JID, as it's known to the clan.
+    pub jid: String,
+
+    /// The player's role in the clan.
+    pub role: Role,
+
+    /// The player's status in the clan.
+    pub status: Status,
+}
+
+/// Data of the response for listing the clans a player is in.
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PlayerClans {
+    /// The clans the player is in.
+    pub clans: Vec<PlayerClan>,
+}
+
+impl PlayerClans {
+    /// Record the player's membership in a clan, for every account they have in it.
+    pub fn push(&mut self, clan: &Clan, username: &str) {
+        for player in clan.members.iter().filter(|p| p.jid.username == username) {
+            self.clans.push(PlayerClan {
+                id: clan.id(),
+                name: clan.name.clone(),
+                tag: clan.tag.clone(),
+                platform: clan.platform.clone(),
+                jid: player.jid.to_string(),
+                role: player.role,
+                status: player.status,
+            });
+        }
+    }
+}
+
+/// A member of a clan, as it's stored.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ClanMember {
+    /// The member's JID.
+    pub jid: String,
+
+    /// The member's role in the clan.
+    pub role: Role,
+
+    /// The member's status in the clan.
+    pub status: Status,
+
+    /// The member's display name.
+    pub online_name: String,
+
+    /// The last time the member interacted with the clan, in ISO 8601 format.
+    pub last_seen: Option<String>,
+}
+
+impl From<Player> for ClanMember {
+    fn from(player: Player) -> Self {
+        Self {
+            jid: player.jid.to_string(),
+            role: player.role,
+            status: player.status,
+            online_name: player.online_name,
+            last_seen: player.last_seen.as_ref().map(utils::date_format::iso8601),
+        }
+    }
+}
+
+/// Data of the response for listing every member of a clan, including the ones the game hides.
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ClanMembers {
+    /// The members, in the order they're stored.
+    pub members: Vec<ClanMember>,
+}
+
+#[cfg(test)]
+mod tests {
+    //! Tests for building the Admin API's responses.
+
+    use actix_web::{body::MessageBody, test::TestRequest, Responder};
+    use chrono::{TimeZone, Utc};
+    use mongodb::bson::oid::ObjectId;
+
+    use super::{BlacklistImport, ClanMember, ClanMembers, ClanMerge, ClanSummary, DataResponse, Diagnostics, FailedWriteInfo, NameValidation, PlatformTransfer, PlayerClans, Rule, SUCCESS};
+    use crate::structs::{
+        entities::{
+            clan::{Clan, Platform},
+            failed_write::FailedWrite,
+            player::{Jid, Player, Role, Status},
+        },
+        responses::error::ErrorCode,
+    };
+
+    /// A name is valid until a rule fails, and the first failure is what the game would get.
+    #[test]
+    fn name_validation() {
+        let mut validation = NameValidation::default();
+        validation.push("name", Rule::Length, Ok(()));
+        assert!(validation.valid);
+        assert_eq!(validation.first_error(), None);
+
+        validation.push("name", Rule::Profanity, Err(ErrorCode::VulgarWordsPosted));
+        validation.push("tag", Rule::Duplicate, Err(ErrorCode::DuplicatedClanTag));
+        validation.push("tag", Rule::Charset, Ok(()));
+
+        assert!(!validation.valid);
+        assert_eq!(validation.first_error(), Some(ErrorCode::VulgarWordsPosted as u8));
+        assert_eq!(validation.status_code, SUCCESS);
+
+        let passed: Vec<_> = validation.rules.iter().map(|rule| rule.passed).collect();
+        assert_eq!(passed, [true, false, false, true]);
+    }
+
+    /// Failed writes are listed with their record's ID, the clan's, and an ISO 8601 date.
+    #[test]
+    fn failed_write_info() {
+        let id = ObjectId::new();
+        let clan = Clan::default();
+        let info = FailedWriteInfo::from(FailedWrite {
+            id: Some(id),
+            clan: clan.clone(),
+            error: "Connection timed out".to_string(),
+            date: Utc.timestamp_opt(1_700_000_000, 0).unwrap(),
+        });
+
+        assert_eq!(info.id, id.to_hex());
+        assert_eq!(info.clan_id, clan.id());
+        assert_eq!(info.error, "Connection timed out");
+        assert_eq!(info.date, "2023-11-14T22:13:20Z");
+    }
+
+    /// Render a response as the JSON it's sent as.
+    fn json(response: impl Responder) -> String {
+        let body = response.respond_to(&TestRequest::default().to_http_request()).into_body();
+        String::from_utf8_lossy(&body.try_into_bytes().unwrap_or_default()).into_owned()
+    }
+
+    /// Members are listed as they're stored, including the ones the game hides.
+    #[test]
+    fn clan_members() {
+        let invited = Player {
+            jid: Jid { username: "player".to_string(), domain: "a1".to_string(), region: "us".to_string() },
+            role: Role::NonMember,
+            status: Status::Invited,
+            online_name: "Player".to_string(),
+            last_seen: Some(Utc.with_ymd_and_hms(2024, 1, 2, 3, 4, 5).unwrap()),
+            ..Default::default()
+        };
+
+        let mut response = DataResponse::<ClanMembers>::from(SUCCESS);
+        response.data.members.push(ClanMember::from(invited));
+
+        assert_eq!(
+            json(response),
+            r#"{"statusCode":0,"members":[{"jid":"player@a1.us.np.playstation.net","role":"NonMember","status":"Invited","onlineName":"Player","lastSeen":"2024-01-02T03:04:05Z"}]}"#
+        );
+        assert_eq!(json(DataResponse::<ClanMembers>::from(ErrorCode::NoSuchClan)), r#"{"statusCode":48,"members":[]}"#);
+    }
+
+    /// The data is flattened next to the status, and left empty on failure.
+    #[test]
+    fn data_response() {
+        let mut success = DataResponse::<PlatformTransfer>::from(SUCCESS);
+        success.data.offending.push("player@a1.us".to_string());
+
+        assert_eq!(json(success), r#"{"statusCode":0,"offending":["player@a1.us"]}"#);
+        assert_eq!(
+            json(DataResponse::<PlatformTransfer>::from(ErrorCode::NoSuchClan)),
+            format!(r#"{{"statusCode":{},"offending":[]}}"#, ErrorCode::NoSuchClan as u8)
+        );
+    }
+
+    /// Every entry of an import is reported in order, whether it was imported or not.
+    #[test]
+    fn blacklist_import() {
+        let mut import = BlacklistImport::default();
+        import.push("player@a1.us".to_string(), Ok(()));
+        import.push("invalid".to_string(), Err(ErrorCode::InvalidNpId));
+
+        let outcomes: Vec<_> = import.entries.iter().map(|e| (e.jid.as_str(), e.imported, e.status_code)).collect();
+        assert_eq!(outcomes, [("player@a1.us", true, SUCCESS), ("invalid", false, ErrorCode::InvalidNpId as u8)]);
+    }
+
+    /// Every broken invariant of a clan is counted, and healthy clans are only counted as checked.
+    #[test]
+    fn diagnostics() {
+        let player = |username: &str, role| Player {
+            jid: Jid { username: username.to_string(), domain: "a1".to_string(), region: "us".to_string() },
+            role,
+            status: Status::Member,
+            ..Default::default()
+        };
+
+        let healthy = Clan { members: vec![player("leader", Role::Leader), player("member", Role::Member)], ..Default::default() };
+        let leaderless = Clan { members: vec![player("member", Role::Member)], ..Default::default() };
+        let corrupt = Clan {
+            members: vec![player("leader", Role::Leader), player("other", Role::Leader), player("other", Role::Member)],
+            blacklist: vec![player("other", Role::Member).jid],
+            ..Default::default()
+        };
+
+        let mut diagnostics = Diagnostics::default();
+        for clan in [&healthy, &leaderless, &corrupt] {
+            diagnostics.record(clan);
+        }
+
+        assert_eq!(diagnostics.clans, 3);
+        assert_eq!(diagnostics.no_leader, 1);
+        assert_eq!(diagnostics.multiple_leaders, 1);
+        assert_eq!(diagnostics.duplicate_members, 1);
+        assert_eq!(diagnostics.blacklisted_members, 1);
+    }
+
+    /// Merged clans keep a single leader, and leave out the players that conflict with the clan.
+    #[test]
+    fn clan_merge() {
+        let jid = |username: &str, domain: &str| Jid { username: username.to_string(), domain: domain.to_string(), region: "us".to_string() };
+        let player = |username: &str, role, status| Player { jid: jid(username, "a1"), role, status, ..Default::default() };
+
+        let mut clan = Clan {
+            platform: Platform::Console,
+            members: vec![player("leader", Role::Leader, Status::Member)],
+            blacklist: vec![jid("banned", "a1")],
+            ..Default::default()
+        };
+        let mut other = Clan {
+            members: vec![
+                player("boss", Role::Leader, Status::Member),
+                player("leader", Role::Member, Status::Member),
+                player("banned", Role::Member, Status::Member),
+                Player { jid: Jid { region: "br".to_string(), ..jid("emulated", "un") }, ..player("emulated", Role::Member, Status::Member) },
+                player("invited", Role::NonMember, Status::Invited),
+            ],
+            blacklist: vec![jid("troll", "a1"), jid("boss", "a1")],
+            announcements: vec![Default::default()],
+            ..Default::default()
+        };
+
+        let mut merge = ClanMerge::default();
+        assert_eq!(merge.perform(&mut clan, &mut other), Ok(()));
+
+        assert_eq!(merge.merged, ["boss@a1.us.np.playstation.net"]);
+        assert_eq!(
+            merge.conflicts,
+            ["leader@a1.us.np.playstation.net", "banned@a1.us.np.playstation.net", "emulated@un.br.np.playstation.net"]
+        );
+        assert!(merge.overflow.is_empty());
+
+        let roles: Vec<_> = clan.members.iter().map(|p| (p.jid.username.as_str(), p.role)).collect();
+        assert_eq!(roles, [("leader", Role::Leader), ("boss", Role::SubLeader), ("invited", Role::NonMember)]);
+
+        let blacklist: Vec<_> = clan.blacklist.iter().map(|jid| jid.username.as_str()).collect();
+        assert_eq!(blacklist, ["banned", "troll"]);
+        assert_eq!(clan.announcements.len(), 1);
+    }
+
+    /// Only actual members are counted in a clan's summary.
+    #[test]
+    fn clan_summary() {
+        let player = |username: &str, status| Player {
+            jid: Jid { username: username.to_string(), domain: "a1".to_string(), region: "us".to_string() },
+            role: Role::Member,
+            status,
+            ..Default::default()
+        };
+
+        let clan = Clan {
+            name: "Clan".to_string(),
+            platform: Platform::Emulator,
+            members: vec![player("member", Status::Member), player("pending", Status::Pending), player("invited", Status::Invited)],
+            ..Default::default()
+        };
+
+        let summary = ClanSummary::from(clan.clone());
+        assert_eq!(summary.id, clan.id());
+        assert_eq!(summary.name, "Clan");
+        assert_eq!(summary.platform, Platform::Emulator);
+        assert_eq!(summary.members, 1);
+    }
+
+    /// Every account of the player is listed, in every clan they're in.
+    #[test]
+    fn player_clans() {
+        let player = |username: &str, domain: &str, role, status| Player {
+            jid: Jid { username: username.to_string(), domain: domain.to_string(), region: "us".to_string() },
+            role,
+            status,
+            ..Default::default()
+        };
+
+        let first = Clan {
+            members: vec![player("player", "a1", Role::Leader, Status::Member), player("other", "a1", Role::Member, Status::Member)],
+            ..Default::default()
+        };
+        let second = Clan {
+            members: vec![player("player", "un", Role::NonMember, Status::Pending), player("player", "a1", Role::Member, Status::Member)],
+            ..Default::default()
+        };
+        let unrelated = Clan { members: vec![player("other", "a1", Role::Leader, Status::Member)], ..Default::default() };
+
+        let mut clans = PlayerClans::default();
+        for clan in [&first, &second, &unrelated] {
+            clans.push(clan, "player");
+        }
+
+        let memberships: Vec<_> = clans.clans.iter().map(|c| (c.id, c.jid.as_str(), c.role, c.status)).collect();
+        assert_eq!(
+            memberships,
+            [
+                (first.id(), "player@a1.us.np.playstation.net", Role::Leader, Status::Member),
+                (second.id(), "player@un.us.np.playstation.net", Role::NonMember, Status::Pending),
+                (second.id(), "player@a1.us.np.playstation.net", Role::Member, Status::Member),
+            ]
+        );
+    }
+
+    /// Every failed rule is sent together, so that they can all be fixed at once.
+    #[test]
+    fn name_validation_failures() {
+        let mut validation = NameValidation::default();
+        validation.push("name", Rule::Length, Err(ErrorCode::PermissionDenied));
+        validation.push("name", Rule::Charset, Ok(()));
+        validation.push("tag", Rule::Duplicate, Err(ErrorCode::DuplicatedClanTag));
+
+        let rule = |field: &str, rule: &str, passed: bool, code: u8| {
+            format!(r#"{{"field":"{field}","rule":"{rule}","passed":{passed},"statusCode":{code}}}"#)
+        };
+
+        assert_eq!(
+            json(validation),
+            format!(
+                r#"{{"statusCode":0,"valid":false,"rules":[{},{},{}]}}"#,
+                rule("name", "length", false, ErrorCode::PermissionDenied as u8),
+                rule("name", "charset", true, SUCCESS),
+                rule("tag", "duplicate", false, ErrorCode::DuplicatedClanTag as u8),
+            )
+        );
+    }
+}
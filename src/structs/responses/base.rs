@@ -10,11 +10,14 @@ use crate::{structs::responses::error::SUCCESS, utils::xml_format::ToXML};
 
 use super::error::ErrorCode;
 
-/// Default headers for the response.
-const HEADERS: [(&str, &str); 3] = [
-    ("Message-Type", "x-ps3-clan"),
-    ("Version", "1.00"),
-    ("Content-Type", "application/x-ps3-clan"),
+/// Headers for the response, as `(name, environment variable, default value)`.
+///
+/// Different builds of the game may expect different values,
+/// so each one can be overridden by its environment variable.
+const HEADERS: [(&str, &str, &str); 3] = [
+    ("Message-Type", "RESPONSE_MESSAGE_TYPE", "x-ps3-clan"),
+    ("Version", "RESPONSE_VERSION", "1.00"),
+    ("Content-Type", "RESPONSE_CONTENT_TYPE", "application/x-ps3-clan"),
 ];
 
 /// A generic clan response, with a status code and content.
@@ -62,8 +65,9 @@ impl<T: ToXML + Debug> Responder for Response<T> {
 
     fn respond_to(self, _: &actix_web::HttpRequest) -> actix_web::HttpResponse<Self::Body> {
         let mut builder = actix_web::HttpResponse::Ok();
-        for (key, value) in &HEADERS {
-            builder.append_header((*key, *value));
+        for (key, env, default) in HEADERS {
+            let value = std::env::var(env).unwrap_or_else(|_| default.to_string());
+            builder.append_header((key, value));
         }
 
         // DEBUG: print the XML's contents
@@ -150,6 +154,21 @@ impl<T: ToXML> ToXML for Content<T> {
 ///     ...
 /// </list>
 /// ```
+///
+/// When paging with cursors, the cursor to the next page is included,
+/// if there is one:
+///
+/// ```xml
+/// <list results="{results}" total="{total}" next="{next}">
+///     ...
+/// </list>
+/// ```
+///
+/// An empty list keeps the same shape, rather than a self-closing tag:
+///
+/// ```xml
+/// <list results="0" total="{total}"></list>
+/// ```
 #[derive(Debug)]
 pub struct List<T: ToXML> {
     /// Number of items in the current response.
@@ -160,6 +179,9 @@ pub struct List<T: ToXML> {
 
     /// List of items.
     pub items: Vec<T>,
+
+    /// Cursor to the next page of items, if paging with cursors.
+    pub next: Option<String>,
 }
 
 impl<T: ToXML> ToXML for List<T> {
@@ -176,11 +198,21 @@ impl<T: ToXML> ToXML for List<T> {
         let results = self.results.to_string();
         let total = self.total.to_string();
 
-        let element = XmlEvent::start_element("list")
+        let mut element = XmlEvent::start_element("list")
             .attr("results", &results)
             .attr("total", &total);
+
+        if let Some(next) = &self.next {
+            element = element.attr("next", next);
+        }
+
         writer.write(element).ok();
 
+        // Write nothing explicitly, so an empty list isn't turned into a self-closing tag
+        if self.items.is_empty() {
+            writer.write(XmlEvent::characters("")).ok();
+        }
+
         for item in &self.items {
             writer.write(XmlEvent::characters(&item.to_xml())).ok();
         }
@@ -191,3 +223,51 @@ impl<T: ToXML> ToXML for List<T> {
         String::from_utf8(result).unwrap()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    //! Tests for the responses' XML and headers.
+
+    use actix_web::{test::TestRequest, Responder};
+
+    use super::{Content, List, Response};
+    use crate::{
+        structs::responses::entities::{BlacklistStatus, CountEntity},
+        utils::xml_format::ToXML,
+    };
+
+    /// Each header keeps its default value unless overridden.
+    ///
+    /// The headers are read from the environment, so every case runs in this single test.
+    #[test]
+    fn headers() {
+        let header = |response: &actix_web::HttpResponse, name: &str| {
+            response.headers().get(name).and_then(|value| value.to_str().ok()).map(str::to_string)
+        };
+
+        std::env::set_var("RESPONSE_VERSION", "2.00");
+        let response = Response::<()>::success(Content::Empty).respond_to(&TestRequest::default().to_http_request());
+        std::env::remove_var("RESPONSE_VERSION");
+
+        assert_eq!(header(&response, "Version").as_deref(), Some("2.00"));
+        assert_eq!(header(&response, "Message-Type").as_deref(), Some("x-ps3-clan"));
+        assert_eq!(header(&response, "Content-Type").as_deref(), Some("application/x-ps3-clan"));
+    }
+
+    /// Empty lists keep their opening and closing tags, as the game doesn't read self-closing ones.
+    #[test]
+    fn empty_list() {
+        let list = List::<BlacklistStatus> { results: 0, total: 3, items: Vec::new(), next: None };
+        assert_eq!(list.to_xml(), r#"<list results="0" total="3"></list>"#);
+
+        let response = Response::success(Content::List(list));
+        assert!(response.to_xml().ends_with(r#"<clan result="00"><list results="0" total="3"></list></clan>"#));
+    }
+
+    /// Counts are sent as a single item, such as the size of a blacklist.
+    #[test]
+    fn count_item() {
+        let response = Response::success(Content::Item(CountEntity::from(2)));
+        assert!(response.to_xml().ends_with(r#"<clan result="00"><count>2</count></clan>"#));
+    }
+}
@@ -0,0 +1,110 @@
+//! Response structs for the server capabilities endpoint.
+//!
+//! Unlike the rest of the API, this is meant for companion clients
+//! rather than the game, so it is serialized as JSON.
+
+use std::collections::BTreeMap;
+
+use actix_web::{body::BoxBody, HttpResponse, Responder};
+use serde::Serialize;
+
+use crate::structs::entities::{
+    clan::{
+        MAX_CLAN_BLACKLIST_SIZE, MAX_CLAN_DESCRIPTION_LENGTH, MAX_CLAN_MEMBERSHIP, MAX_CLAN_NAME_LENGTH,
+        MAX_CLAN_OWNERSHIP, MAX_CLAN_TAG_LENGTH,
+    },
+    permission::Operation,
+    player::Role,
+};
+
+/// Description of what this server supports.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Capabilities {
+    /// Version of the server, as reported by the crate.
+    pub version: &'static str,
+
+    /// Optional features the server was compiled with.
+    pub features: Vec<&'static str>,
+
+    /// Limits enforced by the server.
+    pub limits: Limits,
+}
+
+/// Limits enforced by the server.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Limits {
+    /// Maximum number of clans a player can be a member of.
+    pub membership: usize,
+
+    /// Maximum number of clans a player can own.
+    pub ownership: usize,
+
+    /// Maximum length of a clan's name.
+    pub name_length: usize,
+
+    /// Maximum length of a clan's tag.
+    pub tag_length: usize,
+
+    /// Maximum length of a clan's description.
+    pub description_length: usize,
+
+    /// Maximum number of players a clan can blacklist.
+    pub blacklist: usize,
+}
+
+impl Default for Capabilities {
+    fn default() -> Self {
+        let mut features = Vec::new();
+
+        if cfg!(feature = "admin") {
+            features.push("admin");
+        }
+
+        Self {
+            version: env!("CARGO_PKG_VERSION"),
+            features,
+            limits: Limits {
+                membership: MAX_CLAN_MEMBERSHIP,
+                ownership: MAX_CLAN_OWNERSHIP,
+                name_length: MAX_CLAN_NAME_LENGTH,
+                tag_length: MAX_CLAN_TAG_LENGTH,
+                description_length: MAX_CLAN_DESCRIPTION_LENGTH,
+                blacklist: MAX_CLAN_BLACKLIST_SIZE,
+            },
+        }
+    }
+}
+
+impl Responder for Capabilities {
+    type Body = BoxBody;
+
+    fn respond_to(self, _req: &actix_web::HttpRequest) -> HttpResponse {
+        HttpResponse::Ok().json(self)
+    }
+}
+
+/// The minimum role each operation requires, as the server enforces it.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RolePermissions {
+    /// The minimum role of each operation, e.g. ``{ "kick_member": "SubLeader" }``
+    pub roles: BTreeMap<Operation, Role>,
+}
+
+impl Default for RolePermissions {
+    fn default() -> Self {
+        Self {
+            roles: Operation::ALL.into_iter().map(|op| (op, op.min_role())).collect(),
+        }
+    }
+}
+
+impl Responder for RolePermissions {
+    type Body = BoxBody;
+
+    fn respond_to(self, _req: &actix_web::HttpRequest) -> HttpResponse {
+        HttpResponse::Ok().json(self)
+    }
+}
@@ -13,7 +13,7 @@ use xml::{writer::XmlEvent, EmitterConfig};
 
 use crate::{
     structs::entities::{
-        announcement::{Announcement, Id as AnnouncementId},
+        announcement::{Announcement, Id as AnnouncementId, DEFAULT_FROM_ID},
         clan::{Clan, Id as ClanId, Platform, MAX_CLAN_NAME_LENGTH},
         player::{Jid, Player, Role, Status},
     },
@@ -39,7 +39,12 @@ use crate::{
 ///     <int-attr2>{int_attr2}</int-attr2>
 ///     <int-attr3>{int_attr3}</int-attr3>
 ///     <size>{size}</size>
+///     <viewer-role>{viewer_role}</viewer-role>
+///     <viewer-status>{viewer_status}</viewer-status>
 /// </info>
+/// ```
+///
+/// The viewer's role and status are only included when the viewer is known.
 #[derive(Debug, Clone)]
 pub struct ClanInfo {
     id: ClanId,
@@ -53,7 +58,19 @@ pub struct ClanInfo {
     int_attr2: u32,
     int_attr3: u32,
     size: u32,
-    platform: Platform
+    platform: Platform,
+    viewer: Option<(u32, u32)>,
+}
+
+impl ClanInfo {
+    /// Include the role and status of the player viewing the clan,
+    /// as they'd see them on their platform.
+    pub fn with_viewer(mut self, clan: &Clan, jid: &Jid, platform: &Platform) -> Self {
+        let view = clan.view_for(jid, platform);
+        self.viewer = Some((view.role, view.status));
+
+        self
+    }
 }
 
 #[allow(clippy::cast_possible_truncation)]
@@ -71,7 +88,8 @@ impl From<Clan> for ClanInfo {
             int_attr2: clan.int_attr2,
             int_attr3: clan.int_attr3,
             size: clan.size,
-            platform: clan.platform
+            platform: clan.platform,
+            viewer: None,
         }
     }
 }
@@ -118,6 +136,17 @@ impl ToXML for ClanInfo {
             writer.write(XmlEvent::end_element()).ok();
         }
 
+        if let Some((role, status)) = self.viewer {
+            for (elem, value) in [
+                ("viewer-role", role.to_string()),
+                ("viewer-status", status.to_string()),
+            ] {
+                writer.write(XmlEvent::start_element(elem)).ok();
+                writer.write(XmlEvent::characters(&value)).ok();
+                writer.write(XmlEvent::end_element()).ok();
+            }
+        }
+
         writer.write(XmlEvent::end_element()).ok();
 
         let result = writer.into_inner();
@@ -320,6 +349,7 @@ impl ToXML for ClanPlayerInfo {
 /// ```xml
 /// <info jid="{jid}">
 ///     <role>{role}</role>
+///     <role-name>{role_name}</role-name>
 ///     <status>{status}</status>
 ///     <onlinename>{online_name}</onlinename>
 ///     <description>{description}</description>
@@ -332,6 +362,7 @@ impl ToXML for ClanPlayerInfo {
 pub struct PlayerInfo {
     jid: String,
     role: u32,
+    role_name: String,
     status: u32,
     online_name: String,
     description: String,
@@ -340,11 +371,22 @@ pub struct PlayerInfo {
     size: u32,
 }
 
+impl PlayerInfo {
+    /// Label the player's role with the name the clan gave it, if any.
+    /// See: [`Clan::role_name`]
+    pub fn with_role_name(mut self, clan: &Clan) -> Self {
+        self.role_name = clan.role_name(Role::from(self.role)).to_string();
+
+        self
+    }
+}
+
 impl From<Player> for PlayerInfo {
     fn from(player: Player) -> Self {
         Self {
             jid: player.jid.to_string(),
             role: player.role as u32,
+            role_name: player.role.default_name().to_string(),
             status: player.status as u32,
             online_name: player.online_name,
             description: player.description,
@@ -367,6 +409,7 @@ impl ToXML for PlayerInfo {
 
         for (elem, value) in [
             ("role", &self.role.to_string()),
+            ("role-name", &self.role_name),
             ("status", &self.status.to_string()),
             ("onlinename", &self.online_name),
             ("description", &self.description),
@@ -451,6 +494,7 @@ impl ToXML for PlayerBasicInfo {
 ///
 /// ### Used for:
 /// - `/get_blacklist`
+/// - `/get_announcement_readers`
 ///
 /// ### XML format:
 /// ```xml
@@ -545,6 +589,82 @@ impl ToXML for IdEntity {
     }
 }
 
+/// XML entity for whether a player is blacklisted.
+///
+/// ### Used for:
+/// - `/is_blacklisted`
+///
+/// ### XML format:
+/// ```xml
+/// <blacklisted>{blacklisted}</blacklisted>
+/// ```
+#[derive(Debug, Clone)]
+pub struct BlacklistStatus {
+    blacklisted: u8,
+}
+
+impl From<bool> for BlacklistStatus {
+    fn from(blacklisted: bool) -> Self {
+        Self { blacklisted: u8::from(blacklisted) }
+    }
+}
+
+impl ToXML for BlacklistStatus {
+    fn to_xml(&self) -> String {
+        let mut writer = EmitterConfig::new()
+            .perform_indent(false)
+            .write_document_declaration(false)
+            .create_writer(Vec::new());
+
+        writer.write(XmlEvent::start_element("blacklisted")).ok();
+        writer
+            .write(XmlEvent::characters(&self.blacklisted.to_string()))
+            .ok();
+        writer.write(XmlEvent::end_element()).ok();
+
+        let result = writer.into_inner();
+        String::from_utf8(result).unwrap()
+    }
+}
+
+/// XML entity for how many items were affected by a request.
+///
+/// ### Used for:
+/// - `/delete_announcements_before`
+///
+/// ### XML format:
+/// ```xml
+/// <count>{count}</count>
+/// ```
+#[derive(Debug, Clone)]
+pub struct CountEntity {
+    count: u32,
+}
+
+impl From<u32> for CountEntity {
+    fn from(count: u32) -> Self {
+        Self { count }
+    }
+}
+
+impl ToXML for CountEntity {
+    fn to_xml(&self) -> String {
+        let mut writer = EmitterConfig::new()
+            .perform_indent(false)
+            .write_document_declaration(false)
+            .create_writer(Vec::new());
+
+        writer.write(XmlEvent::start_element("count")).ok();
+        writer
+            .write(XmlEvent::characters(&self.count.to_string()))
+            .ok();
+        writer.write(XmlEvent::end_element()).ok();
+
+        let result = writer.into_inner();
+        String::from_utf8(result).unwrap()
+    }
+}
+
 /// XML entity for an announcement.
 ///
 /// ### Used for:
@@ -573,14 +693,17 @@ pub struct AnnouncementInfo {
 
 impl From<Announcement> for AnnouncementInfo {
     fn from(announcement: Announcement) -> Self {
+        let jid = announcement.author_name();
+
         Self {
             id: announcement.id(),
             subject: announcement.subject,
             msg: announcement.msg,
-            jid: announcement.author.to_string(),
+            jid,
             msg_date: announcement.date_created,
             bin_data: announcement.bin_data,
-            from_id: announcement.from_id,
+            // Stored announcements may predate the check, and ``0`` breaks the game
+            from_id: announcement.from_id.max(DEFAULT_FROM_ID),
         }
     }
 }
@@ -615,3 +738,66 @@ impl ToXML for AnnouncementInfo {
         String::from_utf8(result).unwrap()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    //! Tests for the XML the game receives.
+
+    use super::{AnnouncementInfo, BlacklistStatus, CountEntity, PlayerInfo};
+    use crate::{
+        structs::entities::{
+            announcement::{Announcement, ANNOUNCEMENT_AUTHOR_NAME_ENV},
+            clan::Clan,
+            player::{Jid, Player, Role},
+        },
+        utils::xml_format::ToXML,
+    };
+
+    /// Whether a player is blacklisted is sent as ``1`` or ``0``.
+    #[test]
+    fn blacklist_status() {
+        assert_eq!(BlacklistStatus::from(true).to_xml(), "<blacklisted>1</blacklisted>");
+        assert_eq!(BlacklistStatus::from(false).to_xml(), "<blacklisted>0</blacklisted>");
+    }
+
+    /// Counts are sent as a single element.
+    #[test]
+    fn count() {
+        assert_eq!(CountEntity::from(3).to_xml(), "<count>3</count>");
+    }
+
+    /// The author's online name is only shown when enabled and known.
+    ///
+    /// The option is read from the environment, so every case runs in this single test.
+    #[test]
+    fn announcement_author_name() {
+        let author = Jid { username: "author".to_string(), domain: "a1".to_string(), region: "us".to_string() };
+        let named = Announcement { author: author.clone(), author_online_name: Some("Author".to_string()), ..Default::default() };
+        let unnamed = Announcement { author, ..Default::default() };
+
+        std::env::remove_var(ANNOUNCEMENT_AUTHOR_NAME_ENV);
+        let disabled = AnnouncementInfo::from(named.clone()).to_xml();
+        std::env::set_var(ANNOUNCEMENT_AUTHOR_NAME_ENV, "true");
+        let enabled = AnnouncementInfo::from(named).to_xml();
+        let unknown = AnnouncementInfo::from(unnamed).to_xml();
+        std::env::remove_var(ANNOUNCEMENT_AUTHOR_NAME_ENV);
+
+        assert!(disabled.contains("<jid>author@a1.us.np.playstation.net</jid>"));
+        assert!(enabled.contains("<jid>Author</jid>"));
+        assert!(unknown.contains("<jid>author@a1.us.np.playstation.net</jid>"));
+    }
+
+    /// A member's role is named as the clan customized it, or by its default name.
+    #[test]
+    fn role_name() {
+        let player = Player { role: Role::SubLeader, ..Default::default() };
+        let mut clan = Clan::default();
+
+        let default = PlayerInfo::from(player.clone()).with_role_name(&clan).to_xml();
+        assert!(default.contains("<role>3</role><role-name>Sub-Leader</role-name>"));
+
+        clan.role_names.insert(Role::SubLeader, "Officer".to_string());
+        let custom = PlayerInfo::from(player).with_role_name(&clan).to_xml();
+        assert!(custom.contains("<role-name>Officer</role-name>"));
+    }
+}
@@ -7,7 +7,7 @@ pub const SUCCESS: u8 = 0x00;
 
 /// Error codes for the Clans operations.
 #[allow(dead_code, clippy::module_name_repetitions)]
-#[derive(Debug, Clone, Copy, Serialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
 #[repr(u8)]
 pub enum ErrorCode {
     /// The request was malformed or invalid.
@@ -135,8 +135,7 @@ pub enum ErrorCode {
 
     /// The post contains vulgar or offensive words.
     /// 
-    /// Currently, there's nothing stopping vulgar words,
-    /// so this error is unused.
+    /// The words are configured with the ``BLOCKED_WORDS`` environment variable.
     VulgarWordsPosted = 0x44,
 
     /// The blacklist limit has been reached for the clan.
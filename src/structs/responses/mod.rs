@@ -2,7 +2,10 @@
 //! expects to receive from the server, in XML format.
 
 pub mod base;
+pub mod capabilities;
 pub mod entities;
 pub mod error;
+pub mod stats;
 
+#[cfg(feature = "admin")]
 pub mod admin;
\ No newline at end of file
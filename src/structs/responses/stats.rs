@@ -0,0 +1,34 @@
+//! Response structs for the server stats endpoint.
+//!
+//! Like the capabilities, this is meant for companion clients
+//! (e.g. a status page), so it is serialized as JSON.
+
+use std::collections::BTreeMap;
+
+use actix_web::{body::BoxBody, HttpResponse, Responder};
+use serde::Serialize;
+
+/// Aggregate stats about the clans on the server.
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Stats {
+    /// Number of clans.
+    pub clans: u64,
+
+    /// Number of players that have used the server.
+    pub players: u64,
+
+    /// Number of clans, per platform.
+    pub platforms: BTreeMap<String, u64>,
+
+    /// Average number of members per clan.
+    pub average_members: f64,
+}
+
+impl Responder for Stats {
+    type Body = BoxBody;
+
+    fn respond_to(self, _req: &actix_web::HttpRequest) -> HttpResponse {
+        HttpResponse::Ok().json(self)
+    }
+}
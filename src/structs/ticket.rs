@@ -3,10 +3,22 @@
 //! The ticket is cryptographically signed and contains the user's
 //! username, alongside other data, which we can use to identify them.
 
+use std::{
+    hash::{DefaultHasher, Hash, Hasher},
+    path::PathBuf,
+};
+
 use base64::Engine;
-use openssl::{ec::EcKey, hash::MessageDigest, pkey::PKey, sign::Verifier};
+use openssl::{
+    ec::EcKey,
+    hash::MessageDigest,
+    pkey::{PKey, Public},
+    sign::Verifier,
+};
 use serde::{Deserialize, Deserializer};
 
+use crate::{structs::responses::error::ErrorCode, utils};
+
 /// Default domain RPCN sets for players.
 pub const DEFAULT_DOMAIN: &str = "un";
 
@@ -57,16 +69,78 @@ impl Version {
     }
 
     /// Length of the signature.
-    pub fn signature_length(self, signature: &Signature) -> usize {
-        match signature {
-            // PS3 uses SHA-1 for V2 to V3, and SHA-256 for V4.
-            Signature::Console(_) => match self {
-                Self::V2 | Self::V2_1 | Self::V3 => 16,
-                Self::V4 => 32,
-            },
-
-            Signature::Emulator(_) => unimplemented!(),
-        }
+    /// See: [`SignatureScheme`]
+    ///
+    /// Returns `None` if the platform doesn't sign this version,
+    /// or if its signature runs to the end of the ticket.
+    pub fn signature_length(self, signature: &Signature) -> Option<usize> {
+        SignatureScheme::find(signature.key_name(), self).and_then(|scheme| scheme.signature_length)
+    }
+}
+
+/// Environment variable name for overriding the digest of a signature scheme,
+/// as a comma-separated list of ``<key>:<version>=<digest>`` entries.
+///
+/// Example: ``rpcn:0x3100=sha256``
+const SIGNATURE_DIGESTS_ENV: &str = "SIGNATURE_DIGESTS";
+
+/// How a platform signs a version of the ticket.
+#[derive(Debug, Clone, Copy)]
+pub struct SignatureScheme {
+    /// Name of the platform's public key.
+    /// See: [`Signature::key_name`]
+    pub key: &'static str,
+
+    /// The version of the ticket.
+    pub version: Version,
+
+    /// Name of the digest the ticket is signed with, as ``OpenSSL`` knows it.
+    pub digest: &'static str,
+
+    /// Length of the signature, if it doesn't run to the end of the ticket.
+    pub signature_length: Option<usize>,
+}
+
+impl SignatureScheme {
+    /// Every known signature scheme.
+    ///
+    /// - PS3 uses SHA-1 for V2 to V3, and SHA-256 for V4.
+    /// - RPCN uses SHA-224, and doesn't support V4.
+    pub const ALL: [Self; 7] = [
+        Self { key: "psn", version: Version::V2, digest: "sha1", signature_length: Some(16) },
+        Self { key: "psn", version: Version::V2_1, digest: "sha1", signature_length: Some(16) },
+        Self { key: "psn", version: Version::V3, digest: "sha1", signature_length: Some(16) },
+        Self { key: "psn", version: Version::V4, digest: "sha256", signature_length: Some(32) },
+        Self { key: "rpcn", version: Version::V2, digest: "sha224", signature_length: None },
+        Self { key: "rpcn", version: Version::V2_1, digest: "sha224", signature_length: None },
+        Self { key: "rpcn", version: Version::V3, digest: "sha224", signature_length: None },
+    ];
+
+    /// Find the scheme a platform signs a version of the ticket with.
+    pub fn find(key: &str, version: Version) -> Option<Self> {
+        Self::ALL.into_iter().find(|scheme| scheme.key == key && scheme.version == version)
+    }
+
+    /// The digest the ticket is signed with, unless overridden.
+    /// See: [`SIGNATURE_DIGESTS_ENV`]
+    ///
+    /// Returns `None` if ``OpenSSL`` doesn't know the digest.
+    pub fn digest(self) -> Option<MessageDigest> {
+        let overrides = std::env::var(SIGNATURE_DIGESTS_ENV).unwrap_or_default();
+        let digest = overrides
+            .split(',')
+            .filter_map(|entry| entry.split_once('='))
+            .find(|(scheme, _)| {
+                scheme.split_once(':').is_some_and(|(key, version)| {
+                    let version = version.trim().trim_start_matches("0x");
+                    key.trim() == self.key
+                        && u16::from_str_radix(version, 16).ok().and_then(Version::from_u16)
+                            == Some(self.version)
+                })
+            })
+            .map_or(self.digest, |(_, digest)| digest.trim());
+
+        MessageDigest::from_name(digest)
     }
 }
 
@@ -87,6 +161,14 @@ impl Default for Signature {
 }
 
 impl Signature {
+    /// Name of the platform's public key.
+    pub const fn key_name(&self) -> &'static str {
+        match self {
+            Self::Console(_) => "psn",
+            Self::Emulator(_) => "rpcn",
+        }
+    }
+
     /// Get the data.
     pub fn signed_data(&self) -> &[u8] {
         match self {
@@ -94,16 +176,18 @@ impl Signature {
         }
     }
 
-    /// Length of the data to verify the signature against.
-    pub fn signed_data_length(&self, ticket_version: Version) -> usize {
+    /// Offset the data to verify the signature against ends at.
+    ///
+    /// Returns `None` if the platform doesn't sign this version.
+    pub fn signed_data_length(&self, ticket_version: Version) -> Option<usize> {
         match self {
-            Self::Console(_) => {
-                ticket_version.ticket_length() - (ticket_version.signature_length(self) + 16)
-            }
+            Self::Console(_) => ticket_version
+                .signature_length(self)
+                .map(|length| ticket_version.ticket_length() - (length + 16)),
 
             // The emulator only signs from 0x08 to 0xB0, skipping the first 8 bytes.
             // This is the entirety of the `user_data` section.
-            Self::Emulator(_) => unimplemented!(),
+            Self::Emulator(_) => SignatureScheme::find(self.key_name(), ticket_version).map(|_| 0xB0),
         }
     }
 
@@ -120,7 +204,7 @@ impl Signature {
 }
 
 /// A ``PlayStation Network`` ticket for authenticating requests.
-#[derive(Debug, Default, Clone, PartialEq, Eq)]
+#[derive(Default, Clone, PartialEq, Eq)]
 pub struct Ticket {
     /// The ticket's serial number.
     pub serial: String,
@@ -150,12 +234,146 @@ pub struct Ticket {
     pub service_id: String,
 
     /// Status of the ticket (seems to be always 0)
+    ///
+    /// A non-zero value may mean the account is limited or banned.
+    /// See: [`STRICT_TICKET_STATUS_ENV`]
     pub status: u32,
 
     /// The ticket's signature
     pub signature: Signature,
 }
 
+/// Environment variable name for rejecting tickets with a non-zero status.
+///
+/// Off by default, as the meaning of the other statuses is unknown.
+const STRICT_TICKET_STATUS_ENV: &str = "STRICT_TICKET_STATUS";
+
+/// How far the server's clock may drift from the ticket issuer's, in milliseconds.
+const CLOCK_SKEW_LEEWAY: u64 = 5 * 60 * 1000;
+
+/// A reason for rejecting a ticket.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TicketError {
+    /// The ticket isn't valid base64, or its buffer has the wrong size.
+    Malformed,
+
+    /// The ticket's version isn't supported, on its platform.
+    UnsupportedVersion,
+
+    /// The ticket's issued or expiration dates don't make sense.
+    InvalidDates,
+
+    /// The ticket has a non-zero status. See: [`STRICT_TICKET_STATUS_ENV`]
+    InvalidStatus,
+
+    /// The ticket's signature doesn't match its contents.
+    InvalidSignature,
+
+    /// The ticket has expired.
+    Expired,
+
+    /// The public key to verify the ticket with couldn't be read.
+    MissingKey,
+
+    /// The server's clock is before the UNIX epoch.
+    Clock,
+}
+
+impl TicketError {
+    /// Every reason for rejecting a ticket.
+    pub const ALL: [Self; 8] = [
+        Self::Malformed,
+        Self::UnsupportedVersion,
+        Self::InvalidDates,
+        Self::InvalidStatus,
+        Self::InvalidSignature,
+        Self::Expired,
+        Self::MissingKey,
+        Self::Clock,
+    ];
+
+    /// Find the reason a ticket was rejected for, from the error deserializing it.
+    ///
+    /// Deserializers only carry messages, so this is how the request
+    /// extractor recovers the reason from a failed request. Only the custom
+    /// errors raised while deserializing a [`Ticket`] are matched, and only
+    /// if their message is exactly the reason's.
+    pub fn find(error: &serde_xml_rs::Error) -> Option<Self> {
+        let serde_xml_rs::Error::Custom { field } = error else {
+            return None;
+        };
+
+        Self::ALL.into_iter().find(|reason| reason.to_string() == *field)
+    }
+}
+
+impl std::fmt::Display for TicketError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let message = match self {
+            Self::Malformed => "Malformed ticket",
+            Self::UnsupportedVersion => "Unsupported ticket version",
+            Self::InvalidDates => "Invalid ticket dates",
+            Self::InvalidStatus => "Invalid ticket status",
+            Self::InvalidSignature => "Invalid ticket signature",
+            Self::Expired => "Ticket has expired",
+            Self::MissingKey => "Failed to read public key",
+            Self::Clock => "System time before UNIX EPOCH",
+        };
+
+        write!(f, "{message}")
+    }
+}
+
+impl From<TicketError> for ErrorCode {
+    fn from(error: TicketError) -> Self {
+        match error {
+            TicketError::Malformed
+            | TicketError::UnsupportedVersion
+            | TicketError::InvalidDates
+            | TicketError::InvalidStatus => Self::InvalidTicket,
+            TicketError::InvalidSignature => Self::InvalidSignature,
+            TicketError::Expired => Self::TicketExpired,
+            TicketError::MissingKey | TicketError::Clock => Self::InternalServerError,
+        }
+    }
+}
+
+/// Environment variable name for toggling the redaction of tickets in logs.
+const LOG_REDACT_TICKETS_ENV: &str = "LOG_REDACT_TICKETS";
+
+/// Tickets are credentials, so unless ``LOG_REDACT_TICKETS`` is disabled,
+/// only the username and a short hash of the ticket are printed.
+impl std::fmt::Debug for Ticket {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if utils::env::flag(LOG_REDACT_TICKETS_ENV, true) {
+            let mut hasher = DefaultHasher::new();
+            self.serial.hash(&mut hasher);
+            self.signature.signed_data().hash(&mut hasher);
+            let hash = hasher.finish();
+
+            return f
+                .debug_struct("Ticket")
+                .field("username", &self.username)
+                .field("hash", &format_args!("{:08x}", hash >> 32))
+                .finish_non_exhaustive();
+        }
+
+        f.debug_struct("Ticket")
+            .field("serial", &self.serial)
+            .field("issuer_id", &self.issuer_id)
+            .field("issued_at", &self.issued_at)
+            .field("expires_at", &self.expires_at)
+            .field("account_id", &self.account_id)
+            .field("username", &self.username)
+            .field("region", &self.region)
+            .field("domain", &self.domain)
+            .field("service_id", &self.service_id)
+            .field("status", &self.status)
+            .field("signature", &self.signature)
+            .finish()
+    }
+}
+
 impl<'de> Deserialize<'de> for Ticket {
     fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
     where
@@ -166,11 +384,18 @@ impl<'de> Deserialize<'de> for Ticket {
 
         // Decode the base64 string.
         let engine = base64::engine::general_purpose::STANDARD;
-        let mut decoded = engine.decode(base64).map_err(serde::de::Error::custom)?;
+        let mut decoded = engine
+            .decode(base64)
+            .map_err(|_| serde::de::Error::custom(TicketError::Malformed))?;
 
         // Deserialize the ticket from the decoded bytes.
         let ticket = Self::from_bytes(&mut decoded).map_err(serde::de::Error::custom)?;
 
+        // Refuse tickets that can no longer be used
+        if ticket.is_expired() {
+            return Err(serde::de::Error::custom(TicketError::Expired));
+        }
+
         Ok(ticket)
     }
 }
@@ -183,6 +408,20 @@ impl Ticket {
             .to_string()
     }
 
+    /// Returns the current time, in milliseconds since the UNIX epoch.
+    #[allow(clippy::cast_possible_truncation)]
+    fn now() -> Result<u64, TicketError> {
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|now| now.as_millis() as u64)
+            .map_err(|_| TicketError::Clock)
+    }
+
+    /// Returns whether the ticket has expired (with a 5 minute leeway).
+    pub fn is_expired(&self) -> bool {
+        Self::now().is_ok_and(|now| self.expires_at.saturating_add(CLOCK_SKEW_LEEWAY) < now)
+    }
+
     /// Make sure `issued_at` and `expires_at` make sense.
     ///
     /// - `issued_at` and `expires_at` must be non-zero.
@@ -191,28 +430,37 @@ impl Ticket {
     /// - `issued_at` and `expires_at` must not be more than 1 year in the future.
     ///
     /// The dates are assumed to be in milliseconds.
-    fn validate_dates(issued_at: u64, expires_at: u64) -> Result<(), &'static str> {
+    fn validate_dates(issued_at: u64, expires_at: u64) -> Result<(), TicketError> {
         if issued_at == 0 || expires_at == 0 {
-            return Err("Invalid issued or expiration date");
+            return Err(TicketError::InvalidDates);
         }
 
+        // Check if expires_at is before issued_at
         if expires_at <= issued_at {
-            return Err("Expiration date is before issued date");
+            return Err(TicketError::InvalidDates);
         }
 
         // Check if issued_at is in the future (with a 5 minute leeway)
-        let now = std::time::SystemTime::now()
-            .duration_since(std::time::UNIX_EPOCH)
-            .map_err(|_| "System time before UNIX EPOCH")?
-            .as_secs();
+        let now = Self::now()?;
 
-        if issued_at > now * 1000 + 300 * 1000 {
-            return Err("Issued date is in the future");
+        if issued_at > now + CLOCK_SKEW_LEEWAY {
+            return Err(TicketError::InvalidDates);
         }
 
         // Check if expires_at is too far in the future (more than 1 year)
-        if expires_at > now * 1000 + 31_536_000 * 1000 {
-            return Err("Issued or expiration date is too far in the future");
+        if expires_at > now + 31_536_000 * 1000 {
+            return Err(TicketError::InvalidDates);
+        }
+
+        Ok(())
+    }
+
+    /// Make sure the ticket's status is zero, if ``STRICT_TICKET_STATUS`` is enabled.
+    ///
+    /// A non-zero status may belong to a limited or banned account.
+    fn validate_status(status: u32) -> Result<(), TicketError> {
+        if status != 0 && utils::env::flag(STRICT_TICKET_STATUS_ENV, false) {
+            return Err(TicketError::InvalidStatus);
         }
 
         Ok(())
@@ -224,25 +472,22 @@ impl Ticket {
     /// `bytes` must be mutable, as the function may modify it
     /// to fix endianness issues with the timestamps.
     #[allow(clippy::too_many_lines)]
-    pub fn from_bytes(bytes: &mut [u8]) -> Result<Self, &'static str> {
+    pub fn from_bytes(bytes: &mut [u8]) -> Result<Self, TicketError> {
         let mut ticket = Self::default();
 
-        if bytes.is_empty() {
-            return Err("Empty buffer");
+        // Check the size first, so that every field below can be sliced safely
+        if bytes.len() < 212 || bytes.len() > 400 {
+            return Err(TicketError::Malformed);
         }
 
         let version = u16::from_be_bytes([bytes[0], bytes[1]]);
-        let version = Version::from_u16(version).ok_or("Unsupported version")?;
-
-        if bytes.len() < 212 || bytes.len() > 400 {
-            return Err("Invalid buffer length");
-        }
+        let version = Version::from_u16(version).ok_or(TicketError::UnsupportedVersion)?;
 
         // Helper function to handle endianness issues with timestamps
         let parse_timestamps = |bytes: &mut [u8],
                                 issued_range: std::ops::Range<usize>,
                                 expires_range: std::ops::Range<usize>|
-         -> Result<(u64, u64), &'static str> {
+         -> Result<(u64, u64), TicketError> {
             let mut issued_at = u64::from_be_bytes(bytes[issued_range.clone()].try_into().unwrap());
             let mut expires_at =
                 u64::from_be_bytes(bytes[expires_range.clone()].try_into().unwrap());
@@ -290,10 +535,16 @@ impl Ticket {
 
                 let signed_data = match signature {
                     Signature::Console(_) => {
-                        let data_length = signature.signed_data_length(version);
-                        bytes[0x08..data_length].to_vec()
+                        let data_length = signature
+                            .signed_data_length(version)
+                            .ok_or(TicketError::UnsupportedVersion)?;
+
+                        bytes.get(0x08..data_length).ok_or(TicketError::Malformed)?.to_vec()
+                    }
+                    // Slice defensively, so that a short buffer can't panic
+                    Signature::Emulator(_) => {
+                        bytes.get(0x08..0xB0).ok_or(TicketError::Malformed)?.to_vec()
                     }
-                    Signature::Emulator(_) => bytes[0x08..0xB0].to_vec(),
                 };
 
                 ticket.signature = Signature::from_bytes(*signature_id, &signed_data);
@@ -319,59 +570,48 @@ impl Ticket {
                 let signed_data = match signature {
                     Signature::Console(_) => {
                         let start = 0x08;
-                        let end = bytes.len() - version.signature_length(&signature) - 16;
+                        let length = version
+                            .signature_length(&signature)
+                            .ok_or(TicketError::UnsupportedVersion)?;
+                        let end = bytes.len().checked_sub(length + 16).ok_or(TicketError::Malformed)?;
                         println!("V4 signed data range: {start:#X}..{end:#X}");
                         bytes[start..end].to_vec()
                     }
-                    Signature::Emulator(_) => {
-                        unimplemented!("Emulator does not support version 4 tickets")
-                    }
+                    // The emulator does not support version 4 tickets
+                    Signature::Emulator(_) => return Err(TicketError::UnsupportedVersion),
                 };
                 ticket.signature = Signature::from_bytes(*signature_id, &signed_data);
             }
         }
 
-        let ec_key_name = match &ticket.signature {
-            Signature::Console(_) => "psn",
-            Signature::Emulator(_) => "rpcn",
-        };
-
-        let ec_key_bytes = std::fs::read_to_string(format!("keys/{ec_key_name}.pem"))
-            .map_err(|_| "Failed to read public key")?;
-
-        let ec_key = EcKey::public_key_from_pem(ec_key_bytes.as_bytes())
-            .map_err(|_| "Failed to load public key")?;
+        Self::validate_status(ticket.status)?;
 
-        let keypair = PKey::from_ec_key(ec_key).map_err(|_| "Failed to load public key")?;
+        let keys = Self::public_keys(ticket.signature.key_name())?;
 
         // Verify the signature.
-        let digest = match ticket.signature {
-            Signature::Console(_) => match version {
-                Version::V2 | Version::V2_1 | Version::V3 => MessageDigest::sha1(),
-                Version::V4 => MessageDigest::sha256(),
-            },
-
-            Signature::Emulator(_) => match version {
-                Version::V2 | Version::V2_1 | Version::V3 => MessageDigest::sha224(),
-                Version::V4 => return Err("Ticket version 4 is not supported in the emulator"),
-            },
+        //
+        // Version 4 tickets aren't supported in the emulator
+        let Some(scheme) = SignatureScheme::find(ticket.signature.key_name(), version) else {
+            return Err(TicketError::UnsupportedVersion);
+        };
+
+        let Some(digest) = scheme.digest() else {
+            log::error!("Unknown digest for signature scheme {scheme:?}");
+            return Err(TicketError::UnsupportedVersion);
         };
 
         let data = ticket.signature.signed_data();
         let signature = match ticket.signature {
             Signature::Console(_) => {
-                &bytes[bytes.len() - version.signature_length(&ticket.signature)..]
+                let length = version
+                    .signature_length(&ticket.signature)
+                    .ok_or(TicketError::UnsupportedVersion)?;
+
+                bytes.get(bytes.len().saturating_sub(length)..).ok_or(TicketError::Malformed)?
             }
-            Signature::Emulator(_) => &bytes[0xC0..],
+            Signature::Emulator(_) => bytes.get(0xC0..).ok_or(TicketError::Malformed)?,
         };
 
-        let mut verifier =
-            Verifier::new(digest, &keypair).map_err(|_| "Failed to create verifier")?;
-
-        verifier
-            .update(data)
-            .map_err(|_| "Failed to update verifier")?;
-
         // # Verifying the signature of NP tickets
         //
         // For the time being, only RPCN signatures can be verified.
@@ -386,16 +626,299 @@ impl Ticket {
         //   know PlayStation Home's public key.
         // - Nobody has successfully verified a Version 4 PSN ticket yet
         // - Versions below 4 aren't sent anymore by PSN.
+        //
+        // Every key is attempted in turn, so that rotated keys keep working.
         if let Signature::Emulator(_) = ticket.signature {
-            let result = verifier
-                .verify(signature)
-                .map_err(|_| "Failed to verify signature!")?;
-
-            if !result {
-                return Err("Invalid signature");
+            if !Self::verify_any(&keys, digest, data, signature) {
+                return Err(TicketError::InvalidSignature);
             }
         }
 
         Ok(ticket)
     }
+
+    /// Returns whether `signature` signs `data` with any of the `keys`.
+    fn verify_any(keys: &[PKey<Public>], digest: MessageDigest, data: &[u8], signature: &[u8]) -> bool {
+        keys.iter().any(|key| {
+            let Ok(mut verifier) = Verifier::new(digest, key) else {
+                return false;
+            };
+
+            verifier.update(data).is_ok() && verifier.verify(signature).unwrap_or(false)
+        })
+    }
+
+    /// Load every public key for a platform.
+    ///
+    /// Keys are read from ``keys/{name}.pem`` and, to support key rotation,
+    /// from any ``.pem`` file inside the ``keys/{name}/`` directory.
+    pub fn public_keys(name: &str) -> Result<Vec<PKey<Public>>, TicketError> {
+        let mut paths = vec![PathBuf::from(format!("keys/{name}.pem"))];
+
+        if let Ok(entries) = std::fs::read_dir(format!("keys/{name}")) {
+            let mut rotated: Vec<PathBuf> = entries
+                .filter_map(Result::ok)
+                .map(|entry| entry.path())
+                .filter(|path| path.extension().is_some_and(|ext| ext == "pem"))
+                .collect();
+
+            rotated.sort();
+            paths.extend(rotated);
+        }
+
+        let keys: Vec<PKey<Public>> = paths
+            .iter()
+            .filter_map(|path| std::fs::read_to_string(path).ok().map(|pem| (path, pem)))
+            .filter_map(|(path, pem)| {
+                let key = EcKey::public_key_from_pem(pem.as_bytes())
+                    .and_then(PKey::from_ec_key)
+                    .ok();
+
+                if key.is_none() {
+                    log::warn!("Failed to load public key `{}`", path.display());
+                }
+
+                key
+            })
+            .collect();
+
+        if keys.is_empty() {
+            return Err(TicketError::MissingKey);
+        }
+
+        Ok(keys)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    //! Tests for parsing tickets and picking their signature schemes.
+
+    use openssl::{
+        ec::{EcGroup, EcKey},
+        hash::MessageDigest,
+        nid::Nid,
+        pkey::{PKey, Private, Public},
+        sign::Signer,
+    };
+
+    use super::{
+        Signature, SignatureScheme, Ticket, TicketError, Version, CLOCK_SKEW_LEEWAY, LOG_REDACT_TICKETS_ENV,
+        SIGNATURE_DIGESTS_ENV, STRICT_TICKET_STATUS_ENV,
+    };
+    use crate::structs::responses::error::ErrorCode;
+
+    /// Build a buffer of the given size, starting with a supported version when it fits.
+    fn buffer(length: usize) -> Vec<u8> {
+        let mut bytes = vec![0; length];
+        if let Some(version) = bytes.get_mut(..2) {
+            version.copy_from_slice(&0x3100_u16.to_be_bytes());
+        }
+
+        bytes
+    }
+
+    /// Buffers too short to hold a ticket are rejected, rather than panicking.
+    #[test]
+    fn short_buffers_are_malformed() {
+        for length in [0, 1, 0xBF, 211] {
+            assert_eq!(Ticket::from_bytes(&mut buffer(length)), Err(TicketError::Malformed), "{length} bytes");
+            assert_eq!(Ticket::from_bytes(&mut vec![0; length]), Err(TicketError::Malformed), "{length} zeroes");
+        }
+    }
+
+    /// Buffers too long to hold a ticket are rejected too.
+    #[test]
+    fn long_buffers_are_malformed() {
+        assert_eq!(Ticket::from_bytes(&mut buffer(401)), Err(TicketError::Malformed));
+    }
+
+    /// Every reason is recovered from the error raised with it, and nothing else.
+    #[test]
+    fn find_reasons() {
+        use serde::de::Error;
+
+        for reason in TicketError::ALL {
+            assert_eq!(TicketError::find(&serde_xml_rs::Error::custom(reason)), Some(reason));
+        }
+
+        for message in ["", "Malformed ticket: extra", "Invalid ticket status, or not"] {
+            assert_eq!(TicketError::find(&serde_xml_rs::Error::custom(message)), None, "{message}");
+        }
+
+        let unsupported = serde_xml_rs::Error::UnsupportedOperation { operation: TicketError::Malformed.to_string() };
+        assert_eq!(TicketError::find(&unsupported), None);
+    }
+
+    /// Every reason is answered with the error code the game expects.
+    #[test]
+    fn reason_error_codes() {
+        let expected = [
+            (TicketError::Malformed, ErrorCode::InvalidTicket),
+            (TicketError::UnsupportedVersion, ErrorCode::InvalidTicket),
+            (TicketError::InvalidDates, ErrorCode::InvalidTicket),
+            (TicketError::InvalidStatus, ErrorCode::InvalidTicket),
+            (TicketError::InvalidSignature, ErrorCode::InvalidSignature),
+            (TicketError::Expired, ErrorCode::TicketExpired),
+            (TicketError::MissingKey, ErrorCode::InternalServerError),
+            (TicketError::Clock, ErrorCode::InternalServerError),
+        ];
+
+        assert_eq!(expected.len(), TicketError::ALL.len());
+        for (reason, code) in expected {
+            assert_eq!(ErrorCode::from(reason), code, "{reason:?}");
+        }
+    }
+
+    /// Buffers of a valid size still need a known version.
+    #[test]
+    fn unknown_versions_are_unsupported() {
+        let mut bytes = buffer(212);
+        bytes[..2].copy_from_slice(&0x1234_u16.to_be_bytes());
+
+        assert_eq!(Ticket::from_bytes(&mut bytes), Err(TicketError::UnsupportedVersion));
+    }
+
+    /// The digest a platform signs a version of the ticket with, if any.
+    fn digest_of(key: &str, version: Version) -> Option<Nid> {
+        SignatureScheme::find(key, version)
+            .and_then(SignatureScheme::digest)
+            .map(|digest| digest.type_())
+    }
+
+    /// Every scheme is found by its platform and version, with a digest ``OpenSSL`` knows.
+    ///
+    /// The override is checked in the same test, as the environment is shared between tests.
+    #[test]
+    fn signature_schemes() {
+        std::env::remove_var(SIGNATURE_DIGESTS_ENV);
+
+        for scheme in SignatureScheme::ALL {
+            let found = SignatureScheme::find(scheme.key, scheme.version).expect("scheme is listed");
+            assert_eq!((found.digest, found.signature_length), (scheme.digest, scheme.signature_length));
+            assert!(found.digest().is_some(), "{scheme:?}");
+            assert_eq!(digest_of(scheme.key, scheme.version), MessageDigest::from_name(scheme.digest).map(|digest| digest.type_()));
+        }
+
+        assert_eq!(digest_of("psn", Version::V3), Some(Nid::SHA1));
+        assert_eq!(digest_of("psn", Version::V4), Some(Nid::SHA256));
+        assert_eq!(digest_of("rpcn", Version::V3), Some(Nid::SHA224));
+
+        // The emulator doesn't sign version 4 tickets
+        assert!(SignatureScheme::find("rpcn", Version::V4).is_none());
+        assert_eq!(Version::V4.signature_length(&Signature::Emulator(Vec::new())), None);
+        assert_eq!(Version::V4.signature_length(&Signature::Console(Vec::new())), Some(32));
+
+        // Only the overridden scheme changes
+        std::env::set_var(SIGNATURE_DIGESTS_ENV, "rpcn:0x3100=sha256");
+        let overridden = digest_of("rpcn", Version::V3);
+        let untouched = digest_of("rpcn", Version::V2);
+        std::env::remove_var(SIGNATURE_DIGESTS_ENV);
+
+        assert_eq!(overridden, Some(Nid::SHA256));
+        assert_eq!(untouched, Some(Nid::SHA224));
+    }
+
+    /// Tickets are redacted in logs by default, showing the username and a hash,
+    /// and printed in full only if redaction is disabled.
+    ///
+    /// Every case runs in this single test, as the environment is shared between tests.
+    #[test]
+    fn debug_redaction() {
+        let ticket = Ticket {
+            serial: "0123456789".to_string(),
+            username: "player".to_string(),
+            service_id: "NPWR00000".to_string(),
+            signature: Signature::Emulator(vec![0xAB; 16]),
+            ..Default::default()
+        };
+
+        std::env::remove_var(LOG_REDACT_TICKETS_ENV);
+        let default = format!("{ticket:?}");
+        std::env::set_var(LOG_REDACT_TICKETS_ENV, "true");
+        let redacted = format!("{ticket:?}");
+        std::env::set_var(LOG_REDACT_TICKETS_ENV, "false");
+        let full = format!("{ticket:?}");
+        std::env::remove_var(LOG_REDACT_TICKETS_ENV);
+
+        assert_eq!(default, redacted);
+        assert!(redacted.starts_with("Ticket { username: \"player\", hash: "), "{redacted}");
+        assert!(!redacted.contains("0123456789") && !redacted.contains("NPWR00000"), "{redacted}");
+
+        assert!(full.contains("serial: \"0123456789\"") && full.contains("NPWR00000"), "{full}");
+    }
+
+    /// Generate a key pair on the curve RPCN signs tickets with.
+    fn key_pair() -> (PKey<Private>, PKey<Public>) {
+        let group = EcGroup::from_curve_name(Nid::SECP224K1).unwrap();
+        let private = EcKey::generate(&group).unwrap();
+        let public = EcKey::from_public_key(&group, private.public_key()).unwrap();
+
+        (PKey::from_ec_key(private).unwrap(), PKey::from_ec_key(public).unwrap())
+    }
+
+    /// Signatures are accepted from any key of the platform, so that rotated keys keep working.
+    #[test]
+    fn verify_with_any_key() {
+        let (old_private, old_public) = key_pair();
+        let (new_private, new_public) = key_pair();
+        let (_, unrelated) = key_pair();
+
+        let data = b"ticket data";
+        let sign = |key: &PKey<Private>| {
+            let mut signer = Signer::new(MessageDigest::sha224(), key).unwrap();
+            signer.update(data).unwrap();
+            signer.sign_to_vec().unwrap()
+        };
+
+        let keys = [old_public, new_public];
+        assert!(Ticket::verify_any(&keys, MessageDigest::sha224(), data, &sign(&old_private)));
+        assert!(Ticket::verify_any(&keys, MessageDigest::sha224(), data, &sign(&new_private)));
+
+        assert!(!Ticket::verify_any(&[unrelated], MessageDigest::sha224(), data, &sign(&new_private)));
+        assert!(!Ticket::verify_any(&keys, MessageDigest::sha224(), b"tampered", &sign(&new_private)));
+        assert!(!Ticket::verify_any(&[], MessageDigest::sha224(), data, &sign(&new_private)));
+    }
+
+    /// Non-zero statuses are only rejected in strict mode.
+    ///
+    /// Every case runs in this single test, as the environment is shared between tests.
+    #[test]
+    fn strict_status() {
+        std::env::remove_var(STRICT_TICKET_STATUS_ENV);
+        let lenient = [0, 1, u32::MAX].map(Ticket::validate_status);
+
+        std::env::set_var(STRICT_TICKET_STATUS_ENV, "true");
+        let strict = [0, 1, u32::MAX].map(Ticket::validate_status);
+        std::env::remove_var(STRICT_TICKET_STATUS_ENV);
+
+        assert_eq!(lenient, [Ok(()), Ok(()), Ok(())]);
+        assert_eq!(strict, [Ok(()), Err(TicketError::InvalidStatus), Err(TicketError::InvalidStatus)]);
+    }
+
+    /// Tickets only expire once the leeway for clock skew has passed too.
+    #[test]
+    fn expiry_leeway() {
+        let now = Ticket::now().expect("clock after the epoch");
+        let expiring = |expires_at: u64| Ticket { expires_at, ..Default::default() }.is_expired();
+
+        assert!(!expiring(now + 60_000));
+        assert!(!expiring(now - 60_000));
+        assert!(expiring(now - CLOCK_SKEW_LEEWAY - 60_000));
+        assert!(!expiring(u64::MAX));
+    }
+
+    /// Tickets must be issued before they expire, within the leeway and a year from now.
+    #[test]
+    fn dates() {
+        let now = Ticket::now().expect("clock after the epoch");
+        let year = 31_536_000 * 1000;
+
+        assert_eq!(Ticket::validate_dates(now - 60_000, now + 60_000), Ok(()));
+        assert_eq!(Ticket::validate_dates(now + 60_000, now + 120_000), Ok(()));
+        assert_eq!(Ticket::validate_dates(0, now), Err(TicketError::InvalidDates));
+        assert_eq!(Ticket::validate_dates(now, now), Err(TicketError::InvalidDates));
+        assert_eq!(Ticket::validate_dates(now + CLOCK_SKEW_LEEWAY + 60_000, now + year), Err(TicketError::InvalidDates));
+        assert_eq!(Ticket::validate_dates(now, now + year + 60_000), Err(TicketError::InvalidDates));
+    }
 }
@@ -0,0 +1,111 @@
+//! Background tasks that run alongside the server.
+//!
+//! These are configured through environment variables,
+//! and are disabled unless explicitly enabled.
+
+use std::time::Duration;
+
+use chrono::Utc;
+use mongodb::bson::doc;
+
+use crate::{database::Database, structs::entities::clan::Clan, utils};
+
+/// Environment variable name for how many days announcements are kept for.
+const ANNOUNCEMENT_RETENTION_DAYS_ENV: &str = "ANNOUNCEMENT_RETENTION_DAYS";
+
+/// Environment variable name for how often, in seconds, old announcements are pruned.
+const ANNOUNCEMENT_SWEEP_INTERVAL_ENV: &str = "ANNOUNCEMENT_SWEEP_INTERVAL";
+
+/// Default interval between announcement sweeps: one hour.
+const DEFAULT_ANNOUNCEMENT_SWEEP_INTERVAL: u64 = 60 * 60;
+
+/// Interval between sweeps of disbanded clans: one minute.
+const DISBAND_SWEEP_INTERVAL: u64 = 60;
+
+/// Remove every announcement, across all clans, that was posted
+/// more than `retention_days` ago, regardless of its expiration date.
+///
+/// Returns the number of clans that were modified.
+pub async fn sweep_announcements(
+    database: &Database,
+    retention_days: i64,
+) -> Result<u64, mongodb::error::Error> {
+    let cutoff = Utc::now() - chrono::Duration::days(retention_days);
+
+    let result = database
+        .clans
+        .update_many(
+            doc! { "announcements.date_created": { "$lt": cutoff.timestamp() } },
+            doc! { "$pull": { "announcements": { "date_created": { "$lt": cutoff.timestamp() } } } },
+        )
+        .await?;
+
+    Ok(result.modified_count)
+}
+
+/// Start pruning old announcements periodically, if a retention
+/// period was set with ``ANNOUNCEMENT_RETENTION_DAYS``.
+pub fn spawn_announcement_sweeper(database: Database) {
+    let Some(retention_days) = utils::env::value::<i64>(ANNOUNCEMENT_RETENTION_DAYS_ENV)
+        .filter(|days| *days > 0)
+    else {
+        return;
+    };
+
+    let interval = utils::env::value::<u64>(ANNOUNCEMENT_SWEEP_INTERVAL_ENV)
+        .filter(|seconds| *seconds > 0)
+        .unwrap_or(DEFAULT_ANNOUNCEMENT_SWEEP_INTERVAL);
+
+    log::info!("Pruning announcements older than {retention_days} days, every {interval} seconds");
+
+    actix_web::rt::spawn(async move {
+        let mut interval = actix_web::rt::time::interval(Duration::from_secs(interval));
+
+        loop {
+            interval.tick().await;
+
+            match sweep_announcements(&database, retention_days).await {
+                Ok(0) => {}
+                Ok(count) => log::info!("Pruned old announcements from {count} clans"),
+                Err(e) => log::error!("Failed to prune old announcements: {e}"),
+            }
+        }
+    });
+}
+
+/// Delete every clan whose disband grace period is over.
+///
+/// Returns the number of clans that were deleted.
+pub async fn sweep_disbanded_clans(database: &Database) -> Result<u64, mongodb::error::Error> {
+    let result = database
+        .clans
+        .delete_many(doc! { "disband_at": { "$lte": Utc::now().timestamp() } })
+        .await?;
+
+    Ok(result.deleted_count)
+}
+
+/// Start deleting disbanded clans periodically, if a grace
+/// period was set with ``DISBAND_GRACE_PERIOD``.
+///
+/// Clans that are accessed in the meantime are deleted right away.
+pub fn spawn_disband_sweeper(database: Database) {
+    if Clan::disband_grace_period().is_none() {
+        return;
+    }
+
+    actix_web::rt::spawn(async move {
+        let interval = Duration::from_secs(DISBAND_SWEEP_INTERVAL);
+        let mut interval = actix_web::rt::time::interval(interval);
+
+        loop {
+            interval.tick().await;
+
+            match sweep_disbanded_clans(&database).await {
+                Ok(0) => {}
+                Ok(count) => log::info!("Deleted {count} disbanded clans"),
+                Err(e) => log::error!("Failed to delete disbanded clans: {e}"),
+            }
+        }
+    });
+}
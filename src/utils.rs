@@ -22,6 +22,1362 @@ pub mod xml_format {
     }
 }
 
+pub mod cursor {
+    //! Opaque cursors for paging through clans, keyed on their ID.
+    //!
+    //! Unlike skipping a number of items, cursors don't skip or repeat
+    //! any item when clans are created or deleted between pages.
+
+    use base64::Engine;
+
+    use crate::structs::{entities::clan::Id, responses::error::ErrorCode};
+
+    /// Engine used to encode the cursors, so they're safe to put anywhere.
+    const ENGINE: base64::engine::GeneralPurpose = base64::engine::general_purpose::URL_SAFE_NO_PAD;
+
+    /// Encode the cursor pointing after the given clan.
+    pub fn encode(id: Id) -> String {
+        ENGINE.encode(id.to_be_bytes())
+    }
+
+    /// Decode a cursor, returning the ID of the clan it points after.
+    ///
+    /// An empty cursor points to the start, so that clients can request the first page.
+    pub fn decode(cursor: &str) -> Result<Id, ErrorCode> {
+        if cursor.trim().is_empty() {
+            return Ok(0);
+        }
+
+        let bytes = ENGINE.decode(cursor.trim()).map_err(|_| ErrorCode::BadRequest)?;
+        let bytes = bytes.try_into().map_err(|_| ErrorCode::BadRequest)?;
+
+        Ok(Id::from_be_bytes(bytes))
+    }
+
+    #[cfg(test)]
+    mod tests {
+        //! Tests for encoding and decoding cursors.
+
+        use super::{decode, encode};
+        use crate::structs::responses::error::ErrorCode;
+
+        /// Every ID survives a round-trip.
+        #[test]
+        fn round_trip() {
+            for id in [0, 1, 42, 999_999, u32::MAX] {
+                assert_eq!(decode(&encode(id)), Ok(id));
+            }
+        }
+
+        /// An empty cursor points to the start.
+        #[test]
+        fn empty_cursor_is_first_page() {
+            assert_eq!(decode(""), Ok(0));
+            assert_eq!(decode("  "), Ok(0));
+        }
+
+        /// Cursors that weren't encoded by the server are rejected.
+        #[test]
+        fn invalid_cursors() {
+            assert_eq!(decode("not a cursor"), Err(ErrorCode::BadRequest));
+            assert_eq!(decode(&encode(1)[..3]), Err(ErrorCode::BadRequest));
+        }
+    }
+}
+
+pub mod pagination {
+    //! Helpers for the ``start`` and ``max`` parameters of list requests.
+    //!
+    //! The game counts ``start`` from 1, so ``start = 1`` begins at the first item
+    //! and ``start = 2`` skips it. A ``start`` of 0 or less also begins at the first item.
+
+    /// How many items to skip, to begin at the 1-based ``start`` position.
+    pub const fn skip(start: i32) -> u32 {
+        if start > 1 { start.unsigned_abs() - 1 } else { 0 }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        //! Tests for the ``start`` parameter's conversion.
+
+        use super::skip;
+
+        /// The first positions all begin at the first item.
+        #[test]
+        fn skip_begins_at_first_item() {
+            assert_eq!(skip(0), 0);
+            assert_eq!(skip(1), 0);
+            assert_eq!(skip(-5), 0);
+            assert_eq!(skip(i32::MIN), 0);
+        }
+
+        /// Every later position skips the items before it.
+        #[test]
+        fn skip_counts_from_one() {
+            assert_eq!(skip(2), 1);
+            assert_eq!(skip(10), 9);
+            assert_eq!(skip(i32::MAX), i32::MAX.unsigned_abs() - 1);
+        }
+    }
+}
+
+pub mod env {
+    //! Helpers for reading optional settings from environment variables.
+
+    use std::str::FromStr;
+
+    /// Read a boolean flag, falling back to `default` if it's unset or invalid.
+    ///
+    /// ``1``, ``true``, ``yes`` and ``on`` enable the flag, while
+    /// ``0``, ``false``, ``no`` and ``off`` disable it.
+    pub fn flag(name: &str, default: bool) -> bool {
+        match std::env::var(name).map(|value| value.trim().to_lowercase()).as_deref() {
+            Ok("1" | "true" | "yes" | "on") => true,
+            Ok("0" | "false" | "no" | "off") => false,
+            _ => default,
+        }
+    }
+
+    /// Read a value, returning `None` if it's unset or can't be parsed.
+    pub fn value<T: FromStr>(name: &str) -> Option<T> {
+        std::env::var(name).ok()?.trim().parse().ok()
+    }
+
+    #[cfg(test)]
+    mod tests {
+        //! Tests for reading settings.
+        //!
+        //! Each test uses a variable of its own, as the environment is shared between tests.
+
+        use super::{flag, value};
+
+        /// Flags accept the usual spellings, and fall back to their default otherwise.
+        #[test]
+        fn flags() {
+            const NAME: &str = "CLANS_TEST_FLAG";
+
+            std::env::remove_var(NAME);
+            assert!(flag(NAME, true));
+            assert!(!flag(NAME, false));
+
+            for enabled in ["1", "true", " YES ", "On"] {
+                std::env::set_var(NAME, enabled);
+                assert!(flag(NAME, false), "{enabled}");
+            }
+
+            for disabled in ["0", "false", "No", " off"] {
+                std::env::set_var(NAME, disabled);
+                assert!(!flag(NAME, true), "{disabled}");
+            }
+
+            std::env::set_var(NAME, "maybe");
+            assert!(flag(NAME, true));
+            std::env::remove_var(NAME);
+        }
+
+        /// Values are parsed ignoring surrounding whitespace, and missing if invalid.
+        #[test]
+        fn values() {
+            const NAME: &str = "CLANS_TEST_VALUE";
+
+            std::env::remove_var(NAME);
+            assert_eq!(value::<u32>(NAME), None);
+
+            std::env::set_var(NAME, " 42 ");
+            assert_eq!(value::<u32>(NAME), Some(42));
+
+            std::env::set_var(NAME, "-1");
+            assert_eq!(value::<u32>(NAME), None);
+            assert_eq!(value::<i64>(NAME), Some(-1));
+            std::env::remove_var(NAME);
+        }
+    }
+}
+
+pub mod validation {
+    //! Validation rules for a clan's name and tag, and its members' online names.
+    //!
+    //! Both the game and the Admin API go through these,
+    //! so that the two can't drift apart.
+
+    use actix_web::web::Data;
+    use mongodb::bson::doc;
+
+    use crate::{
+        database::Database,
+        structs::{
+            entities::{
+                clan::{MAX_CLAN_NAME_LENGTH, MAX_CLAN_TAG_LENGTH},
+                player::{Jid, Player},
+            },
+            requests::clans::ClanSearchFilterOperator,
+            responses::error::ErrorCode,
+        },
+    };
+
+    /// Environment variable name for the comma-separated list of blocked words.
+    const BLOCKED_WORDS_ENV: &str = "BLOCKED_WORDS";
+
+    /// Environment variable name for the comma-separated list of reserved names.
+    const RESERVED_NAMES_ENV: &str = "RESERVED_NAMES";
+
+    /// Environment variable name for also rejecting names that contain a reserved name.
+    const RESERVED_NAMES_SUBSTRING_ENV: &str = "RESERVED_NAMES_SUBSTRING";
+
+    /// Environment variable name for rejecting online names that
+    /// are another member's PSN username, ignoring case.
+    const REJECT_IMPERSONATION_ENV: &str = "REJECT_IMPERSONATION";
+
+    /// Environment variable name for the comma-separated list of markers
+    /// online names can't contain, ignoring case (e.g. ``[Leader]``).
+    const RESERVED_NAME_MARKERS_ENV: &str = "RESERVED_NAME_MARKERS";
+
+    /// Environment variable name for the length, in characters, below
+    /// which tags don't have to be unique.
+    ///
+    /// Very short tags are a small namespace, so enforcing their uniqueness
+    /// may be too strict. The tradeoff is that the database can't index tags
+    /// by their length, so once this is set, the unique index on tags is
+    /// dropped and only the server checks them: two clans created at the
+    /// same time may still end up sharing a longer tag.
+    const TAG_UNIQUE_MIN_LENGTH_ENV: &str = "TAG_UNIQUE_MIN_LENGTH";
+
+    /// Returns the length below which tags don't have to be unique, if any.
+    /// See: [`TAG_UNIQUE_MIN_LENGTH_ENV`]
+    pub fn tag_unique_min_length() -> Option<usize> {
+        super::env::value(TAG_UNIQUE_MIN_LENGTH_ENV).filter(|length| *length > 0)
+    }
+
+    /// Returns whether `tag` has to be unique, as it's not too short to be exempt.
+    /// See: [`TAG_UNIQUE_MIN_LENGTH_ENV`]
+    fn tag_must_be_unique(tag: &str) -> bool {
+        tag_unique_min_length().is_none_or(|min| tag.trim().chars().count() >= min)
+    }
+
+    /// Limit `value` to `max` characters, making sure that
+    /// Unicode characters don't push it past `max` bytes either.
+    pub fn length(value: &str, max: usize) -> Result<String, ErrorCode> {
+        let value: String = value.chars().take(max).collect();
+
+        if value.len() > max {
+            return Err(ErrorCode::PermissionDenied);
+        }
+
+        Ok(value)
+    }
+
+    /// Make sure `value` is not blank and has no control characters,
+    /// which the game can't display.
+    pub fn charset(value: &str) -> Result<(), ErrorCode> {
+        if value.trim().is_empty() || value.chars().any(char::is_control) {
+            return Err(ErrorCode::BadRequest);
+        }
+
+        Ok(())
+    }
+
+    /// Make sure `value` doesn't contain any of the words
+    /// listed in the ``BLOCKED_WORDS`` environment variable.
+    pub fn profanity(value: &str) -> Result<(), ErrorCode> {
+        let Ok(words) = std::env::var(BLOCKED_WORDS_ENV) else {
+            return Ok(());
+        };
+
+        let value = value.to_lowercase();
+        let blocked = words
+            .split(',')
+            .map(|word| word.trim().to_lowercase())
+            .filter(|word| !word.is_empty())
+            .any(|word| value.contains(&word));
+
+        if blocked {
+            return Err(ErrorCode::VulgarWordsPosted);
+        }
+
+        Ok(())
+    }
+
+    /// Make sure `value` isn't one of the names listed in
+    /// the ``RESERVED_NAMES`` environment variable, ignoring case.
+    ///
+    /// If ``RESERVED_NAMES_SUBSTRING`` is enabled, names
+    /// containing a reserved name are rejected too.
+    pub fn reserved(value: &str) -> Result<(), ErrorCode> {
+        let Ok(names) = std::env::var(RESERVED_NAMES_ENV) else {
+            return Ok(());
+        };
+
+        let substring = super::env::flag(RESERVED_NAMES_SUBSTRING_ENV, false);
+        let value = value.trim().to_lowercase();
+        let reserved = names
+            .split(',')
+            .map(|name| name.trim().to_lowercase())
+            .filter(|name| !name.is_empty())
+            .any(|name| if substring { value.contains(&name) } else { value == name });
+
+        if reserved {
+            return Err(ErrorCode::PermissionDenied);
+        }
+
+        Ok(())
+    }
+
+    /// Make sure the online name `name`, chosen by `author`, doesn't pass them off
+    /// as another of the `members`, if ``REJECT_IMPERSONATION`` is enabled,
+    /// nor contains a marker listed in ``RESERVED_NAME_MARKERS``.
+    pub fn impersonation(name: &str, author: &Jid, members: &[Player]) -> Result<(), ErrorCode> {
+        let name = name.trim().to_lowercase();
+        if name.is_empty() {
+            return Ok(());
+        }
+
+        let impersonating = super::env::flag(REJECT_IMPERSONATION_ENV, false)
+            && members
+                .iter()
+                .any(|p| p.jid != *author && p.jid.username.to_lowercase() == name);
+
+        let marked = std::env::var(RESERVED_NAME_MARKERS_ENV).is_ok_and(|markers| {
+            markers
+                .split(',')
+                .map(|marker| marker.trim().to_lowercase())
+                .filter(|marker| !marker.is_empty())
+                .any(|marker| name.contains(&marker))
+        });
+
+        if impersonating || marked {
+            return Err(ErrorCode::BadRequest);
+        }
+
+        Ok(())
+    }
+
+    /// Make sure no other clan already uses `name`, ignoring case
+    /// and surrounding whitespace.
+    pub async fn duplicate_name(name: &str, database: &Data<Database>) -> Result<(), ErrorCode> {
+        let filter = ClanSearchFilterOperator::Equal.to_filter("name", name.trim());
+
+        let count = database
+            .clans
+            .count_documents(filter)
+            .await
+            .map_err(|_| ErrorCode::InternalServerError)?;
+
+        if count > 0 {
+            return Err(ErrorCode::DuplicatedClanName);
+        }
+
+        Ok(())
+    }
+
+    /// Make sure no other clan already uses `tag`, ignoring case
+    /// and surrounding whitespace.
+    ///
+    /// Tags are unique across every platform, like the unique index
+    /// on them, so that clans can move between platforms freely.
+    ///
+    /// Tags shorter than ``TAG_UNIQUE_MIN_LENGTH`` are exempt.
+    pub async fn duplicate_tag(tag: &str, database: &Data<Database>) -> Result<(), ErrorCode> {
+        let tag = tag.trim();
+        if !tag_must_be_unique(tag) {
+            return Ok(());
+        }
+
+        let filter = doc! {
+            "tag": {
+                "$regex": format!("^{}$", regex::escape(tag)),
+                "$options": "i"
+            }
+        };
+
+        let count = database
+            .clans
+            .count_documents(filter)
+            .await
+            .map_err(|_| ErrorCode::InternalServerError)?;
+
+        if count > 0 {
+            return Err(ErrorCode::DuplicatedClanTag);
+        }
+
+        Ok(())
+    }
+
+    /// Run every check on a clan's name and tag, in order,
+    /// returning them limited to their maximum lengths.
+    pub async fn clan(
+        name: &str,
+        tag: &str,
+        database: &Data<Database>,
+    ) -> Result<(String, String), ErrorCode> {
+        let name = length(name, MAX_CLAN_NAME_LENGTH)?;
+        let tag = length(tag, MAX_CLAN_TAG_LENGTH)?;
+
+        charset(&name)?;
+        charset(&tag)?;
+
+        profanity(&name)?;
+        profanity(&tag)?;
+
+        reserved(&name)?;
+
+        duplicate_name(&name, database).await?;
+        duplicate_tag(&tag, database).await?;
+
+        Ok((name, tag))
+    }
+
+    #[cfg(test)]
+    mod tests {
+        //! Tests for the rules that don't need the database.
+        //!
+        //! Each rule reads its own environment variables, so the tests
+        //! setting them can run alongside each other.
+
+        use super::{
+            charset, impersonation, length, profanity, reserved, tag_must_be_unique, tag_unique_min_length,
+            BLOCKED_WORDS_ENV, REJECT_IMPERSONATION_ENV, RESERVED_NAMES_ENV, RESERVED_NAMES_SUBSTRING_ENV,
+            RESERVED_NAME_MARKERS_ENV, TAG_UNIQUE_MIN_LENGTH_ENV,
+        };
+        use crate::structs::{
+            entities::player::{Jid, Player},
+            responses::error::ErrorCode,
+        };
+
+        /// Values are cut to the maximum number of characters,
+        /// unless their characters take up more bytes than that.
+        #[test]
+        fn lengths() {
+            assert_eq!(length("clan", 8), Ok("clan".to_string()));
+            assert_eq!(length("a long name", 6), Ok("a long".to_string()));
+            assert_eq!(length("", 6), Ok(String::new()));
+            assert_eq!(length("ééé", 6), Ok("ééé".to_string()));
+            assert_eq!(length("éééé", 4), Err(ErrorCode::PermissionDenied));
+        }
+
+        /// Blank values and control characters are rejected.
+        #[test]
+        fn charsets() {
+            assert_eq!(charset("Clan"), Ok(()));
+            assert_eq!(charset("Clan ☆"), Ok(()));
+
+            for value in ["", "   ", "Cl\nan", "Clan\u{7}"] {
+                assert_eq!(charset(value), Err(ErrorCode::BadRequest), "{value:?}");
+            }
+        }
+
+        /// Blocked words are rejected anywhere in a value, ignoring case.
+        #[test]
+        fn blocked_words() {
+            std::env::remove_var(BLOCKED_WORDS_ENV);
+            let unset = profanity("darn");
+
+            std::env::set_var(BLOCKED_WORDS_ENV, " Darn, ,heck");
+            let results = ["darn", "DarnClan", "the heck", "Clan"].map(profanity);
+            std::env::remove_var(BLOCKED_WORDS_ENV);
+
+            assert_eq!(unset, Ok(()));
+            assert_eq!(
+                results,
+                [
+                    Err(ErrorCode::VulgarWordsPosted),
+                    Err(ErrorCode::VulgarWordsPosted),
+                    Err(ErrorCode::VulgarWordsPosted),
+                    Ok(()),
+                ]
+            );
+        }
+
+        /// Reserved names are rejected ignoring case and surrounding whitespace,
+        /// and also as part of a name if enabled.
+        ///
+        /// Both settings are read from the environment, so every case runs in this single test.
+        #[test]
+        fn reserved_names() {
+            std::env::remove_var(RESERVED_NAMES_ENV);
+            std::env::remove_var(RESERVED_NAMES_SUBSTRING_ENV);
+            let unset = reserved("Admin");
+
+            std::env::set_var(RESERVED_NAMES_ENV, " Admin, ,Staff");
+            let exact = ["admin", " ADMIN ", "Staff", "Admins", "Clan"].map(reserved);
+
+            std::env::set_var(RESERVED_NAMES_SUBSTRING_ENV, "true");
+            let substring = ["Admins", "The Staff Clan", "Clan"].map(reserved);
+
+            std::env::remove_var(RESERVED_NAMES_ENV);
+            std::env::remove_var(RESERVED_NAMES_SUBSTRING_ENV);
+
+            let denied = Err(ErrorCode::PermissionDenied);
+            assert_eq!(unset, Ok(()));
+            assert_eq!(exact, [denied, denied, denied, Ok(()), Ok(())]);
+            assert_eq!(substring, [denied, denied, Ok(())]);
+        }
+
+        /// Tags shorter than the threshold may be shared, in characters and ignoring
+        /// surrounding whitespace, and every tag is unique without one.
+        ///
+        /// The threshold is read from the environment, so every case runs in this single test.
+        #[test]
+        fn short_tags() {
+            std::env::remove_var(TAG_UNIQUE_MIN_LENGTH_ENV);
+            let unset = (tag_unique_min_length(), tag_must_be_unique("A"));
+
+            std::env::set_var(TAG_UNIQUE_MIN_LENGTH_ENV, "0");
+            let disabled = tag_unique_min_length();
+
+            std::env::set_var(TAG_UNIQUE_MIN_LENGTH_ENV, "3");
+            let tags = ["AB", " AB ", "éé", "ABC", "ABCD"].map(tag_must_be_unique);
+            std::env::remove_var(TAG_UNIQUE_MIN_LENGTH_ENV);
+
+            assert_eq!(unset, (None, true));
+            assert_eq!(disabled, None);
+            assert_eq!(tags, [false, false, false, true, true]);
+        }
+
+        /// Online names can't be another member's username, when enabled,
+        /// nor contain a reserved marker, ignoring case.
+        ///
+        /// The options are read from the environment, so every case runs in this single test.
+        #[test]
+        fn impersonation_checks() {
+            let jid = |username: &str| Jid { username: username.to_string(), domain: "a1".to_string(), region: "us".to_string() };
+            let members = [Player { jid: jid("leader"), ..Default::default() }, Player { jid: jid("author"), ..Default::default() }];
+            let check = |name: &str| impersonation(name, &jid("author"), &members);
+
+            std::env::remove_var(REJECT_IMPERSONATION_ENV);
+            std::env::remove_var(RESERVED_NAME_MARKERS_ENV);
+            let unset = ["Leader", "[Leader] Author"].map(check);
+
+            std::env::set_var(REJECT_IMPERSONATION_ENV, "true");
+            let impersonating = [" LEADER ", "Author", "Leader2", ""].map(check);
+
+            std::env::set_var(RESERVED_NAME_MARKERS_ENV, "[Leader], ,[Mod]");
+            let marked = ["[leader] Author", "Author [MOD]", "Author"].map(check);
+
+            std::env::remove_var(REJECT_IMPERSONATION_ENV);
+            std::env::remove_var(RESERVED_NAME_MARKERS_ENV);
+
+            let rejected = Err(ErrorCode::BadRequest);
+            assert_eq!(unset, [Ok(()), Ok(())]);
+            assert_eq!(impersonating, [rejected, Ok(()), Ok(()), Ok(())]);
+            assert_eq!(marked, [rejected, rejected, Ok(())]);
+        }
+    }
+}
+
+pub mod ip {
+    //! Helpers for finding a client's real IP address,
+    //! when the server is running behind a reverse proxy.
+    //!
+    //! Forwarding headers are only honored when they come from a proxy
+    //! listed in the ``TRUSTED_PROXIES`` environment variable, as a
+    //! comma-separated list of CIDRs (e.g. ``10.0.0.0/8,::1/128``),
+    //! so that clients can't spoof their address.
+
+    use std::{
+        net::{IpAddr, SocketAddr},
+        str::FromStr,
+    };
+
+    use actix_web::http::header::{HeaderMap, FORWARDED, X_FORWARDED_FOR};
+
+    /// Environment variable name for the trusted proxies.
+    const TRUSTED_PROXIES_ENV: &str = "TRUSTED_PROXIES";
+
+    /// A range of IP addresses, in CIDR notation.
+    #[derive(Debug, Clone, Copy)]
+    pub struct Cidr {
+        /// The first address of the range.
+        network: IpAddr,
+
+        /// How many leading bits of the address are fixed.
+        prefix: u32,
+    }
+
+    impl FromStr for Cidr {
+        type Err = &'static str;
+
+        fn from_str(value: &str) -> Result<Self, Self::Err> {
+            let (address, prefix) = value.split_once('/').unwrap_or((value, ""));
+            let network = IpAddr::from_str(address.trim()).map_err(|_| "Invalid IP address")?;
+
+            let max = if network.is_ipv4() { 32 } else { 128 };
+            let prefix = if prefix.is_empty() {
+                max
+            } else {
+                prefix.trim().parse().map_err(|_| "Invalid prefix length")?
+            };
+
+            if prefix > max {
+                return Err("Invalid prefix length");
+            }
+
+            Ok(Self { network, prefix })
+        }
+    }
+
+    impl Cidr {
+        /// Returns whether the range contains the given address.
+        pub fn contains(&self, ip: &IpAddr) -> bool {
+            match (self.network, ip) {
+                (IpAddr::V4(network), IpAddr::V4(ip)) => {
+                    let mask = u32::MAX.checked_shl(32 - self.prefix).unwrap_or(0);
+                    u32::from(network) & mask == u32::from(*ip) & mask
+                }
+                (IpAddr::V6(network), IpAddr::V6(ip)) => {
+                    let mask = u128::MAX.checked_shl(128 - self.prefix).unwrap_or(0);
+                    u128::from(network) & mask == u128::from(*ip) & mask
+                }
+                _ => false,
+            }
+        }
+    }
+
+    /// Read the trusted proxies from the environment, skipping invalid entries.
+    fn trusted_proxies() -> Vec<Cidr> {
+        std::env::var(TRUSTED_PROXIES_ENV)
+            .unwrap_or_default()
+            .split(',')
+            .filter(|cidr| !cidr.trim().is_empty())
+            .filter_map(|cidr| match Cidr::from_str(cidr) {
+                Ok(cidr) => Some(cidr),
+                Err(e) => {
+                    log::warn!("Ignoring trusted proxy `{cidr}`: {e}");
+                    None
+                }
+            })
+            .collect()
+    }
+
+    /// Parse an address from a forwarding header, which may
+    /// be quoted, and include brackets and a port.
+    fn parse_address(value: &str) -> Option<IpAddr> {
+        let value = value.trim().trim_matches('"');
+
+        if let Ok(ip) = IpAddr::from_str(value) {
+            return Some(ip);
+        }
+
+        SocketAddr::from_str(value).ok().map(|address| address.ip())
+    }
+
+    /// Collect the addresses a request was forwarded for, from the client to the last proxy.
+    ///
+    /// The standard ``Forwarded`` header takes precedence over ``X-Forwarded-For``.
+    fn forwarded_for(headers: &HeaderMap) -> Vec<Option<IpAddr>> {
+        let forwarded: Vec<Option<IpAddr>> = headers
+            .get_all(FORWARDED)
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .filter_map(|element| {
+                element.split(';').find_map(|pair| {
+                    let (key, value) = pair.split_once('=')?;
+                    key.trim().eq_ignore_ascii_case("for").then(|| parse_address(value))
+                })
+            })
+            .collect();
+
+        if !forwarded.is_empty() {
+            return forwarded;
+        }
+
+        headers
+            .get_all(X_FORWARDED_FOR)
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .map(parse_address)
+            .collect()
+    }
+
+    /// Resolve the real IP address of a client.
+    ///
+    /// Starting from the peer, every trusted proxy is skipped, walking the
+    /// forwarding headers backwards, until an untrusted address is found.
+    pub fn real_ip(peer: Option<SocketAddr>, headers: &HeaderMap) -> Option<IpAddr> {
+        resolve(peer, headers, &trusted_proxies())
+    }
+
+    /// Resolve the real IP address of a client, trusting the given proxies.
+    /// See: [`real_ip`]
+    fn resolve(peer: Option<SocketAddr>, headers: &HeaderMap, proxies: &[Cidr]) -> Option<IpAddr> {
+        let mut ip = peer?.ip();
+
+        let is_trusted = |ip: &IpAddr| proxies.iter().any(|cidr| cidr.contains(ip));
+
+        for address in forwarded_for(headers).into_iter().rev() {
+            if !is_trusted(&ip) {
+                break;
+            }
+
+            // A malformed entry can't be trusted, so stop at the last proxy
+            let Some(address) = address else { break };
+            ip = address;
+        }
+
+        Some(ip)
+    }
+
+    #[cfg(test)]
+    mod tests {
+        //! Tests for parsing CIDRs and resolving clients behind proxies.
+
+        use std::{net::IpAddr, str::FromStr};
+
+        use actix_web::http::header::{HeaderMap, HeaderValue, FORWARDED, X_FORWARDED_FOR};
+
+        use super::{resolve, Cidr};
+
+        /// Parse an address, for brevity.
+        fn ip(value: &str) -> IpAddr {
+            IpAddr::from_str(value).expect("valid address")
+        }
+
+        /// Parse a CIDR, for brevity.
+        fn cidr(value: &str) -> Cidr {
+            Cidr::from_str(value).expect("valid CIDR")
+        }
+
+        /// Build headers with the given ``X-Forwarded-For`` value.
+        fn forwarded_for(value: &'static str) -> HeaderMap {
+            let mut headers = HeaderMap::new();
+            headers.insert(X_FORWARDED_FOR, HeaderValue::from_static(value));
+
+            headers
+        }
+
+        /// IPv4 ranges match the addresses under their mask, including the widest and narrowest.
+        #[test]
+        fn ipv4_ranges() {
+            assert!(cidr("10.0.0.0/8").contains(&ip("10.200.3.4")));
+            assert!(!cidr("10.0.0.0/8").contains(&ip("11.0.0.1")));
+
+            assert!(cidr("0.0.0.0/0").contains(&ip("203.0.113.7")));
+            assert!(cidr("0.0.0.0/0").contains(&ip("255.255.255.255")));
+
+            assert!(cidr("192.0.2.1/32").contains(&ip("192.0.2.1")));
+            assert!(!cidr("192.0.2.1/32").contains(&ip("192.0.2.2")));
+            assert!(cidr("192.0.2.1").contains(&ip("192.0.2.1")));
+            assert!(!cidr("192.0.2.1").contains(&ip("192.0.2.0")));
+        }
+
+        /// IPv6 ranges work the same, and never match the other family.
+        #[test]
+        fn ipv6_ranges() {
+            assert!(cidr("::1/128").contains(&ip("::1")));
+            assert!(!cidr("::1/128").contains(&ip("::2")));
+            assert!(cidr("2001:db8::/32").contains(&ip("2001:db8:1::1")));
+            assert!(!cidr("2001:db8::/32").contains(&ip("2001:db9::1")));
+            assert!(cidr("::/0").contains(&ip("2001:db8::1")));
+
+            assert!(!cidr("0.0.0.0/0").contains(&ip("::1")));
+            assert!(!cidr("::/0").contains(&ip("127.0.0.1")));
+        }
+
+        /// Invalid addresses and prefixes are rejected.
+        #[test]
+        fn invalid_ranges() {
+            for value in ["", "nonsense", "10.0.0.0/33", "::/129", "10.0.0.0/x", "10.0.0/8"] {
+                assert!(Cidr::from_str(value).is_err(), "{value}");
+            }
+        }
+
+        /// Headers from an untrusted peer are ignored, so they can't be spoofed.
+        #[test]
+        fn untrusted_peer() {
+            let peer = "203.0.113.7:1234".parse().ok();
+            let headers = forwarded_for("198.51.100.1");
+
+            assert_eq!(resolve(peer, &headers, &[]), Some(ip("203.0.113.7")));
+            assert_eq!(resolve(peer, &headers, &[cidr("10.0.0.0/8")]), Some(ip("203.0.113.7")));
+        }
+
+        /// Trusted proxies are skipped, until an untrusted address is found.
+        #[test]
+        fn trusted_proxies() {
+            let peer = "10.0.0.1:1234".parse().ok();
+            let proxies = [cidr("10.0.0.0/8")];
+
+            let headers = forwarded_for("198.51.100.1");
+            assert_eq!(resolve(peer, &headers, &proxies), Some(ip("198.51.100.1")));
+
+            // The client can prepend anything, but only the entries added by proxies are used
+            let headers = forwarded_for("192.0.2.99, 198.51.100.1, 10.0.0.2");
+            assert_eq!(resolve(peer, &headers, &proxies), Some(ip("198.51.100.1")));
+
+            // Without any header, the peer is the client
+            assert_eq!(resolve(peer, &HeaderMap::new(), &proxies), Some(ip("10.0.0.1")));
+        }
+
+        /// The standard header is read too, and takes precedence.
+        #[test]
+        fn forwarded_header() {
+            let peer = "[::1]:1234".parse().ok();
+            let proxies = [cidr("::1/128")];
+
+            let mut headers = forwarded_for("198.51.100.1");
+            headers.insert(FORWARDED, HeaderValue::from_static("for=\"[2001:db8::1]:4711\";proto=https"));
+
+            assert_eq!(resolve(peer, &headers, &proxies), Some(ip("2001:db8::1")));
+        }
+
+        /// A malformed entry stops the walk at the last trusted proxy.
+        #[test]
+        fn malformed_entry() {
+            let peer = "10.0.0.1:1234".parse().ok();
+            let headers = forwarded_for("198.51.100.1, garbage");
+
+            assert_eq!(resolve(peer, &headers, &[cidr("10.0.0.0/8")]), Some(ip("10.0.0.1")));
+        }
+
+        /// Without a peer, there's no address to start from.
+        #[test]
+        fn missing_peer() {
+            assert_eq!(resolve(None, &forwarded_for("198.51.100.1"), &[]), None);
+        }
+    }
+}
+
+pub mod flags {
+    //! Runtime switches for turning off parts of the service.
+    //!
+    //! Routes listed in the ``DISABLED_ROUTES`` environment variable, as a
+    //! comma-separated list of names (e.g. ``create_clan,join_clan``), reply
+    //! with [`ErrorCode::ClosedService`] instead of running.
+
+    use actix_web::{
+        body::{EitherBody, MessageBody},
+        dev::{ServiceRequest, ServiceResponse},
+        middleware::Next,
+        Error, Responder,
+    };
+
+    use crate::structs::responses::{base::Response, error::ErrorCode};
+
+    /// Environment variable name for the disabled routes.
+    const DISABLED_ROUTES_ENV: &str = "DISABLED_ROUTES";
+
+    /// Returns whether the route at `path` has been disabled.
+    ///
+    /// Routes are named after the last segment of their path, and only
+    /// the game's, under ``/clan_manager``, can be disabled, so that
+    /// administration routes sharing a name keep working.
+    fn is_disabled(path: &str) -> bool {
+        if !path.starts_with("/clan_manager") {
+            return false;
+        }
+
+        let Ok(disabled) = std::env::var(DISABLED_ROUTES_ENV) else {
+            return false;
+        };
+
+        let name = path.trim_end_matches('/').rsplit('/').next().unwrap_or_default();
+        disabled.split(',').any(|route| route.trim() == name)
+    }
+
+    /// Middleware that rejects the requests to disabled routes.
+    #[allow(clippy::future_not_send)]
+    pub async fn disabled_routes(
+        req: ServiceRequest,
+        next: Next<impl MessageBody>,
+    ) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, Error> {
+        if !is_disabled(req.path()) {
+            return next.call(req).await.map(ServiceResponse::map_into_left_body);
+        }
+
+        let response = Response::<()>::error(ErrorCode::ClosedService).respond_to(req.request());
+        Ok(req.into_response(response).map_into_right_body())
+    }
+
+    #[cfg(test)]
+    mod tests {
+        //! Tests for disabling routes at runtime.
+
+        use actix_web::{
+            middleware::from_fn,
+            test::{self, TestRequest},
+            App, HttpResponse,
+        };
+
+        use super::{disabled_routes, is_disabled, DISABLED_ROUTES_ENV};
+
+        /// Disabled routes are closed, and every other route keeps working.
+        ///
+        /// The routes are read from the environment, so every case runs in this single test.
+        #[actix_web::test]
+        async fn disabled_route() {
+            std::env::set_var(DISABLED_ROUTES_ENV, "create_clan, join_clan");
+
+            let matches = [
+                "/clan_manager_update/sec/create_clan",
+                "/clan_manager_update/sec/create_clan/",
+                "/clan_manager_update/sec/join_clan",
+                "/clan_manager_update/sec/leave_clan",
+                "/clan_manager_view/sec/get_clan_info",
+                "/admin/create_clan",
+                "/create_clan",
+            ]
+            .map(is_disabled);
+
+            let app = test::init_service(
+                App::new()
+                    .wrap(from_fn(disabled_routes))
+                    .default_service(actix_web::web::to(|| async { HttpResponse::Ok().body("open") })),
+            )
+            .await;
+
+            let request = |path| TestRequest::post().uri(path).to_request();
+            let closed = test::call_and_read_body(&app, request("/clan_manager_update/sec/create_clan")).await;
+            let open = test::call_and_read_body(&app, request("/clan_manager_update/sec/leave_clan")).await;
+
+            std::env::remove_var(DISABLED_ROUTES_ENV);
+
+            assert_eq!(matches, [true, true, true, false, false, false, false]);
+            assert!(String::from_utf8_lossy(&closed).contains(r#"result="33""#));
+            assert_eq!(open, "open");
+        }
+    }
+}
+
+pub mod rate_limit {
+    //! Per-IP rate limiting, to resist abuse from a single host.
+    //!
+    //! Each client, identified by its real IP address, can make up to
+    //! ``RATE_LIMIT_REQUESTS`` requests every ``RATE_LIMIT_WINDOW`` seconds.
+    //! Rate limiting is off unless ``RATE_LIMIT_REQUESTS`` is set.
+    //!
+    //! Clan creation is limited per player as well. See: [`CreationLimiter`]
+
+    use std::{collections::HashMap, net::IpAddr, sync::Mutex};
+
+    use actix_web::{
+        body::{EitherBody, MessageBody},
+        dev::{ServiceRequest, ServiceResponse},
+        middleware::Next,
+        web::Data,
+        Error, HttpResponse, Responder,
+    };
+    use chrono::{DateTime, Duration, Utc};
+
+    use crate::structs::responses::{base::Response, error::ErrorCode};
+
+    /// Environment variable name for the number of requests allowed per window.
+    const RATE_LIMIT_REQUESTS_ENV: &str = "RATE_LIMIT_REQUESTS";
+
+    /// Environment variable name for the duration of a window, in seconds.
+    const RATE_LIMIT_WINDOW_ENV: &str = "RATE_LIMIT_WINDOW";
+
+    /// Default duration of a window, in seconds.
+    /// See: [`RATE_LIMIT_WINDOW_ENV`]
+    const DEFAULT_RATE_LIMIT_WINDOW: i64 = 60;
+
+    /// Number of tracked clients past which expired windows are forgotten.
+    const MAX_TRACKED_CLIENTS: usize = 10_000;
+
+    /// Paths that are never rate limited, as they're meant
+    /// for administrators and status checks.
+    const EXEMPT_PATHS: [&str; 3] = ["/admin", "/capabilities", "/stats"];
+
+    /// The requests made by every client in their current window.
+    #[derive(Debug, Default)]
+    pub struct RateLimiter {
+        /// When each client's window started, and how many requests they made in it.
+        windows: Mutex<HashMap<IpAddr, (DateTime<Utc>, u32)>>,
+    }
+
+    impl RateLimiter {
+        /// Count a request from a client, returning whether it's within the limit.
+        fn allow(&self, ip: IpAddr, limit: u32, window: Duration) -> bool {
+            let now = Utc::now();
+            let mut windows = self.windows.lock().unwrap();
+
+            if windows.len() > MAX_TRACKED_CLIENTS {
+                windows.retain(|_, (start, _)| now - *start < window);
+            }
+
+            let (start, count) = windows.entry(ip).or_insert((now, 0));
+            if now - *start >= window {
+                (*start, *count) = (now, 0);
+            }
+
+            *count += 1;
+            let allowed = *count <= limit;
+            drop(windows);
+
+            allowed
+        }
+    }
+
+    /// Middleware that rejects clients making too many requests.
+    ///
+    /// The game gets a [`ErrorCode::ClosedService`] error,
+    /// while any other client gets ``429 Too Many Requests``.
+    #[allow(clippy::future_not_send)]
+    pub async fn limit(
+        req: ServiceRequest,
+        next: Next<impl MessageBody>,
+    ) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, Error> {
+        let Some(limit) = super::env::value::<u32>(RATE_LIMIT_REQUESTS_ENV) else {
+            return next.call(req).await.map(ServiceResponse::map_into_left_body);
+        };
+
+        if is_exempt(req.path()) {
+            return next.call(req).await.map(ServiceResponse::map_into_left_body);
+        }
+
+        let window = super::env::value(RATE_LIMIT_WINDOW_ENV).unwrap_or(DEFAULT_RATE_LIMIT_WINDOW);
+        let ip = super::ip::real_ip(req.peer_addr(), req.headers());
+
+        let allowed = match (ip, req.app_data::<Data<RateLimiter>>()) {
+            (Some(ip), Some(limiter)) => limiter.allow(ip, limit, Duration::seconds(window)),
+            _ => true,
+        };
+
+        if allowed {
+            return next.call(req).await.map(ServiceResponse::map_into_left_body);
+        }
+
+        let response = if req.path().starts_with("/clan_manager") {
+            Response::<()>::error(ErrorCode::ClosedService).respond_to(req.request())
+        } else {
+            HttpResponse::TooManyRequests().finish()
+        };
+
+        Ok(req.into_response(response).map_into_right_body())
+    }
+
+    /// Returns whether requests to a path are never rate limited.
+    /// See: [`EXEMPT_PATHS`]
+    fn is_exempt(path: &str) -> bool {
+        EXEMPT_PATHS.iter().any(|exempt| path.starts_with(exempt))
+    }
+
+    /// Environment variable name for the number of clans a player can create per window.
+    ///
+    /// Setting it to ``0`` lets players create clans as often as they like.
+    const CLAN_CREATION_LIMIT_ENV: &str = "CLAN_CREATION_LIMIT";
+
+    /// Environment variable name for the duration of a clan creation window, in seconds.
+    const CLAN_CREATION_WINDOW_ENV: &str = "CLAN_CREATION_WINDOW";
+
+    /// Default number of clans a player can create per window.
+    /// See: [`CLAN_CREATION_LIMIT_ENV`]
+    const DEFAULT_CLAN_CREATION_LIMIT: u32 = 1;
+
+    /// Default duration of a clan creation window, in seconds (1 hour).
+    /// See: [`CLAN_CREATION_WINDOW_ENV`]
+    const DEFAULT_CLAN_CREATION_WINDOW: i64 = 60 * 60;
+
+    /// The clans created by every player in their current window.
+    ///
+    /// Players that go over the limit get a [`ErrorCode::ExceedsCreateClanFrequency`] error.
+    #[derive(Debug)]
+    pub struct CreationLimiter {
+        /// Number of clans a player can create per window.
+        limit: u32,
+
+        /// Duration of a window.
+        window: Duration,
+
+        /// When each player's window started, and how many clans they created in it.
+        windows: Mutex<HashMap<String, (DateTime<Utc>, u32)>>,
+    }
+
+    impl CreationLimiter {
+        /// Read the limit and the duration of a window from the environment.
+        pub fn from_env() -> Self {
+            let limit = super::env::value(CLAN_CREATION_LIMIT_ENV).unwrap_or(DEFAULT_CLAN_CREATION_LIMIT);
+            let window = super::env::value(CLAN_CREATION_WINDOW_ENV).unwrap_or(DEFAULT_CLAN_CREATION_WINDOW);
+
+            Self {
+                limit,
+                window: Duration::seconds(window),
+                windows: Mutex::default(),
+            }
+        }
+
+        /// Returns whether a player, identified by their JID, can create another clan.
+        pub fn allows(&self, jid: &str) -> bool {
+            if self.limit == 0 {
+                return true;
+            }
+
+            let now = Utc::now();
+            let windows = self.windows.lock().unwrap();
+
+            let allowed = windows
+                .get(jid)
+                .is_none_or(|(start, count)| now - *start >= self.window || *count < self.limit);
+            drop(windows);
+
+            allowed
+        }
+
+        /// Record that a player, identified by their JID, created a clan.
+        ///
+        /// This is separate from [`CreationLimiter::allows`], so that
+        /// attempts that fail for other reasons aren't counted.
+        pub fn record(&self, jid: &str) {
+            let now = Utc::now();
+            let mut windows = self.windows.lock().unwrap();
+
+            if windows.len() > MAX_TRACKED_CLIENTS {
+                windows.retain(|_, (start, _)| now - *start < self.window);
+            }
+
+            let (start, count) = windows.entry(jid.to_string()).or_insert((now, 0));
+            if now - *start >= self.window {
+                (*start, *count) = (now, 0);
+            }
+
+            *count += 1;
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        //! Tests for counting requests and clan creations per window.
+
+        use std::{net::IpAddr, sync::Mutex};
+
+        use actix_web::{
+            http::StatusCode,
+            middleware::from_fn,
+            test::{self, TestRequest},
+            web::Data,
+            App, HttpResponse,
+        };
+        use chrono::{Duration, Utc};
+
+        use super::{is_exempt, limit, CreationLimiter, RateLimiter, RATE_LIMIT_REQUESTS_ENV};
+
+        /// Parse an address, for brevity.
+        fn ip(value: &str) -> IpAddr {
+            value.parse().expect("valid address")
+        }
+
+        /// Clients can make requests until they reach the limit, independently of each other.
+        #[test]
+        fn request_limit_reached() {
+            let limiter = RateLimiter::default();
+
+            assert!(limiter.allow(ip("10.0.0.1"), 2, Duration::minutes(1)));
+            assert!(limiter.allow(ip("10.0.0.1"), 2, Duration::minutes(1)));
+            assert!(!limiter.allow(ip("10.0.0.1"), 2, Duration::minutes(1)));
+
+            assert!(limiter.allow(ip("10.0.0.2"), 2, Duration::minutes(1)));
+            assert!(limiter.allow(ip("::1"), 2, Duration::minutes(1)));
+        }
+
+        /// Clients can make requests again once their window is over.
+        #[test]
+        fn request_window_expires() {
+            let limiter = RateLimiter::default();
+            assert!(limiter.allow(ip("10.0.0.1"), 1, Duration::minutes(1)));
+            assert!(!limiter.allow(ip("10.0.0.1"), 1, Duration::minutes(1)));
+
+            limiter.windows.lock().unwrap().insert(ip("10.0.0.1"), (Utc::now() - Duration::minutes(2), 5));
+            assert!(limiter.allow(ip("10.0.0.1"), 1, Duration::minutes(1)));
+            assert!(!limiter.allow(ip("10.0.0.1"), 1, Duration::minutes(1)));
+        }
+
+        /// Administration and status routes are never limited, unlike the game's.
+        #[test]
+        fn exempt_paths() {
+            for path in ["/admin/clans", "/capabilities", "/capabilities/roles", "/stats"] {
+                assert!(is_exempt(path), "{path}");
+            }
+
+            for path in ["/clan_manager_view/sec/get_clan_info", "/", "/status"] {
+                assert!(!is_exempt(path), "{path}");
+            }
+        }
+
+        /// Clients over the limit are turned away, the game with the error it expects,
+        /// and every other client with ``429 Too Many Requests``.
+        ///
+        /// The limit is read from the environment, so every case runs in this single test.
+        #[actix_web::test]
+        async fn over_the_limit() {
+            std::env::set_var(RATE_LIMIT_REQUESTS_ENV, "1");
+
+            let app = test::init_service(
+                App::new()
+                    .app_data(Data::new(RateLimiter::default()))
+                    .wrap(from_fn(limit))
+                    .default_service(actix_web::web::to(|| async { HttpResponse::Ok().finish() })),
+            )
+            .await;
+
+            let request = |path: &str, peer: &str| {
+                TestRequest::get().uri(path).peer_addr(peer.parse().expect("valid address")).to_request()
+            };
+
+            let first = test::call_service(&app, request("/clan_manager_view/sec/get_clan_info", "10.0.0.1:1")).await;
+            let game = test::call_service(&app, request("/clan_manager_view/sec/get_clan_info", "10.0.0.1:2")).await;
+            let other = test::call_service(&app, request("/other", "10.0.0.1:3")).await;
+            let exempt = test::call_service(&app, request("/stats", "10.0.0.1:4")).await;
+            let separate = test::call_service(&app, request("/other", "10.0.0.2:1")).await;
+
+            std::env::remove_var(RATE_LIMIT_REQUESTS_ENV);
+
+            assert_eq!(first.status(), StatusCode::OK);
+            assert_eq!(other.status(), StatusCode::TOO_MANY_REQUESTS);
+            assert_eq!(exempt.status(), StatusCode::OK);
+            assert_eq!(separate.status(), StatusCode::OK);
+
+            let body = test::read_body(game).await;
+            assert!(String::from_utf8_lossy(&body).contains(r#"result="33""#));
+        }
+
+        /// Build a creation limiter, without reading the environment.
+        fn creation_limiter(limit: u32) -> CreationLimiter {
+            CreationLimiter { limit, window: Duration::hours(1), windows: Mutex::default() }
+        }
+
+        /// Players can create clans until they reach the limit, independently of each other.
+        #[test]
+        fn creation_limit_reached() {
+            let limiter = creation_limiter(2);
+
+            assert!(limiter.allows("a"));
+            limiter.record("a");
+            assert!(limiter.allows("a"));
+            limiter.record("a");
+            assert!(!limiter.allows("a"));
+            assert!(limiter.allows("b"));
+        }
+
+        /// Players can create clans again once their window is over.
+        #[test]
+        fn creation_window_expires() {
+            let limiter = creation_limiter(1);
+            limiter.record("a");
+            assert!(!limiter.allows("a"));
+
+            limiter.windows.lock().unwrap().insert("a".to_string(), (Utc::now() - Duration::hours(2), 1));
+            assert!(limiter.allows("a"));
+
+            limiter.record("a");
+            assert_eq!(limiter.windows.lock().unwrap().get("a").map(|(_, count)| *count), Some(1));
+            assert!(!limiter.allows("a"));
+        }
+
+        /// A limit of ``0`` lets players create clans as often as they like.
+        #[test]
+        fn creation_limit_disabled() {
+            let limiter = creation_limiter(0);
+
+            for _ in 0..10 {
+                assert!(limiter.allows("a"));
+                limiter.record("a");
+            }
+        }
+    }
+}
+
+pub mod startup {
+    //! Checks run once at startup, so that a misconfigured server
+    //! refuses to start, instead of failing on its first request.
+
+    use std::time::Duration;
+
+    use mongodb::{bson::doc, options::ClientOptions};
+
+    use crate::{
+        database::Database,
+        structs::{entities::permission, ticket::Ticket},
+    };
+
+    /// How long to wait for the database to answer, before giving up.
+    const DATABASE_TIMEOUT: Duration = Duration::from_secs(5);
+
+    /// Names of the public keys needed to verify tickets.
+    const KEY_NAMES: [&str; 2] = ["psn", "rpcn"];
+
+    /// Validate the server's configuration.
+    ///
+    /// Every problem found is returned, so that they can all be fixed at once.
+    pub async fn validate() -> Result<(), Vec<String>> {
+        let mut problems = config();
+
+        if let Err(e) = ping(&Database::uri()).await {
+            problems.push(format!("Failed to reach the database at MONGO_URI: {e}"));
+        }
+
+        if problems.is_empty() { Ok(()) } else { Err(problems) }
+    }
+
+    /// Validate the configuration that doesn't need a connection.
+    fn config() -> Vec<String> {
+        let mut problems = Vec::new();
+
+        // Check if every public key can be loaded
+        for name in KEY_NAMES {
+            if let Err(e) = Ticket::public_keys(name) {
+                problems.push(format!("{e} `{name}`: expected `keys/{name}.pem` or `keys/{name}/*.pem`"));
+            }
+        }
+
+        // Check if the Admin endpoints can be authenticated
+        #[cfg(feature = "admin")]
+        if !std::env::var(super::auth::ADMIN_TOKEN_ENV).is_ok_and(|token| !token.is_empty()) {
+            problems.push(format!("Missing {} environment variable", super::auth::ADMIN_TOKEN_ENV));
+        }
+
+        // Check if every permission override can be applied
+        problems.extend(permission::invalid_overrides());
+
+        problems
+    }
+
+    /// Check if the database at the given URI answers.
+    async fn ping(uri: &str) -> mongodb::error::Result<()> {
+        let mut options = ClientOptions::parse(uri).await?;
+        options.server_selection_timeout = Some(DATABASE_TIMEOUT);
+
+        let client = mongodb::Client::with_options(options)?;
+        client.database("admin").run_command(doc! { "ping": 1 }).await?;
+
+        Ok(())
+    }
+
+    #[cfg(test)]
+    mod tests {
+        //! Tests for the checks that don't need the database.
+
+        use super::config;
+
+        /// The keys shipped with the server load, so they're never reported.
+        #[test]
+        fn keys() {
+            let problems = config();
+            assert!(!problems.iter().any(|problem| problem.contains("keys/")), "{problems:?}");
+        }
+
+        /// The Admin API can't start without a token to authenticate it with.
+        ///
+        /// The token is read from the environment, so every case runs in this single test.
+        #[cfg(feature = "admin")]
+        #[test]
+        fn admin_token() {
+            use crate::utils::auth::ADMIN_TOKEN_ENV;
+
+            let missing = |problems: Vec<String>| problems.iter().any(|problem| problem.contains(ADMIN_TOKEN_ENV));
+
+            std::env::remove_var(ADMIN_TOKEN_ENV);
+            let unset = missing(config());
+            std::env::set_var(ADMIN_TOKEN_ENV, "");
+            let empty = missing(config());
+            std::env::set_var(ADMIN_TOKEN_ENV, "token");
+            let set = missing(config());
+            std::env::remove_var(ADMIN_TOKEN_ENV);
+
+            assert!(unset);
+            assert!(empty);
+            assert!(!set);
+        }
+    }
+}
+
 #[cfg(feature = "admin")]
 pub mod auth {
     //! Helpers for authenticating Admin requests.
@@ -32,7 +1388,7 @@ pub mod auth {
     const ADMIN_TOKEN_HEADER: &str = "X-Admin-Token";
 
     /// Environment variable name for the Admin token.
-    const ADMIN_TOKEN_ENV: &str = "ADMIN_TOKEN";
+    pub const ADMIN_TOKEN_ENV: &str = "ADMIN_TOKEN";
     
     /// Middleware that checks for the `X-Admin-Token` header.
     #[allow(clippy::future_not_send)]